@@ -0,0 +1,142 @@
+//! Core throughput: single instructions, sprite drawing, scaling and whole programs
+//! Run with `cargo bench`, or `cargo bench --features jit` to include the recompiler.
+
+use chip_8::cpu::display::{Display, ALL_ROWS, HEIGHT, WIDTH};
+use chip_8::cpu::instruction::Instruction;
+use chip_8::cpu::CPU;
+use chip_8::engine::Engine;
+use chip_8::render;
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+
+/// One opcode per family, with operands that succeed on the machine from `machine`
+const OPCODES: [u16; 35] = [
+    0x0123, 0x00E0, 0x00EE, 0x1300, 0x2300, 0x3012, 0x4012, 0x5010, 0x6012, 0x7012, 0x8010, 0x8011,
+    0x8012, 0x8013, 0x8014, 0x8015, 0x8016, 0x8017, 0x801E, 0x9010, 0xA300, 0xB300, 0xC0FF, 0xD01F,
+    0xE09E, 0xE0A1, 0xF007, 0xF00A, 0xF015, 0xF018, 0xF01E, 0xF029, 0xF033, 0xFF55, 0xFF65,
+];
+
+/// A machine one call deep, with I pointing at free memory and key 0 down
+fn machine() -> CPU {
+    let mut cpu = CPU::new();
+    cpu.reset();
+    cpu.execute(0x2300).unwrap();
+    cpu.execute(0xA300).unwrap();
+    for x in 0..16 {
+        cpu.set_v(x, 0x11 * x as u8);
+    }
+    cpu.keyboard.key_down(0);
+    cpu
+}
+
+fn execute(c: &mut Criterion) {
+    let mut group = c.benchmark_group("execute");
+    for &opcode in OPCODES.iter() {
+        let family = Instruction::decode(opcode).family();
+        group.bench_function(family, |b| {
+            b.iter_batched_ref(
+                machine,
+                |cpu| cpu.execute(opcode).unwrap(),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn draw_sprite(c: &mut Criterion) {
+    let memory = [0xAA; 16];
+    let mut group = c.benchmark_group("draw_sprite");
+    for &n in [1, 5, 15].iter() {
+        // Top left, and the bottom right corner where sprites wrap or get clipped
+        for &(name, x, y) in [("inside", 0, 0), ("edge", WIDTH - 4, HEIGHT - 2)].iter() {
+            let mut display = Display::new();
+            group.bench_function(format!("wrap/{}/{}", n, name), |b| {
+                b.iter(|| display.draw_sprite(&memory, n, 0, x, y))
+            });
+            group.bench_function(format!("clip/{}/{}", n, name), |b| {
+                b.iter(|| display.draw_sprite_clipped(&memory, n, 0, x, y))
+            });
+        }
+    }
+    group.finish();
+}
+
+fn scale(c: &mut Criterion) {
+    let mut display = Display::new();
+    for i in 0..WIDTH * HEIGHT {
+        display.set_pixel(i % WIDTH, i / WIDTH, (i + i / WIDTH) % 2 == 1);
+    }
+    let mut group = c.benchmark_group("scale");
+    for &scale in [1, 15].iter() {
+        let stride = WIDTH * scale;
+        let mut buffer = vec![0; stride * HEIGHT * scale];
+        group.throughput(Throughput::Elements(1));
+        group.bench_function(format!("frame/{}x", scale), |b| {
+            b.iter(|| {
+                render::scale(
+                    &display,
+                    ALL_ROWS,
+                    &mut buffer,
+                    stride,
+                    scale,
+                    0x00FFFF,
+                    0x333333,
+                )
+            })
+        });
+    }
+    group.finish();
+}
+
+/// Register arithmetic in a loop, forever
+#[rustfmt::skip]
+const ALU: [u8; 22] = [
+    0x60, 0xFF, // 200: LD V0, 0xFF
+    0x61, 0x01, // 202: LD V1, 0x01
+    0x82, 0x04, // 204: ADD V2, V0
+    0x83, 0x23, // 206: XOR V3, V2
+    0x84, 0x36, // 208: SHR V4, V3
+    0xA3, 0x00, // 20A: LD I, 0x300
+    0xF2, 0x1E, // 20C: ADD I, V2
+    0x80, 0x15, // 20E: SUB V0, V1
+    0x30, 0x00, // 210: SE V0, 0
+    0x12, 0x04, // 212: JP 0x204
+    0x12, 0x00, // 214: JP 0x200
+];
+
+/// Draws every font digit across the screen, forever
+#[rustfmt::skip]
+const DRAW: [u8; 22] = [
+    0x00, 0xE0, // 200: CLS
+    0x60, 0x00, // 202: LD V0, 0
+    0xF0, 0x29, // 204: LD F, V0
+    0x81, 0x00, // 206: LD V1, V0
+    0x81, 0x0E, // 208: SHL V1, V0
+    0x81, 0x0E, // 20A: SHL V1, V0
+    0xD1, 0x25, // 20C: DRW V1, V2, 5
+    0x70, 0x01, // 20E: ADD V0, 1
+    0x30, 0x10, // 210: SE V0, 16
+    0x12, 0x04, // 212: JP 0x204
+    0x12, 0x00, // 214: JP 0x200
+];
+
+fn programs(c: &mut Criterion) {
+    const CYCLES: u64 = 100_000;
+    let mut group = c.benchmark_group("program");
+    group.throughput(Throughput::Elements(CYCLES));
+    for &(program, rom) in [("alu", &ALU[..]), ("draw", &DRAW[..])].iter() {
+        for name in Engine::NAMES.iter() {
+            let mut cpu = CPU::new();
+            cpu.reset();
+            cpu.load_rom(rom).unwrap();
+            let mut engine = Engine::from_name(name).unwrap();
+            group.bench_function(format!("{}/{}", program, name), |b| {
+                b.iter(|| engine.run(&mut cpu, CYCLES).unwrap())
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, execute, draw_sprite, scale, programs);
+criterion_main!(benches);
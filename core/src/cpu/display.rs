@@ -0,0 +1,154 @@
+pub const WIDTH: usize = 64;
+pub const HEIGHT: usize = 32;
+
+/// Every bit of a dirty mask set, one per row
+pub const ALL_ROWS: u32 = !0;
+
+/// 64x32 monochrome screen, one u64 per row
+/// The most significant bit of a row is its leftmost pixel, so a sprite byte shifted right
+/// by x lines up with the row and drawing it is a single XOR.
+pub struct Display {
+    rows: [u64; HEIGHT],
+    /// Bit y is set when row y changed since the last `take_dirty`
+    dirty: u32,
+}
+
+pub fn byte_index(byte: u8, index: usize) -> u8 {
+    (byte & (1 << index)) >> index
+}
+
+impl Default for Display {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Display {
+    /// Returns a new, cleared display instance
+    pub fn new() -> Display {
+        Display {
+            rows: [0; HEIGHT],
+            dirty: ALL_ROWS,
+        }
+    }
+
+    /// Pixel rows, most significant bit on the left
+    pub fn rows(&self) -> &[u64; HEIGHT] {
+        &self.rows
+    }
+
+    /// Pixels, row by row, 1 for lit and 0 for unlit
+    /// Unpacks the rows, prefer `rows` or `pixel` where speed matters.
+    pub fn screen(&self) -> [u8; WIDTH * HEIGHT] {
+        let mut screen = [0; WIDTH * HEIGHT];
+        for (index, pixel) in screen.iter_mut().enumerate() {
+            *pixel = self.pixel(index % WIDTH, index / WIDTH) as u8;
+        }
+        screen
+    }
+
+    /// Whether the pixel at (x, y) is lit, wrapping coordinates past the edges
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self.rows[y % HEIGHT] & mask(x % WIDTH) != 0
+    }
+
+    /// Lights or clears the pixel at (x, y), wrapping coordinates past the edges
+    pub fn set_pixel(&mut self, x: usize, y: usize, lit: bool) {
+        let (row, mask) = (y % HEIGHT, mask(x % WIDTH));
+        if lit {
+            self.rows[row] |= mask;
+        } else {
+            self.rows[row] &= !mask;
+        }
+        self.dirty |= 1 << row;
+    }
+
+    /// Rows changed since the last call, as a mask with bit y set for row y
+    /// Frontends only need to redraw these rows.
+    pub fn take_dirty(&mut self) -> u32 {
+        core::mem::replace(&mut self.dirty, 0)
+    }
+
+    /// Clears the display
+    pub fn cls(&mut self) {
+        self.rows = [0; HEIGHT];
+        self.dirty = ALL_ROWS;
+    }
+
+    /// The interpreter reads n bytes from memory, starting at the address stored in i.
+    /// These bytes are then displayed as sprites on screen at coordinates (Vx, Vy).
+    /// Sprites are XORed onto the existing screen. If this causes any pixels to be erased,
+    /// VF is set to 1, otherwise it is set to 0. If the sprite is positioned so part of it
+    /// is outside the coordinates of the display, it wraps around to the opposite side of
+    /// the screen.
+    /// Returns true if a collision was detected, false otherwise
+    pub fn draw_sprite(&mut self, memory: &[u8], n: usize, i: usize, vx: usize, vy: usize) -> bool {
+        let x = (vx % WIDTH) as u32;
+        let mut res = false;
+        for (r, &byte) in memory[i..i + n].iter().enumerate() {
+            let row = (vy + r) % HEIGHT;
+            // Rotating rather than shifting wraps the sprite around the right edge
+            let sprite = ((byte as u64) << (WIDTH - 8)).rotate_right(x);
+            res |= self.xor_row(row, sprite);
+        }
+        res
+    }
+
+    /// Like `draw_sprite`, but the parts of the sprite past the right and bottom edges are
+    /// cut off instead of wrapping. The starting coordinates still wrap.
+    pub fn draw_sprite_clipped(
+        &mut self,
+        memory: &[u8],
+        n: usize,
+        i: usize,
+        vx: usize,
+        vy: usize,
+    ) -> bool {
+        let (x, y) = (vx % WIDTH, vy % HEIGHT);
+        let mut res = false;
+        for (r, &byte) in memory[i..i + n].iter().enumerate().take(HEIGHT - y) {
+            let sprite = ((byte as u64) << (WIDTH - 8)) >> x;
+            res |= self.xor_row(y + r, sprite);
+        }
+        res
+    }
+
+    /// XORs sprite onto a row, returning true if it erased any pixel
+    fn xor_row(&mut self, row: usize, sprite: u64) -> bool {
+        if sprite != 0 {
+            self.dirty |= 1 << row;
+        }
+        let erased = self.rows[row] & sprite != 0;
+        self.rows[row] ^= sprite;
+        erased
+    }
+}
+
+/// Bit of column x within a row
+fn mask(x: usize) -> u64 {
+    1 << (WIDTH - 1 - x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sprites_wrap_and_mark_rows_dirty() {
+        let mut display = Display::new();
+        display.take_dirty();
+        // Two rows of a sprite drawn over the bottom right corner
+        display.draw_sprite(&[0xC3, 0x81], 2, 0, WIDTH - 4, HEIGHT - 1);
+        assert_eq!(display.take_dirty(), 1 << (HEIGHT - 1) | 1);
+        assert_eq!(display.take_dirty(), 0);
+
+        let lit = [(3, 0), (60, 0), (2, 31), (3, 31), (60, 31), (61, 31)];
+        for &(x, y) in lit.iter() {
+            assert!(display.pixel(x, y));
+        }
+        assert_eq!(
+            display.screen().iter().filter(|&&pixel| pixel == 1).count(),
+            lit.len()
+        );
+    }
+}
@@ -0,0 +1,37 @@
+use core::fmt;
+
+/// Why the CPU couldn't carry on
+/// When an instruction fails, the machine is left as it was before the instruction ran.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// CALL with all 16 stack levels in use
+    StackOverflow,
+    /// RET with an empty stack
+    StackUnderflow,
+    /// An instruction fetch or memory access starting at this address runs past 0xFFF
+    AddressOutOfRange(u16),
+    /// Ex9E or ExA1 with a Vx that isn't a key
+    InvalidKey(u8),
+    /// The ROM is this many bytes, more than fit in memory after 0x200
+    RomTooLarge(usize),
+    /// Save state data that is truncated, from another version or inconsistent
+    InvalidState,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::StackOverflow => write!(f, "stack overflow"),
+            Error::StackUnderflow => write!(f, "return with an empty stack"),
+            Error::AddressOutOfRange(address) => {
+                write!(f, "memory access out of range at {:#05x}", address)
+            }
+            Error::InvalidKey(key) => write!(f, "no key {:#04x}", key),
+            Error::RomTooLarge(size) => write!(f, "ROM is too large ({} bytes)", size),
+            Error::InvalidState => write!(f, "invalid save state"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
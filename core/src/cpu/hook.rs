@@ -0,0 +1,75 @@
+use super::{Error, CPU};
+
+/// Callbacks invoked by `CPU::execute_cycle_with` around every instruction
+/// All methods default to doing nothing, so implementors only override what they need.
+/// `CPU::execute_cycle` runs with `NoHook`, which compiles down to no extra work.
+pub trait Hook {
+    /// Called after the opcode has been fetched, before it is executed
+    fn before_instruction(&mut self, _cpu: &CPU, _opcode: u16) {}
+
+    /// Called once the opcode has been executed
+    fn after_instruction(&mut self, _cpu: &CPU, _opcode: u16) {}
+
+    /// Called instead of `after_instruction` when the instruction fails, with the machine as it
+    /// was before it ran
+    /// opcode is None when the instruction couldn't be fetched.
+    fn on_error(&mut self, _cpu: &CPU, _opcode: Option<u16>, _error: &Error) {}
+}
+
+/// A hook that does nothing
+pub struct NoHook;
+
+impl Hook for NoHook {}
+
+impl<H: Hook + ?Sized> Hook for &mut H {
+    fn before_instruction(&mut self, cpu: &CPU, opcode: u16) {
+        (**self).before_instruction(cpu, opcode);
+    }
+
+    fn after_instruction(&mut self, cpu: &CPU, opcode: u16) {
+        (**self).after_instruction(cpu, opcode);
+    }
+
+    fn on_error(&mut self, cpu: &CPU, opcode: Option<u16>, error: &Error) {
+        (**self).on_error(cpu, opcode, error);
+    }
+}
+
+/// Runs the hook if there is one, e.g. for a debugging tool that is only sometimes enabled
+impl<H: Hook> Hook for Option<H> {
+    fn before_instruction(&mut self, cpu: &CPU, opcode: u16) {
+        if let Some(hook) = self {
+            hook.before_instruction(cpu, opcode);
+        }
+    }
+
+    fn after_instruction(&mut self, cpu: &CPU, opcode: u16) {
+        if let Some(hook) = self {
+            hook.after_instruction(cpu, opcode);
+        }
+    }
+
+    fn on_error(&mut self, cpu: &CPU, opcode: Option<u16>, error: &Error) {
+        if let Some(hook) = self {
+            hook.on_error(cpu, opcode, error);
+        }
+    }
+}
+
+/// Runs both hooks, first then second
+impl<A: Hook, B: Hook> Hook for (A, B) {
+    fn before_instruction(&mut self, cpu: &CPU, opcode: u16) {
+        self.0.before_instruction(cpu, opcode);
+        self.1.before_instruction(cpu, opcode);
+    }
+
+    fn after_instruction(&mut self, cpu: &CPU, opcode: u16) {
+        self.0.after_instruction(cpu, opcode);
+        self.1.after_instruction(cpu, opcode);
+    }
+
+    fn on_error(&mut self, cpu: &CPU, opcode: Option<u16>, error: &Error) {
+        self.0.on_error(cpu, opcode, error);
+        self.1.on_error(cpu, opcode, error);
+    }
+}
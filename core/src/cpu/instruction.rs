@@ -0,0 +1,227 @@
+// Mnemonics follow Cowgod's reference: http://devernay.free.fr/hacks/chip8/C8TECH10.HTM#3.1
+
+use core::fmt;
+
+/// A decoded CHIP-8 instruction
+/// x and y are register indices, kk is a byte, n is a nibble, and nnn is an address
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    /// 0nnn - SYS addr
+    Sys(u16),
+    /// 00E0 - CLS
+    Cls,
+    /// 00EE - RET
+    Ret,
+    /// 1nnn - JP addr
+    Jp(u16),
+    /// 2nnn - CALL addr
+    Call(u16),
+    /// 3xkk - SE Vx, byte
+    SeByte(usize, u8),
+    /// 4xkk - SNE Vx, byte
+    SneByte(usize, u8),
+    /// 5xy0 - SE Vx, Vy
+    SeReg(usize, usize),
+    /// 6xkk - LD Vx, byte
+    LdByte(usize, u8),
+    /// 7xkk - ADD Vx, byte
+    AddByte(usize, u8),
+    /// 8xy0 - LD Vx, Vy
+    LdReg(usize, usize),
+    /// 8xy1 - OR Vx, Vy
+    Or(usize, usize),
+    /// 8xy2 - AND Vx, Vy
+    And(usize, usize),
+    /// 8xy3 - XOR Vx, Vy
+    Xor(usize, usize),
+    /// 8xy4 - ADD Vx, Vy
+    AddReg(usize, usize),
+    /// 8xy5 - SUB Vx, Vy
+    Sub(usize, usize),
+    /// 8xy6 - SHR Vx {, Vy}
+    Shr(usize, usize),
+    /// 8xy7 - SUBN Vx, Vy
+    Subn(usize, usize),
+    /// 8xyE - SHL Vx {, Vy}
+    Shl(usize, usize),
+    /// 9xy0 - SNE Vx, Vy
+    SneReg(usize, usize),
+    /// Annn - LD I, addr
+    LdI(u16),
+    /// Bnnn - JP V0, addr
+    JpV0(u16),
+    /// Cxkk - RND Vx, byte
+    Rnd(usize, u8),
+    /// Dxyn - DRW Vx, Vy, nibble
+    Drw(usize, usize, u8),
+    /// Ex9E - SKP Vx
+    Skp(usize),
+    /// ExA1 - SKNP Vx
+    Sknp(usize),
+    /// Fx07 - LD Vx, DT
+    LdVxDt(usize),
+    /// Fx0A - LD Vx, K
+    LdVxK(usize),
+    /// Fx15 - LD DT, Vx
+    LdDtVx(usize),
+    /// Fx18 - LD ST, Vx
+    LdStVx(usize),
+    /// Fx1E - ADD I, Vx
+    AddI(usize),
+    /// Fx29 - LD F, Vx
+    LdF(usize),
+    /// Fx33 - LD B, Vx
+    LdB(usize),
+    /// Fx55 - LD [I], Vx
+    StoreRegs(usize),
+    /// Fx65 - LD Vx, [I]
+    LoadRegs(usize),
+    /// Any opcode that does not map to an instruction
+    Unknown(u16),
+}
+
+impl Instruction {
+    /// Splits the opcode into its fields and returns the matching instruction
+    pub fn decode(opcode: u16) -> Instruction {
+        let nnn = opcode & 0x0FFF;
+        let n = (opcode & 0x000F) as u8;
+        let x = ((opcode & 0x0F00) >> 8) as usize;
+        let y = ((opcode & 0x00F0) >> 4) as usize;
+        let kk = (opcode & 0x00FF) as u8;
+
+        match opcode {
+            0x00E0 => Instruction::Cls,
+            0x00EE => Instruction::Ret,
+            0x0000..=0x0FFF => Instruction::Sys(nnn),
+            0x1000..=0x1FFF => Instruction::Jp(nnn),
+            0x2000..=0x2FFF => Instruction::Call(nnn),
+            0x3000..=0x3FFF => Instruction::SeByte(x, kk),
+            0x4000..=0x4FFF => Instruction::SneByte(x, kk),
+            0x5000..=0x5FFF if n == 0 => Instruction::SeReg(x, y),
+            0x6000..=0x6FFF => Instruction::LdByte(x, kk),
+            0x7000..=0x7FFF => Instruction::AddByte(x, kk),
+            0x8000..=0x8FFF => match n {
+                0x0 => Instruction::LdReg(x, y),
+                0x1 => Instruction::Or(x, y),
+                0x2 => Instruction::And(x, y),
+                0x3 => Instruction::Xor(x, y),
+                0x4 => Instruction::AddReg(x, y),
+                0x5 => Instruction::Sub(x, y),
+                0x6 => Instruction::Shr(x, y),
+                0x7 => Instruction::Subn(x, y),
+                0xE => Instruction::Shl(x, y),
+                _ => Instruction::Unknown(opcode),
+            },
+            0x9000..=0x9FFF if n == 0 => Instruction::SneReg(x, y),
+            0xA000..=0xAFFF => Instruction::LdI(nnn),
+            0xB000..=0xBFFF => Instruction::JpV0(nnn),
+            0xC000..=0xCFFF => Instruction::Rnd(x, kk),
+            0xD000..=0xDFFF => Instruction::Drw(x, y, n),
+            0xE000..=0xEFFF => match kk {
+                0x9E => Instruction::Skp(x),
+                0xA1 => Instruction::Sknp(x),
+                _ => Instruction::Unknown(opcode),
+            },
+            0xF000..=0xFFFF => match kk {
+                0x07 => Instruction::LdVxDt(x),
+                0x0A => Instruction::LdVxK(x),
+                0x15 => Instruction::LdDtVx(x),
+                0x18 => Instruction::LdStVx(x),
+                0x1E => Instruction::AddI(x),
+                0x29 => Instruction::LdF(x),
+                0x33 => Instruction::LdB(x),
+                0x55 => Instruction::StoreRegs(x),
+                0x65 => Instruction::LoadRegs(x),
+                _ => Instruction::Unknown(opcode),
+            },
+            _ => Instruction::Unknown(opcode),
+        }
+    }
+
+    /// The opcode pattern this instruction was decoded from, e.g. "8xy4"
+    /// Patterns double as opcode families: every "8xy_" pattern starts with "8"
+    pub fn family(&self) -> &'static str {
+        match self {
+            Instruction::Sys(_) => "0nnn",
+            Instruction::Cls => "00E0",
+            Instruction::Ret => "00EE",
+            Instruction::Jp(_) => "1nnn",
+            Instruction::Call(_) => "2nnn",
+            Instruction::SeByte(..) => "3xkk",
+            Instruction::SneByte(..) => "4xkk",
+            Instruction::SeReg(..) => "5xy0",
+            Instruction::LdByte(..) => "6xkk",
+            Instruction::AddByte(..) => "7xkk",
+            Instruction::LdReg(..) => "8xy0",
+            Instruction::Or(..) => "8xy1",
+            Instruction::And(..) => "8xy2",
+            Instruction::Xor(..) => "8xy3",
+            Instruction::AddReg(..) => "8xy4",
+            Instruction::Sub(..) => "8xy5",
+            Instruction::Shr(..) => "8xy6",
+            Instruction::Subn(..) => "8xy7",
+            Instruction::Shl(..) => "8xyE",
+            Instruction::SneReg(..) => "9xy0",
+            Instruction::LdI(_) => "Annn",
+            Instruction::JpV0(_) => "Bnnn",
+            Instruction::Rnd(..) => "Cxkk",
+            Instruction::Drw(..) => "Dxyn",
+            Instruction::Skp(_) => "Ex9E",
+            Instruction::Sknp(_) => "ExA1",
+            Instruction::LdVxDt(_) => "Fx07",
+            Instruction::LdVxK(_) => "Fx0A",
+            Instruction::LdDtVx(_) => "Fx15",
+            Instruction::LdStVx(_) => "Fx18",
+            Instruction::AddI(_) => "Fx1E",
+            Instruction::LdF(_) => "Fx29",
+            Instruction::LdB(_) => "Fx33",
+            Instruction::StoreRegs(_) => "Fx55",
+            Instruction::LoadRegs(_) => "Fx65",
+            Instruction::Unknown(_) => "????",
+        }
+    }
+}
+
+/// Formats the instruction as an assembler mnemonic, e.g. "ADD V1, V2"
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Instruction::Sys(nnn) => write!(f, "SYS 0x{:03X}", nnn),
+            Instruction::Cls => write!(f, "CLS"),
+            Instruction::Ret => write!(f, "RET"),
+            Instruction::Jp(nnn) => write!(f, "JP 0x{:03X}", nnn),
+            Instruction::Call(nnn) => write!(f, "CALL 0x{:03X}", nnn),
+            Instruction::SeByte(x, kk) => write!(f, "SE V{:X}, 0x{:02X}", x, kk),
+            Instruction::SneByte(x, kk) => write!(f, "SNE V{:X}, 0x{:02X}", x, kk),
+            Instruction::SeReg(x, y) => write!(f, "SE V{:X}, V{:X}", x, y),
+            Instruction::LdByte(x, kk) => write!(f, "LD V{:X}, 0x{:02X}", x, kk),
+            Instruction::AddByte(x, kk) => write!(f, "ADD V{:X}, 0x{:02X}", x, kk),
+            Instruction::LdReg(x, y) => write!(f, "LD V{:X}, V{:X}", x, y),
+            Instruction::Or(x, y) => write!(f, "OR V{:X}, V{:X}", x, y),
+            Instruction::And(x, y) => write!(f, "AND V{:X}, V{:X}", x, y),
+            Instruction::Xor(x, y) => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Instruction::AddReg(x, y) => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Instruction::Sub(x, y) => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Instruction::Shr(x, y) => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Instruction::Subn(x, y) => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Instruction::Shl(x, y) => write!(f, "SHL V{:X}, V{:X}", x, y),
+            Instruction::SneReg(x, y) => write!(f, "SNE V{:X}, V{:X}", x, y),
+            Instruction::LdI(nnn) => write!(f, "LD I, 0x{:03X}", nnn),
+            Instruction::JpV0(nnn) => write!(f, "JP V0, 0x{:03X}", nnn),
+            Instruction::Rnd(x, kk) => write!(f, "RND V{:X}, 0x{:02X}", x, kk),
+            Instruction::Drw(x, y, n) => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Instruction::Skp(x) => write!(f, "SKP V{:X}", x),
+            Instruction::Sknp(x) => write!(f, "SKNP V{:X}", x),
+            Instruction::LdVxDt(x) => write!(f, "LD V{:X}, DT", x),
+            Instruction::LdVxK(x) => write!(f, "LD V{:X}, K", x),
+            Instruction::LdDtVx(x) => write!(f, "LD DT, V{:X}", x),
+            Instruction::LdStVx(x) => write!(f, "LD ST, V{:X}", x),
+            Instruction::AddI(x) => write!(f, "ADD I, V{:X}", x),
+            Instruction::LdF(x) => write!(f, "LD F, V{:X}", x),
+            Instruction::LdB(x) => write!(f, "LD B, V{:X}", x),
+            Instruction::StoreRegs(x) => write!(f, "LD [I], V{:X}", x),
+            Instruction::LoadRegs(x) => write!(f, "LD V{:X}, [I]", x),
+            Instruction::Unknown(opcode) => write!(f, "DW 0x{:04X}", opcode),
+        }
+    }
+}
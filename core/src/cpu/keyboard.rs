@@ -2,6 +2,12 @@ pub struct Keyboard {
     keys: [bool; 16],
 }
 
+impl Default for Keyboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Keyboard {
     pub fn new() -> Keyboard {
         Keyboard { keys: [false; 16] }
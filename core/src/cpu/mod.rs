@@ -0,0 +1,691 @@
+// Documentation: http://devernay.free.fr/hacks/chip8/C8TECH10.HTM#2.5
+
+pub mod display;
+mod error;
+pub mod hook;
+pub mod instruction;
+pub mod keyboard;
+pub mod quirks;
+mod state;
+
+pub use error::Error;
+pub use state::STATE_SIZE;
+
+use hook::{Hook, NoHook};
+use instruction::Instruction;
+use quirks::Quirks;
+
+/// Seed used until `CPU::seed` is called, any non-zero value works
+const DEFAULT_SEED: u64 = 0x853C_49E6_748F_EA9B;
+
+const FONT_SET: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, //  0
+    0x20, 0x60, 0x20, 0x20, 0x70, //  1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, //  2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, //  3
+    0x90, 0x90, 0xF0, 0x10, 0x10, //  4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, //  5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, //  6
+    0xF0, 0x10, 0x20, 0x40, 0x40, //  7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, //  8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, //  9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, //  A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, //  B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, //  C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, //  D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, //  E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, //  F
+];
+
+#[allow(non_snake_case)]
+pub struct CPU {
+    /// CHIP-8 CPU
+
+    /// 4K of RAM
+    // Most programs start at 512 (0x200)
+    memory: [u8; 4096],
+
+    /// 16 general purpose 8-bit registers, named V0 - VF
+    /// The VF register should not be touched as it is used a flag by some instructions
+    V: [u8; 16],
+
+    /// One 16-bit register to store memory addresses (the index register)
+    /// Usually, only the lower 12 bits are used
+    I: u16,
+
+    /// Two special purpose 16-bit registers for the delay and sound timers
+    /// While non-zero, decremented at a rate of 60hz
+
+    /// The delay timer is active whenever the delay timer register (DT) is non-zero
+    /// This timer does nothing more than subtract 1 from the value of DT at a rate of 60Hz
+    /// When DT reaches 0, it deactivates
+    DT: u16,
+    /// The sound timer is active whenever the sound timer register (ST) is non-zero
+    /// his timer also decrements at a rate of 60Hz, however, as long as ST's value is greater than zero, the Chip-8 buzzer will sound
+    /// When ST reaches zero, the sound timer deactivates
+    ST: u16,
+
+    /// Program counter (PC) should be 16-bit
+    PC: u16,
+
+    /// Stack pointer can be 8-bit, used to indicate the topmost level of the stack
+    SP: u8,
+
+    /// Stack is an array of 16 16-bit values
+    /// CHIP-8 allows for up to 16 levels of nested subroutines
+    stack: [u16; 16],
+
+    /// 16-key hexadecimal keyboard
+    /// Not sure if this is the best way to implement this
+    pub keyboard: keyboard::Keyboard,
+
+    /// 64x32-pixel monochrome display
+    pub display: display::Display,
+
+    /// Number of instructions executed since the last reset
+    cycles: u64,
+
+    /// Number of 60Hz frames (timer ticks) since the last reset
+    frames: u64,
+
+    /// Interpreter behaviours to emulate, kept across resets
+    pub quirks: Quirks,
+
+    /// xorshift state behind Cxkk, see `seed`
+    rng: u64,
+}
+
+impl Default for CPU {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CPU {
+    /// New CPU instance
+    pub fn new() -> CPU {
+        CPU {
+            memory: [0; 4096],
+            V: [0; 16],
+            I: 0,
+            DT: 0,
+            ST: 0,
+            PC: 0,
+            SP: 0,
+            stack: [0; 16],
+            keyboard: keyboard::Keyboard::new(),
+            display: display::Display::new(),
+            cycles: 0,
+            frames: 0,
+            quirks: Quirks::default(),
+            rng: DEFAULT_SEED,
+        }
+    }
+
+    /// Resets all registers, clears the display, sets the PC to 0x200,
+    /// and loads the font set in memory
+    pub fn reset(&mut self) {
+        self.memory = [0; 4096];
+        self.V = [0; 16];
+        self.I = 0;
+        self.DT = 0;
+        self.ST = 0;
+        self.PC = 0x200;
+        self.SP = 0;
+        self.stack = [0; 16];
+        self.keyboard.reset();
+        self.display.cls();
+        self.cycles = 0;
+        self.frames = 0;
+        self.memory[0..80].copy_from_slice(&FONT_SET);
+    }
+
+    /// Copies the ROM into memory at 0x200
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), Error> {
+        let rom_size = rom.len();
+        if rom_size > self.memory.len() - 0x200 {
+            return Err(Error::RomTooLarge(rom_size));
+        }
+        self.memory[0x200..0x200 + rom_size].copy_from_slice(rom);
+        Ok(())
+    }
+
+    /// All instructions are two bytes long and are stored most-significant-byte first
+    /// In memory, the first byte of each instruction should be located at an even addresses
+    fn read_opcode(&self) -> Result<u16, Error> {
+        let pc = self.PC as usize;
+        if pc + 1 >= self.memory.len() {
+            return Err(Error::AddressOutOfRange(self.PC));
+        }
+        Ok(((self.memory[pc] as u16) << 8) | (self.memory[pc + 1] as u16))
+    }
+
+    /// Executes the current cycle
+    pub fn execute_cycle(&mut self) -> Result<(), Error> {
+        self.execute_cycle_with(&mut NoHook)
+    }
+
+    /// Executes the current cycle, calling the hook before and after the instruction runs
+    /// If it fails, the hook's `on_error` is called instead of `after_instruction`.
+    pub fn execute_cycle_with<H: Hook>(&mut self, hook: &mut H) -> Result<(), Error> {
+        let opcode = match self.read_opcode() {
+            Ok(opcode) => opcode,
+            Err(error) => {
+                hook.on_error(self, None, &error);
+                return Err(error);
+            }
+        };
+        hook.before_instruction(self, opcode);
+        if let Err(error) = self.execute(opcode) {
+            hook.on_error(self, Some(opcode), &error);
+            return Err(error);
+        }
+        self.cycles += 1;
+        hook.after_instruction(self, opcode);
+        Ok(())
+    }
+
+    /// Decreases all currently active timers by 1
+    /// Called once per 60Hz frame, so this also advances the frame counter
+    pub fn decrement_timers(&mut self) {
+        self.frames += 1;
+        self.DT = if self.DT > 0 { self.DT - 1 } else { self.DT };
+        self.ST = if self.ST > 0 { self.ST - 1 } else { self.ST };
+    }
+
+    /// 4K of RAM
+    pub fn memory(&self) -> &[u8; 4096] {
+        &self.memory
+    }
+
+    /// Writes value to memory at address, e.g. from a debugger
+    /// Only the lower 12 bits of the address are used
+    pub fn poke(&mut self, address: u16, value: u8) {
+        self.memory[(address & 0xFFF) as usize] = value;
+    }
+
+    /// General purpose registers V0 - VF
+    pub fn v(&self) -> &[u8; 16] {
+        &self.V
+    }
+
+    /// Index register
+    pub fn i(&self) -> u16 {
+        self.I
+    }
+
+    /// Delay timer
+    pub fn dt(&self) -> u16 {
+        self.DT
+    }
+
+    /// Sound timer
+    pub fn st(&self) -> u16 {
+        self.ST
+    }
+
+    /// Program counter
+    pub fn pc(&self) -> u16 {
+        self.PC
+    }
+
+    /// Stack pointer
+    pub fn sp(&self) -> u8 {
+        self.SP
+    }
+
+    /// Return addresses of the subroutine stack, only the first SP entries are in use
+    pub fn stack(&self) -> &[u16; 16] {
+        &self.stack
+    }
+
+    /// Sets register Vx
+    pub fn set_v(&mut self, x: usize, value: u8) {
+        self.V[x] = value;
+    }
+
+    /// Sets the index register
+    pub fn set_i(&mut self, value: u16) {
+        self.I = value;
+    }
+
+    /// Sets the delay timer
+    pub fn set_dt(&mut self, value: u16) {
+        self.DT = value;
+    }
+
+    /// Sets the sound timer
+    pub fn set_st(&mut self, value: u16) {
+        self.ST = value;
+    }
+
+    /// Sets the program counter
+    pub fn set_pc(&mut self, value: u16) {
+        self.PC = value;
+    }
+
+    /// Sets the stack pointer
+    /// Fails with `Error::StackOverflow`, leaving it as it was, if it is past the 16 levels.
+    pub fn set_sp(&mut self, value: u8) -> Result<(), Error> {
+        if value as usize > self.stack.len() {
+            return Err(Error::StackOverflow);
+        }
+        self.SP = value;
+        Ok(())
+    }
+
+    /// Number of instructions executed since the last reset
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Counts instructions executed without going through the CPU, e.g. by a recompiler
+    pub fn add_cycles(&mut self, cycles: u64) {
+        self.cycles += cycles;
+    }
+
+    /// Seeds the random numbers behind Cxkk, kept across resets
+    /// The core has no entropy source of its own, so frontends should seed it, e.g. from the
+    /// OS or a hardware RNG. The same seed always gives the same numbers.
+    pub fn seed(&mut self, seed: u64) {
+        // xorshift never leaves zero
+        self.rng = if seed == 0 { DEFAULT_SEED } else { seed };
+    }
+
+    /// Next random byte, xorshift64*
+    fn random(&mut self) -> u8 {
+        self.rng ^= self.rng >> 12;
+        self.rng ^= self.rng << 25;
+        self.rng ^= self.rng >> 27;
+        (self.rng.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 56) as u8
+    }
+
+    /// Number of 60Hz frames since the last reset
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Processes the given opcode
+    /// In these listings, the following variables are used:
+    ///
+    /// nnn or addr - A 12-bit value, the lowest 12 bits of the instruction     _nnn
+    /// n or nibble - A 4-bit value, the lowest 4 bits of the instruction       ___n
+    /// x - A 4-bit value, the lower 4 bits of the high byte of the instruction _x__
+    /// y - A 4-bit value, the upper 4 bits of the low byte of the instruction  __y_
+    /// kk or byte - An 8-bit value, the lowest 8 bits of the instruction       __kk
+    ///
+    /// The opcode runs as if it had just been fetched from PC, so PC is advanced past it first.
+    /// This doesn't count as a cycle, see `execute_cycle` to fetch and execute the next one.
+    /// If the instruction fails, nothing is changed.
+    pub fn execute(&mut self, opcode: u16) -> Result<(), Error> {
+        self.execute_instruction(Instruction::decode(opcode))
+    }
+
+    /// Executes one cycle with an instruction decoded ahead of time
+    /// The instruction must be the one at PC. Engines that cache decoded code use this to
+    /// skip fetching and decoding.
+    pub fn execute_cycle_decoded(&mut self, instruction: Instruction) -> Result<(), Error> {
+        self.execute_instruction(instruction)?;
+        self.cycles += 1;
+        Ok(())
+    }
+
+    fn execute_instruction(&mut self, instruction: Instruction) -> Result<(), Error> {
+        let pc = self.PC;
+        let result = self.process_instruction(instruction);
+        if result.is_err() {
+            self.PC = pc;
+        }
+        result
+    }
+
+    /// Executes instruction, checking for errors before changing anything but PC
+    fn process_instruction(&mut self, instruction: Instruction) -> Result<(), Error> {
+        // Increment program counter
+        // Remember! Opcodes are two bytes but memory is byte addressed
+        self.PC += 2;
+
+        match instruction {
+            // 0nnn - SYS addr
+            // Jump to a machine code routine at nnn.
+            // This instruction is only used on the old computers on which Chip-8 was originally implemented. It is ignored by modern interpreters.
+            Instruction::Sys(_) => (),
+
+            // 00E0 - CLS
+            // Clear the display.
+            Instruction::Cls => self.display.cls(),
+
+            // 00EE - RET
+            // Return from a subroutine.
+            // DO THIS BACKWARDS
+            // The interpreter sets the program counter to the address at the top of the stack, then subtracts 1 from the stack pointer.
+            Instruction::Ret => {
+                if self.SP == 0 {
+                    return Err(Error::StackUnderflow);
+                }
+                self.SP -= 1;
+                self.PC = self.stack[self.SP as usize];
+            }
+
+            //1nnn - JP addr
+            //Jump to location nnn.
+            //The interpreter sets the program counter to nnn.
+            Instruction::Jp(nnn) => self.PC = nnn,
+
+            // 2nnn - CALL addr
+            // Call subroutine at nnn.
+            // DO THIS BACKWARDS
+            // The interpreter increments the stack pointer, then puts the current PC on the top of the stack. The PC is then set to nnn.
+            Instruction::Call(nnn) => {
+                if self.SP as usize == self.stack.len() {
+                    return Err(Error::StackOverflow);
+                }
+                self.stack[self.SP as usize] = self.PC;
+                self.SP += 1;
+                self.PC = nnn;
+            }
+
+            // 3xkk - SE Vx, byte
+            // Skip next instruction if Vx = kk.
+            // The interpreter compares register Vx to kk, and if they are equal, increments the program counter by 2.
+            Instruction::SeByte(x, kk) => {
+                if self.V[x] == kk {
+                    self.PC += 2;
+                }
+            }
+
+            // 4xkk - SNE Vx, byte
+            // Skip next instruction if Vx != kk.
+            // The interpreter compares register Vx to kk, and if they are not equal, increments the program counter by 2.
+            Instruction::SneByte(x, kk) => {
+                if self.V[x] != kk {
+                    self.PC += 2;
+                }
+            }
+
+            // 5xy0 - SE Vx, Vy
+            // Skip next instruction if Vx = Vy.
+            // The interpreter compares register Vx to register Vy, and if they are equal, increments the program counter by 2.
+            Instruction::SeReg(x, y) => {
+                if self.V[x] == self.V[y] {
+                    self.PC += 2;
+                }
+            }
+
+            // 6xkk - LD Vx, byte
+            // Set Vx = kk.
+            // The interpreter puts the value kk into register Vx.
+            Instruction::LdByte(x, kk) => {
+                self.V[x] = kk;
+            }
+
+            // 7xkk - ADD Vx, byte
+            // Set Vx = Vx + kk.
+            // Adds the value kk to the value of register Vx, then stores the result in Vx.
+            Instruction::AddByte(x, kk) => {
+                self.V[x] = self.V[x].wrapping_add(kk);
+            }
+
+            // 8xy0 - LD Vx, Vy
+            // Set Vx = Vy.
+            // Stores the value of register Vy in register Vx.
+            Instruction::LdReg(x, y) => self.V[x] = self.V[y],
+
+            // 8xy1 - OR Vx, Vy
+            // Set Vx = Vx OR Vy.
+            // Performs a bitwise OR on the values of Vx and Vy, then stores the result in Vx.
+            Instruction::Or(x, y) => {
+                self.V[x] |= self.V[y];
+                if self.quirks.vf_reset {
+                    self.V[0xF] = 0;
+                }
+            }
+
+            // 8xy2 - AND Vx, Vy
+            // Set Vx = Vx AND Vy.
+            // Performs a bitwise AND on the values of Vx and Vy, then stores the result in Vx.
+            Instruction::And(x, y) => {
+                self.V[x] &= self.V[y];
+                if self.quirks.vf_reset {
+                    self.V[0xF] = 0;
+                }
+            }
+
+            // 8xy3 - XOR Vx, Vy
+            // Set Vx = Vx XOR Vy.
+            // Performs a bitwise exclusive OR on the values of Vx and Vy, then stores the result in Vx.
+            Instruction::Xor(x, y) => {
+                self.V[x] ^= self.V[y];
+                if self.quirks.vf_reset {
+                    self.V[0xF] = 0;
+                }
+            }
+
+            // 8xy4 - ADD Vx, Vy
+            // Set Vx = Vx + Vy, set VF = carry.
+            // The values of Vx and Vy are added together. If the result is greater than 8 bits (i.e., > 255,) VF is set to 1, otherwise 0. Only the lowest 8 bits of the result are kept, and stored in Vx.
+            // VF is written last, so it holds the flag even when x is F.
+            Instruction::AddReg(x, y) => {
+                let (result, carry) = self.V[x].overflowing_add(self.V[y]);
+                self.V[x] = result;
+                self.V[0xF] = if carry { 1 } else { 0 };
+            }
+
+            // 8xy5 - SUB Vx, Vy
+            // Set Vx = Vx - Vy, set VF = NOT borrow.
+            // If Vx > Vy, then VF is set to 1, otherwise 0. Then Vy is subtracted from Vx, and the results stored in Vx.
+            Instruction::Sub(x, y) => {
+                let (res, overflow) = self.V[x].overflowing_sub(self.V[y]);
+                self.V[x] = res;
+                self.V[0xF] = if !overflow { 1 } else { 0 };
+            }
+
+            // 8xy6 - SHR Vx {, Vy}
+            // Set Vx = Vx SHR 1.
+            // If the least-significant bit of Vx is 1, then VF is set to 1, otherwise 0. Then Vx is divided by 2.
+            Instruction::Shr(x, y) => {
+                let value = if self.quirks.shift_vy {
+                    self.V[y]
+                } else {
+                    self.V[x]
+                };
+                self.V[x] = value >> 1;
+                self.V[0xF] = value & 0b1;
+            }
+
+            // 8xy7 - SUBN Vx, Vy
+            // Set Vx = Vy - Vx, set VF = NOT borrow.
+            // If Vy > Vx, then VF is set to 1, otherwise 0. Then Vx is subtracted from Vy, and the results stored in Vx.
+            Instruction::Subn(x, y) => {
+                let (res, overflow) = self.V[y].overflowing_sub(self.V[x]);
+                self.V[x] = res;
+                self.V[0xF] = if !overflow { 1 } else { 0 };
+            }
+
+            // 8xyE - SHL Vx {, Vy}
+            // Set Vx = Vx SHL 1.
+            // If the most-significant bit of Vx is 1, then VF is set to 1, otherwise to 0. Then Vx is multiplied by 2.
+            Instruction::Shl(x, y) => {
+                let value = if self.quirks.shift_vy {
+                    self.V[y]
+                } else {
+                    self.V[x]
+                };
+                self.V[x] = value << 1;
+                self.V[0xF] = (value & 0b10000000) >> 7;
+            }
+
+            // 9xy0 - SNE Vx, Vy
+            // Skip next instruction if Vx != Vy.
+            // The values of Vx and Vy are compared, and if they are not equal, the program counter is increased by 2.
+            Instruction::SneReg(x, y) => {
+                if self.V[x] != self.V[y] {
+                    self.PC += 2;
+                }
+            }
+
+            // Annn - LD I, addr
+            // Set I = nnn.
+            // The value of register I is set to nnn.
+            Instruction::LdI(nnn) => self.I = nnn,
+
+            // Bnnn - JP V0, addr
+            // Jump to location nnn + V0.
+            // The program counter is set to nnn plus the value of V0.
+            Instruction::JpV0(nnn) => {
+                let x = if self.quirks.jump_vx {
+                    (nnn >> 8) as usize
+                } else {
+                    0
+                };
+                self.PC = self.V[x] as u16 + nnn;
+            }
+
+            // Cxkk - RND Vx, byte
+            // Set Vx = random byte AND kk.
+            // The interpreter generates a random number from 0 to 255, which is then ANDed with the value kk. The results are stored in Vx. See instruction 8xy2 for more information on AND.
+            Instruction::Rnd(x, kk) => {
+                let random_number = self.random();
+                self.V[x] = random_number & kk;
+            }
+
+            // Dxyn - DRW Vx, Vy, nibble
+            // The interpreter reads n bytes from memory, starting at the address stored in I.
+            // These bytes are then displayed as sprites on screen at coordinates (Vx, Vy).
+            // Sprites are XORed onto the existing screen. If this causes any pixels to be erased,
+            // VF is set to 1, otherwise it is set to 0. If the sprite is positioned so part of it
+            // is outside the coordinates of the display, it wraps around to the opposite side of
+            // the screen.
+            Instruction::Drw(x, y, n) => {
+                self.check_range(n as usize)?;
+                let collision = if self.quirks.clip_sprites {
+                    self.display.draw_sprite_clipped(
+                        &self.memory,
+                        n as usize,
+                        self.I as usize,
+                        self.V[x] as usize,
+                        self.V[y] as usize,
+                    )
+                } else {
+                    self.display.draw_sprite(
+                        &self.memory,
+                        n as usize,
+                        self.I as usize,
+                        self.V[x] as usize,
+                        self.V[y] as usize,
+                    )
+                };
+                self.V[0xF_usize] = collision as u8;
+            }
+
+            // Ex9E - SKP Vx
+            // Skip next instruction if key with the value of Vx is pressed.
+            // Checks the keyboard, and if the key corresponding to the value of Vx is currently in the down position, PC is increased by 2.
+            Instruction::Skp(x) => {
+                self.PC += if self.key_pressed(self.V[x])? { 2 } else { 0 };
+            }
+
+            // ExA1 - SKNP Vx
+            // Checks the keyboard, and if the key corresponding to the value of Vx is currently in the up position, PC is increased by 2.
+            // Skip next instruction if key with the value of Vx is not pressed.
+            Instruction::Sknp(x) => {
+                self.PC += if !self.key_pressed(self.V[x])? { 2 } else { 0 };
+            }
+
+            // Fx07 - LD Vx, DT
+            // Set Vx = delay timer value.
+            // The value of DT is placed into Vx.
+            Instruction::LdVxDt(x) => self.V[x] = self.DT as u8,
+
+            // Fx0A - LD Vx, K
+            // Wait for a key press, store the value of the key in Vx.
+            // All execution stops until a key is pressed, then the value of that key is stored in Vx.
+            // Execution "stops" by running this instruction again until a key is down.
+            Instruction::LdVxK(x) => match self.keyboard.keys().iter().position(|&key| key) {
+                Some(key) => self.V[x] = key as u8,
+                None => self.PC -= 2,
+            },
+
+            // Fx15 - LD DT, Vx
+            // Set delay timer = Vx.
+            // DT is set equal to the value of Vx.
+            Instruction::LdDtVx(x) => self.DT = self.V[x] as u16,
+
+            // Fx18 - LD ST, Vx
+            // Set sound timer = Vx.
+            // ST is set equal to the value of Vx.
+            Instruction::LdStVx(x) => self.ST = self.V[x] as u16,
+
+            // Fx1E - ADD I, Vx
+            // Set I = I + Vx.
+            // The values of I and Vx are added, and the results are stored in I.
+            Instruction::AddI(x) => self.I = self.I.wrapping_add(self.V[x] as u16),
+
+            // Fx29 - LD F, Vx
+            // Set I = location of sprite for digit Vx.
+            // The value of I is set to the location for the hexadecimal sprite
+            // corresponding to the value of Vx.
+            // See section 2.4, Display, for more information on the Chip-8 hexadecimal font.
+            // 5 since font set sprites ar 5 bytes in width
+            // Only the low nibble of Vx is a digit.
+            Instruction::LdF(x) => self.I = (self.V[x] & 0xF) as u16 * 5,
+
+            // Fx33 - LD B, Vx
+            // Store BCD representation of Vx in memory locations I, I+1, and I+2.
+            // The interpreter takes the decimal value of Vx, and places the hundreds digit in memory at location in I, the tens digit at location I+1, and the ones digit at location I+2.
+            Instruction::LdB(x) => {
+                self.check_range(3)?;
+                let vx = self.V[x];
+                let hundreds = (vx / 100) % 10;
+                let tens = (vx / 10) % 10;
+                let ones = vx % 10;
+                self.memory[self.I as usize] = hundreds;
+                self.memory[(self.I + 1) as usize] = tens;
+                self.memory[(self.I + 2) as usize] = ones;
+            }
+
+            // Fx55 - LD [I], Vx
+            // Store registers V0 through Vx in memory starting at location I.
+            // The interpreter copies the values of registers V0 through Vx into memory, starting at the address in I.
+            Instruction::StoreRegs(x) => {
+                self.check_range(x + 1)?;
+                self.memory[(self.I as usize)..(self.I as usize + x + 1)]
+                    .copy_from_slice(&self.V[0..(x + 1)]);
+                if self.quirks.load_store_increment_i {
+                    self.I += x as u16 + 1;
+                }
+            }
+
+            // Fx65 - LD Vx, [I]
+            // Read registers V0 through Vx from memory starting at location I.
+            // The interpreter reads values from memory starting at location I into registers V0 through Vx.
+            Instruction::LoadRegs(x) => {
+                self.check_range(x + 1)?;
+                self.V[0..(x + 1)]
+                    .copy_from_slice(&self.memory[(self.I as usize)..(self.I as usize + x + 1)]);
+                if self.quirks.load_store_increment_i {
+                    self.I += x as u16 + 1;
+                }
+            }
+
+            Instruction::Unknown(_) => (),
+        }
+        Ok(())
+    }
+
+    /// Whether the key in a register is down, failing if the value isn't a key
+    fn key_pressed(&self, key: u8) -> Result<bool, Error> {
+        if key as usize >= self.keyboard.keys().len() {
+            return Err(Error::InvalidKey(key));
+        }
+        Ok(self.keyboard.key_pressed(key as usize))
+    }
+
+    /// Checks that len bytes starting at I are all in memory
+    fn check_range(&self, len: usize) -> Result<(), Error> {
+        if self.I as usize + len > self.memory.len() {
+            return Err(Error::AddressOutOfRange(self.I));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,51 @@
+// Quirk reference: https://github.com/Timendus/chip8-test-suite#quirks-test
+
+/// Behaviours that differ between CHIP-8 interpreters
+/// ROMs written for one interpreter can break on another, so the CPU lets each be chosen.
+/// The default matches what this emulator has always done.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8xy6 and 8xyE shift Vy into Vx instead of shifting Vx in place
+    pub shift_vy: bool,
+    /// Fx55 and Fx65 leave I pointing past the last register stored or loaded
+    pub load_store_increment_i: bool,
+    /// 8xy1, 8xy2 and 8xy3 reset VF to 0
+    pub vf_reset: bool,
+    /// Bnnn jumps to nnn + Vx, where x is the high nibble of nnn, instead of nnn + V0
+    pub jump_vx: bool,
+    /// Sprites are cut off at the screen edges instead of wrapping around
+    pub clip_sprites: bool,
+}
+
+impl Quirks {
+    /// The original COSMAC VIP interpreter
+    pub const CHIP8: Quirks = Quirks {
+        shift_vy: true,
+        load_store_increment_i: true,
+        vf_reset: true,
+        jump_vx: false,
+        clip_sprites: true,
+    };
+
+    /// SUPER-CHIP 1.1, which most later games were written for
+    pub const SCHIP: Quirks = Quirks {
+        shift_vy: false,
+        load_store_increment_i: false,
+        vf_reset: false,
+        jump_vx: true,
+        clip_sprites: true,
+    };
+
+    /// Profile names accepted by `Quirks::profile`
+    pub const PROFILES: [&'static str; 3] = ["default", "chip8", "schip"];
+
+    /// Looks up a profile by name
+    pub fn profile(name: &str) -> Option<Quirks> {
+        match name {
+            "default" => Some(Quirks::default()),
+            "chip8" => Some(Quirks::CHIP8),
+            "schip" => Some(Quirks::SCHIP),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use super::display::{HEIGHT, WIDTH};
+use super::quirks::Quirks;
+use super::{Error, CPU};
+
+const MAGIC: [u8; 4] = *b"C8SS";
+const VERSION: u8 = 2;
+
+/// Size of a save state in bytes, see `CPU::save_state`
+pub const STATE_SIZE: usize = 4 + 1 // magic, version
+    + 4096 // memory
+    + 16 // V0 - VF
+    + 2 * 4 + 1 // I, DT, ST, PC, SP
+    + 2 * 16 // stack
+    + 2 // keys, one bit each
+    + WIDTH * HEIGHT / 8 // screen, one bit per pixel
+    + 8 * 3 // cycles, frames, random number generator
+    + 1; // quirks, one bit each
+
+/// Quirk flags in the order their bits are stored
+fn quirk_flags(quirks: &mut Quirks) -> [&mut bool; 5] {
+    [
+        &mut quirks.shift_vy,
+        &mut quirks.load_store_increment_i,
+        &mut quirks.vf_reset,
+        &mut quirks.jump_vx,
+        &mut quirks.clip_sprites,
+    ]
+}
+
+/// Writes fields one after the other into a state buffer
+struct Writer<'a> {
+    data: &'a mut [u8],
+    at: usize,
+}
+
+impl Writer<'_> {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.data[self.at..self.at + bytes.len()].copy_from_slice(bytes);
+        self.at += bytes.len();
+    }
+}
+
+/// Reads fields one after the other from a state buffer of the right size
+struct Reader<'a> {
+    data: &'a [u8],
+    at: usize,
+}
+
+impl Reader<'_> {
+    fn bytes(&mut self, len: usize) -> &[u8] {
+        self.at += len;
+        &self.data[self.at - len..self.at]
+    }
+
+    fn u8(&mut self) -> u8 {
+        self.bytes(1)[0]
+    }
+
+    fn u16(&mut self) -> u16 {
+        let bytes = self.bytes(2);
+        u16::from_be_bytes([bytes[0], bytes[1]])
+    }
+
+    fn u64(&mut self) -> u64 {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.bytes(8));
+        u64::from_be_bytes(bytes)
+    }
+}
+
+impl CPU {
+    /// Snapshot of the whole machine, including the keys held down, the quirks in use and the
+    /// random number generator, so a restored machine draws the same Cxkk numbers
+    /// Multi-byte values are big endian, and the screen is packed 8 pixels per byte.
+    pub fn save_state(&self) -> [u8; STATE_SIZE] {
+        let mut data = [0; STATE_SIZE];
+        let mut out = Writer {
+            data: &mut data,
+            at: 0,
+        };
+        out.bytes(&MAGIC);
+        out.bytes(&[VERSION]);
+        out.bytes(&self.memory);
+        out.bytes(&self.V);
+        for value in [self.I, self.DT, self.ST, self.PC].iter() {
+            out.bytes(&value.to_be_bytes());
+        }
+        out.bytes(&[self.SP]);
+        for address in self.stack.iter() {
+            out.bytes(&address.to_be_bytes());
+        }
+        let keys = self.keyboard.keys();
+        let keys = (0..16).fold(0u16, |bits, key| bits | (keys[key] as u16) << key);
+        out.bytes(&keys.to_be_bytes());
+        for row in self.display.rows().iter() {
+            out.bytes(&row.to_be_bytes());
+        }
+        out.bytes(&self.cycles.to_be_bytes());
+        out.bytes(&self.frames.to_be_bytes());
+        out.bytes(&self.rng.to_be_bytes());
+        let mut quirks = self.quirks;
+        let flags = quirk_flags(&mut quirks);
+        let quirks = (0..flags.len()).fold(0u8, |bits, bit| bits | (*flags[bit] as u8) << bit);
+        out.bytes(&[quirks]);
+        data
+    }
+
+    /// Restores a snapshot taken with `save_state`
+    /// The CPU is left untouched if the data isn't a valid state.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), Error> {
+        if data.len() != STATE_SIZE || data[..4] != MAGIC || data[4] != VERSION {
+            return Err(Error::InvalidState);
+        }
+        let mut input = Reader { data, at: 5 };
+        let mut state = CPU::new();
+        state.memory.copy_from_slice(input.bytes(4096));
+        state.V.copy_from_slice(input.bytes(16));
+        state.I = input.u16();
+        state.DT = input.u16();
+        state.ST = input.u16();
+        state.PC = input.u16();
+        state.SP = input.u8();
+        for address in state.stack.iter_mut() {
+            *address = input.u16();
+        }
+        if state.PC > 0xFFF
+            || state.SP as usize > state.stack.len()
+            || state.stack.iter().any(|&address| address > 0xFFF)
+        {
+            return Err(Error::InvalidState);
+        }
+        let keys = input.u16();
+        for key in (0..16).filter(|key| keys & 1 << key != 0) {
+            state.keyboard.key_down(key);
+        }
+        for y in 0..HEIGHT {
+            let row = input.u64();
+            for x in 0..WIDTH {
+                state
+                    .display
+                    .set_pixel(x, y, row & 1 << (WIDTH - 1 - x) != 0);
+            }
+        }
+        state.cycles = input.u64();
+        state.frames = input.u64();
+        state.rng = input.u64();
+        // xorshift never leaves zero, so no seed gives it
+        if state.rng == 0 {
+            return Err(Error::InvalidState);
+        }
+        let quirks = input.u8();
+        let mut flags = quirk_flags(&mut state.quirks);
+        if quirks >> flags.len() != 0 {
+            return Err(Error::InvalidState);
+        }
+        for (bit, flag) in flags.iter_mut().enumerate() {
+            **flag = quirks & 1 << bit != 0;
+        }
+        *self = state;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn state_round_trips() {
+        let mut cpu = CPU::new();
+        cpu.reset();
+        cpu.quirks = Quirks::CHIP8;
+        // 200: CALL 206; 206: LD F, V0 (with V0 = 8); 208: DRW V1, V1, 5
+        cpu.load_rom(&[0x22, 0x06, 0, 0, 0, 0, 0xF0, 0x29, 0xD1, 0x15])
+            .unwrap();
+        cpu.set_v(0, 8);
+        cpu.keyboard.key_down(0xA);
+        for _ in 0..3 {
+            cpu.execute_cycle().unwrap();
+        }
+        cpu.decrement_timers();
+
+        let state = cpu.save_state();
+        let mut copy = CPU::new();
+        copy.load_state(&state).unwrap();
+        assert_eq!(copy.save_state()[..], state[..]);
+        assert_eq!(copy.pc(), 0x20A);
+        assert_eq!(copy.sp(), 1);
+        assert_eq!(copy.frames(), 1);
+        assert!(copy.keyboard.key_pressed(0xA));
+        assert_eq!(copy.quirks, Quirks::CHIP8);
+
+        assert_eq!(copy.load_state(&state[1..]), Err(Error::InvalidState));
+    }
+
+    #[test]
+    fn restored_states_draw_the_same_random_numbers() {
+        let mut cpu = CPU::new();
+        cpu.reset();
+        cpu.seed(42);
+        // RND V0, 0xFF, four times
+        cpu.load_rom(&[0xC0, 0xFF, 0xC0, 0xFF, 0xC0, 0xFF, 0xC0, 0xFF])
+            .unwrap();
+        cpu.execute_cycle().unwrap();
+
+        let state = cpu.save_state();
+        let draw = |cpu: &mut CPU| {
+            let mut numbers = [0; 3];
+            for number in numbers.iter_mut() {
+                cpu.execute_cycle().unwrap();
+                *number = cpu.v()[0];
+            }
+            numbers
+        };
+        let first = draw(&mut cpu);
+        cpu.load_state(&state).unwrap();
+        assert_eq!(draw(&mut cpu), first);
+
+        // Restored into a machine with another seed, too
+        let mut other = CPU::new();
+        other.seed(7);
+        other.load_state(&state).unwrap();
+        assert_eq!(draw(&mut other), first);
+    }
+}
@@ -0,0 +1,11 @@
+//! The CHIP-8 interpreter, without a frontend
+//!
+//! Needs neither std nor an allocator. Everything platform specific is up to the caller:
+//! call `CPU::decrement_timers` at 60Hz, feed keys into `CPU::keyboard`, draw
+//! `CPU::display` and seed the random number generator with `CPU::seed`.
+#![no_std]
+
+#[cfg(feature = "std")]
+extern crate std;
+
+pub mod cpu;
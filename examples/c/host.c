@@ -0,0 +1,63 @@
+/*
+ * Runs a ROM headless for a number of frames, then prints the screen as text.
+ *
+ *   cargo build --release --features ffi
+ *   cc -Iinclude examples/c/host.c -Ltarget/release -lchip_8 -o host
+ *   LD_LIBRARY_PATH=target/release ./host game.ch8 [frames]
+ */
+#include <stdio.h>
+#include <stdlib.h>
+
+#include "chip8.h"
+
+static int fail(const char *what, Chip8Error error) {
+    fprintf(stderr, "%s: %s\n", what, chip8_error_message(error));
+    return 1;
+}
+
+int main(int argc, char **argv) {
+    if (argc < 2) {
+        fprintf(stderr, "usage: %s <rom> [frames]\n", argv[0]);
+        return 1;
+    }
+    int frames = argc > 2 ? atoi(argv[2]) : 60;
+
+    FILE *file = fopen(argv[1], "rb");
+    if (!file) {
+        perror(argv[1]);
+        return 1;
+    }
+    uint8_t rom[4096];
+    size_t len = fread(rom, 1, sizeof rom, file);
+    fclose(file);
+
+    Chip8 *chip8 = chip8_new(1);
+    Chip8Error error = chip8_load_rom(chip8, rom, len);
+    if (error != CHIP8_ERROR_OK) {
+        return fail("load", error);
+    }
+
+    /* A save state taken after the first frame, restored at the end as a round trip */
+    uint8_t state[CHIP8_STATE_SIZE];
+    for (int frame = 0; frame < frames; frame++) {
+        error = chip8_run_frame(chip8);
+        if (error != CHIP8_ERROR_OK) {
+            return fail("run", error);
+        }
+        if (frame == 0) {
+            chip8_save_state(chip8, state, sizeof state);
+        }
+    }
+
+    const uint8_t *screen = chip8_framebuffer(chip8);
+    for (int y = 0; y < CHIP8_HEIGHT; y++) {
+        for (int x = 0; x < CHIP8_WIDTH; x++) {
+            putchar(screen[y * CHIP8_WIDTH + x] ? '#' : '.');
+        }
+        putchar('\n');
+    }
+
+    error = chip8_load_state(chip8, state, sizeof state);
+    chip8_free(chip8);
+    return error == CHIP8_ERROR_OK ? 0 : fail("restore", error);
+}
@@ -0,0 +1,55 @@
+// Compares engine speed in millions of CHIP-8 instructions per second
+// Usage: cargo run --release --example mips [--features jit] [rom]
+// Without a ROM, runs a built-in loop of register arithmetic.
+
+use chip_8::cpu::CPU;
+use chip_8::engine::Engine;
+use std::time::Instant;
+
+/// Counts V0 down from 255, mixing in the other ALU instructions, forever
+#[rustfmt::skip]
+const LOOP: [u8; 24] = [
+    0x60, 0xFF, // 200: LD V0, 0xFF
+    0x61, 0x01, // 202: LD V1, 0x01
+    0x82, 0x04, // 204: ADD V2, V0
+    0x83, 0x23, // 206: XOR V3, V2
+    0x84, 0x36, // 208: SHR V4, V3
+    0xA3, 0x00, // 20A: LD I, 0x300
+    0xF2, 0x1E, // 20C: ADD I, V2
+    0x80, 0x15, // 20E: SUB V0, V1
+    0x30, 0x00, // 210: SE V0, 0
+    0x12, 0x04, // 212: JP 0x204
+    0x12, 0x00, // 214: JP 0x200
+    0x00, 0x00,
+];
+
+const CYCLES: u64 = 50_000_000;
+
+fn main() {
+    let rom = match std::env::args().nth(1) {
+        Some(path) => std::fs::read(path).unwrap(),
+        None => LOOP.to_vec(),
+    };
+
+    for name in Engine::NAMES.iter() {
+        let mut cpu = CPU::new();
+        cpu.reset();
+        cpu.load_rom(&rom).unwrap();
+        let mut engine = Engine::from_name(name).unwrap();
+
+        let start = Instant::now();
+        // Stop at the first error, e.g. a ROM that runs off the end of memory
+        let result = engine.run(&mut cpu, CYCLES);
+        let seconds = start.elapsed().as_secs_f64();
+
+        print!(
+            "{:>12}: {:8.1} MIPS",
+            name,
+            cpu.cycles() as f64 / seconds / 1e6
+        );
+        match result {
+            Ok(()) => println!(),
+            Err(e) => println!(" (stopped: {})", e),
+        }
+    }
+}
@@ -0,0 +1,46 @@
+// Loads arbitrary bytes as a ROM and runs them, any panic is a bug
+// cargo +nightly fuzz run execute
+
+#![no_main]
+
+use chip_8_core::cpu::quirks::Quirks;
+use chip_8_core::cpu::CPU;
+use libfuzzer_sys::fuzz_target;
+
+/// Cycles to run each input for
+const CYCLES: u32 = 10_000;
+
+/// Cycles per 60Hz frame, the same as the frontend
+const CYCLES_PER_FRAME: u32 = 10;
+
+fuzz_target!(|data: &[u8]| {
+    // The first byte picks the quirks and the keys held down, the rest is the ROM
+    let (&setup, rom) = match data.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+    let mut cpu = CPU::new();
+    cpu.reset();
+    cpu.quirks = Quirks {
+        shift_vy: setup & 0x01 != 0,
+        load_store_increment_i: setup & 0x02 != 0,
+        vf_reset: setup & 0x04 != 0,
+        jump_vx: setup & 0x08 != 0,
+        clip_sprites: setup & 0x10 != 0,
+    };
+    if setup & 0x20 != 0 {
+        cpu.keyboard.key_down(setup as usize & 0xF);
+    }
+    if cpu.load_rom(rom).is_err() {
+        return;
+    }
+    for cycle in 1..=CYCLES {
+        // Errors are fine, panics are not
+        if cpu.execute_cycle().is_err() {
+            break;
+        }
+        if cycle % CYCLES_PER_FRAME == 0 {
+            cpu.decrement_timers();
+        }
+    }
+});
@@ -0,0 +1,21 @@
+// Feeds arbitrary bytes to the save state loader, then runs whatever it accepted
+// cargo +nightly fuzz run load_state
+
+#![no_main]
+
+use chip_8_core::cpu::CPU;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let mut cpu = CPU::new();
+    if cpu.load_state(data).is_err() {
+        return;
+    }
+    // Anything accepted must save back to the same bytes
+    assert_eq!(&cpu.save_state()[..], data);
+    for _ in 0..1000 {
+        if cpu.execute_cycle().is_err() {
+            break;
+        }
+    }
+});
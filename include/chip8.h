@@ -0,0 +1,152 @@
+/* C API for the chip-8 emulator, build the library with `cargo build --release --features ffi` */
+
+#ifndef CHIP8_H
+#define CHIP8_H
+
+/* Generated by cbindgen from src/ffi.rs, don't edit by hand */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Screen width in pixels
+ */
+#define CHIP8_WIDTH 64
+
+/**
+ * Screen height in pixels
+ */
+#define CHIP8_HEIGHT 32
+
+/**
+ * Size of a save state in bytes
+ */
+#define CHIP8_STATE_SIZE 4441
+
+/**
+ * Result of every fallible call, 0 on success
+ * Values are part of the ABI: new ones may be added, existing ones never change.
+ */
+typedef enum Chip8Error {
+  CHIP8_ERROR_OK = 0,
+  /**
+   * A pointer argument was null
+   */
+  CHIP8_ERROR_NULL = 1,
+  /**
+   * A buffer argument is too small
+   */
+  CHIP8_ERROR_BUFFER_TOO_SMALL = 2,
+  CHIP8_ERROR_STACK_OVERFLOW = 3,
+  CHIP8_ERROR_STACK_UNDERFLOW = 4,
+  CHIP8_ERROR_ADDRESS_OUT_OF_RANGE = 5,
+  CHIP8_ERROR_INVALID_KEY = 6,
+  CHIP8_ERROR_ROM_TOO_LARGE = 7,
+  CHIP8_ERROR_INVALID_STATE = 8,
+} Chip8Error;
+
+/**
+ * An emulator instance, only ever handled through a pointer
+ */
+typedef struct Chip8 Chip8;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Creates an emulator with nothing loaded, free it with `chip8_free`
+ * seed feeds the random numbers of Cxkk.
+ */
+struct Chip8 *chip8_new(uint64_t seed);
+
+/**
+ * Frees an emulator from `chip8_new`, does nothing for null
+ *
+ * # Safety
+ * chip8 must be null or come from `chip8_new`, and not be used afterwards.
+ */
+void chip8_free(struct Chip8 *chip8);
+
+/**
+ * Resets the machine and copies len bytes of rom to 0x200
+ *
+ * # Safety
+ * chip8 must come from `chip8_new` and rom must point to len readable bytes.
+ */
+enum Chip8Error chip8_load_rom(struct Chip8 *chip8, const uint8_t *rom, size_t len);
+
+/**
+ * Runs one 60Hz frame: the instructions, then a timer tick
+ * On an error the machine stops at the failing instruction, as it was before it ran.
+ *
+ * # Safety
+ * chip8 must come from `chip8_new`.
+ */
+enum Chip8Error chip8_run_frame(struct Chip8 *chip8);
+
+/**
+ * Sets how many instructions `chip8_run_frame` executes
+ *
+ * # Safety
+ * chip8 must come from `chip8_new`.
+ */
+void chip8_set_cycles_per_frame(struct Chip8 *chip8, uint32_t cycles);
+
+/**
+ * Presses (down is true) or releases key 0-F
+ *
+ * # Safety
+ * chip8 must come from `chip8_new`.
+ */
+enum Chip8Error chip8_key(struct Chip8 *chip8, uint8_t key, bool down);
+
+/**
+ * The screen as of the last call, `CHIP8_WIDTH * CHIP8_HEIGHT` bytes row by row, 1 for a lit
+ * pixel and 0 for an unlit one
+ * The pointer stays valid until chip8 is freed.
+ *
+ * # Safety
+ * chip8 must come from `chip8_new`.
+ */
+const uint8_t *chip8_framebuffer(const struct Chip8 *chip8);
+
+/**
+ * Whether the buzzer should sound
+ *
+ * # Safety
+ * chip8 must come from `chip8_new`.
+ */
+bool chip8_sound(const struct Chip8 *chip8);
+
+/**
+ * Writes a snapshot of the machine, `CHIP8_STATE_SIZE` bytes, into out
+ *
+ * # Safety
+ * chip8 must come from `chip8_new` and out must point to len writable bytes.
+ */
+enum Chip8Error chip8_save_state(const struct Chip8 *chip8, uint8_t *out, size_t len);
+
+/**
+ * Restores a snapshot from `chip8_save_state`, leaving the machine untouched if it's invalid
+ *
+ * # Safety
+ * chip8 must come from `chip8_new` and state must point to len readable bytes.
+ */
+enum Chip8Error chip8_load_state(struct Chip8 *chip8, const uint8_t *state, size_t len);
+
+/**
+ * Describes an error, as a static NUL-terminated string
+ * Any int is accepted: values that aren't a `Chip8Error`, e.g. from a newer library, are
+ * described as "unknown error".
+ */
+const char *chip8_error_message(int error);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* CHIP8_H */
@@ -0,0 +1,71 @@
+# Run with pytest after `maturin develop`, as .github/workflows/python.yml does
+import chip8
+import pytest
+
+# Counts V0 up and stores it at 0x300 forever
+# 200: ADD V0, 1; 202: LD I, 0x300; 204: LD [I], V0; 206: JP 0x200
+COUNTER = bytes([0x70, 0x01, 0xA3, 0x00, 0xF0, 0x55, 0x12, 0x00])
+
+
+def test_cpu_views():
+    cpu = chip8.CPU()
+    cpu.load_rom(COUNTER)
+    cpu.run(4)
+    assert cpu.pc == 0x200
+    assert cpu.memory[0x300] == 1
+    assert cpu.v[0] == 1
+    assert cpu.screen.shape == (32, 64)
+
+
+# Scores the counter and ends the episode when it reaches 10
+CONFIG = """
+frame_skip = 1
+cycles_per_frame = 4
+
+[[reward]]
+address = 0x300
+
+[[terminal]]
+address = 0x300
+equals = 10
+"""
+
+
+def test_env_rewards_and_terminates():
+    env = chip8.Env(COUNTER, config=CONFIG)
+    obs = env.reset(seed=1)
+    assert obs.shape == (32, 64)
+    total, done, steps = 0.0, False, 0
+    while not done:
+        obs, reward, done = env.step(0)
+        total += reward
+        steps += 1
+    assert (total, steps, env.frame) == (10.0, 10, 10)
+
+
+def test_env_settings_override_the_config():
+    env = chip8.Env(COUNTER, config=CONFIG, frame_skip=2, sticky_actions=0.5, max_frames=6)
+    steps = 0
+    done = False
+    while not done:
+        _, _, done = env.step(1 << 4 | 1)
+        steps += 1
+    assert (steps, env.frame) == (3, 6)
+
+
+def test_custom_reward_callables():
+    env = chip8.Env(COUNTER, reward=chip8.Delta(0x300, scale=0.5), done=chip8.Equals(0x300, 3),
+                    frame_skip=1, cycles_per_frame=4)
+    rewards = [env.step()[1] for _ in range(2)]
+    assert rewards == [0.5, 0.5]
+    assert env.step()[2]
+
+    env = chip8.Env(COUNTER, reward=lambda before, after: float(after[0x300] % 2))
+    _, reward, _ = env.step(1 << 3)
+    assert reward in (0.0, 1.0)
+
+
+def test_bad_configs_are_rejected():
+    for config in ['quirks = "nope"', "frames = 2"]:
+        with pytest.raises(ValueError):
+            chip8.Env(COUNTER, config=config)
@@ -0,0 +1,14 @@
+// Debug Adapter Protocol server speaking over stdin/stdout
+// Point an editor's debug configuration at this binary with a launch request such as
+// { "program": "game.ch8", "symbols": "game.sym", "stopOnEntry": true }
+
+use std::io::{self, BufReader};
+use std::process;
+
+fn main() {
+    if let Err(e) = chip_8::debug::dap::serve(BufReader::new(io::stdin()), io::stdout()) {
+        // stdout belongs to the protocol
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
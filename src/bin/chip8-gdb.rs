@@ -0,0 +1,43 @@
+// Usage: chip8-gdb <rom> [port]
+// Then from gdb: target remote localhost:<port> (1234 by default)
+
+use chip_8::cpu::CPU;
+use chip_8::debug::gdb::GdbStub;
+use chip_8::rom;
+use std::fmt;
+use std::process;
+
+const USAGE: &str = "usage: chip8-gdb <rom> [port]";
+
+fn fail<E: fmt::Display>(error: E) -> ! {
+    eprintln!("error: {}", error);
+    process::exit(1);
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let game_path = args.next().unwrap_or_else(|| {
+        eprintln!("{}", USAGE);
+        process::exit(2);
+    });
+    let port = match args.next() {
+        Some(port) => port
+            .parse::<u16>()
+            .unwrap_or_else(|_| fail(format!("invalid port '{}'\n{}", port, USAGE))),
+        None => 1234,
+    };
+
+    let mut cpu = CPU::new();
+    cpu.reset();
+    cpu.seed(rand::random());
+
+    let rom = rom::load(&game_path).unwrap_or_else(|e| fail(format!("{}: {}", game_path, e)));
+    // rom::load checked that it fits
+    cpu.load_rom(&rom).unwrap();
+
+    let mut stub = GdbStub::new(cpu);
+    println!("Waiting for gdb on localhost:{}", port);
+    if let Err(e) = stub.listen(("127.0.0.1", port)) {
+        fail(format!("localhost:{}: {}", port, e));
+    }
+}
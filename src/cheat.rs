@@ -0,0 +1,262 @@
+use crate::cpu::CPU;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// How a memory value must have changed since the last snapshot to stay a search candidate
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Equal(u8),
+    Changed,
+    Unchanged,
+    Increased,
+    Decreased,
+}
+
+impl Comparison {
+    fn matches(self, old: u8, new: u8) -> bool {
+        match self {
+            Comparison::Equal(value) => new == value,
+            Comparison::Changed => new != old,
+            Comparison::Unchanged => new == old,
+            Comparison::Increased => new > old,
+            Comparison::Decreased => new < old,
+        }
+    }
+}
+
+/// Iterative memory search
+/// Starts with every address as a candidate and narrows them down on each `filter` call,
+/// e.g. "the lives counter decreased since I last looked".
+pub struct Search {
+    snapshot: [u8; 4096],
+    candidates: Vec<u16>,
+}
+
+impl Search {
+    /// Starts a search with every address as a candidate, snapshotting memory
+    pub fn new(cpu: &CPU) -> Search {
+        Search {
+            snapshot: *cpu.memory(),
+            candidates: (0..4096).collect(),
+        }
+    }
+
+    /// Keeps the candidates whose value matches comparison, then takes a new snapshot
+    pub fn filter(&mut self, cpu: &CPU, comparison: Comparison) {
+        let memory = cpu.memory();
+        let snapshot = &self.snapshot;
+        self.candidates.retain(|&address| {
+            let address = address as usize;
+            comparison.matches(snapshot[address], memory[address])
+        });
+        self.snapshot = *memory;
+    }
+
+    /// Addresses still matching every filter so far
+    pub fn candidates(&self) -> &[u16] {
+        &self.candidates
+    }
+
+    /// Value of address in the last snapshot
+    pub fn value(&self, address: u16) -> u8 {
+        self.snapshot[address as usize & 0xFFF]
+    }
+}
+
+/// Freeze codes are written every frame, poke codes only once
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Freeze,
+    Poke,
+}
+
+/// A single cheat: write value to address
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Code {
+    pub kind: Kind,
+    pub address: u16,
+    pub value: u8,
+    pub description: String,
+    pub enabled: bool,
+}
+
+/// Formats the code as a line of a cheat file
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match self.kind {
+            Kind::Freeze => "freeze",
+            Kind::Poke => "poke",
+        };
+        write!(
+            f,
+            "{}{} 0x{:03X} 0x{:02X}",
+            if self.enabled { "" } else { "#" },
+            kind,
+            self.address,
+            self.value
+        )?;
+        if !self.description.is_empty() {
+            write!(f, " {}", self.description)?;
+        }
+        Ok(())
+    }
+}
+
+/// The cheats for one ROM
+///
+/// Cheat files hold one code per line: "freeze" or "poke", a hex address, a hex value and an
+/// optional description. A line starting with '#' is a disabled code if a valid code follows the
+/// '#', and a comment otherwise.
+///
+/// ```text
+/// freeze 0x3F0 0x09 Infinite lives
+/// #poke 0x2A5 0x00 Skip intro
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Cheats {
+    pub codes: Vec<Code>,
+    /// Indices of the poke codes that were already written
+    poked: Vec<usize>,
+}
+
+impl Cheats {
+    pub fn new() -> Cheats {
+        Cheats::default()
+    }
+
+    /// Where the cheat file for rom lives inside dir, named after the ROM's hash
+    pub fn path_for(dir: &Path, rom: &[u8]) -> PathBuf {
+        dir.join(format!("{:08x}.cht", crate::rom::hash(rom)))
+    }
+
+    /// Reads a cheat file
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Cheats> {
+        Cheats::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Writes every code to a cheat file
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut text = String::new();
+        for code in &self.codes {
+            text.push_str(&format!("{}\n", code));
+        }
+        fs::write(path, text)
+    }
+
+    /// Parses the contents of a cheat file
+    pub fn parse(text: &str) -> io::Result<Cheats> {
+        let mut cheats = Cheats::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            let (enabled, line) = match line.strip_prefix('#') {
+                Some(rest) => (false, rest.trim_start()),
+                None => (true, line),
+            };
+            let mut fields = line.splitn(4, char::is_whitespace);
+            let kind = match fields.next() {
+                Some("freeze") => Kind::Freeze,
+                Some("poke") => Kind::Poke,
+                // Blank lines and comments
+                _ if !enabled || line.is_empty() => continue,
+                _ => return Err(invalid(number, line)),
+            };
+            let address = fields.next().and_then(parse_hex);
+            let value = fields.next().and_then(parse_hex);
+            match (address, value) {
+                (Some(address), Some(value)) if address < 0x1000 && value <= 0xFF => {
+                    cheats.codes.push(Code {
+                        kind,
+                        address,
+                        value: value as u8,
+                        description: fields.next().unwrap_or_default().trim().to_string(),
+                        enabled,
+                    })
+                }
+                // A comment starting with a word like "freeze"
+                _ if !enabled => continue,
+                _ => return Err(invalid(number, line)),
+            }
+        }
+        Ok(cheats)
+    }
+
+    /// Adds a code, enabled
+    pub fn add(&mut self, kind: Kind, address: u16, value: u8, description: &str) {
+        self.codes.push(Code {
+            kind,
+            address: address & 0xFFF,
+            value,
+            description: description.to_string(),
+            enabled: true,
+        });
+    }
+
+    /// Writes the enabled codes into memory, call once per frame
+    /// Poke codes are only written the first time they are seen enabled. Returns the addresses
+    /// whose value changed, for `Machine::invalidate`.
+    pub fn apply(&mut self, cpu: &mut CPU) -> Vec<u16> {
+        let mut changed = Vec::new();
+        for (index, code) in self.codes.iter().enumerate() {
+            if !code.enabled {
+                continue;
+            }
+            match code.kind {
+                Kind::Freeze => (),
+                Kind::Poke if !self.poked.contains(&index) => self.poked.push(index),
+                Kind::Poke => continue,
+            }
+            if cpu.memory()[code.address as usize & 0xFFF] != code.value {
+                cpu.poke(code.address, code.value);
+                changed.push(code.address);
+            }
+        }
+        changed
+    }
+}
+
+fn parse_hex(text: &str) -> Option<u16> {
+    let digits = text.trim_start_matches("0x").trim_start_matches("0X");
+    u16::from_str_radix(digits, 16).ok()
+}
+
+fn invalid(number: usize, line: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid cheat on line {}: {}", number + 1, line),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn search_narrows_down_and_freeze_holds() {
+        let mut cpu = CPU::new();
+        cpu.reset();
+        cpu.poke(0x3F0, 3);
+        let mut search = Search::new(&cpu);
+        cpu.poke(0x3F0, 2);
+        search.filter(&cpu, Comparison::Decreased);
+        search.filter(&cpu, Comparison::Equal(2));
+        assert_eq!(search.candidates(), &[0x3F0]);
+
+        let mut cheats =
+            Cheats::parse("# lives\nfreeze 0x3F0 0x09 Infinite lives\n#poke 0x200 0x00\n").unwrap();
+        assert_eq!(cheats.codes.len(), 2);
+        let comments = Cheats::parse("# freeze the lives\n#poke 0x1000 0x00\n").unwrap();
+        assert!(comments.codes.is_empty());
+        assert!(Cheats::parse("freeze the lives\n").is_err());
+        assert!(!cheats.codes[1].enabled);
+        assert_eq!(cheats.apply(&mut cpu), [0x3F0]);
+        assert_eq!(cpu.memory()[0x3F0], 9);
+        // Only memory that changes is reported, so engines keep their code
+        assert!(cheats.apply(&mut cpu).is_empty());
+        assert_eq!(
+            Cheats::parse(&cheats.codes[0].to_string()).unwrap().codes[0],
+            cheats.codes[0]
+        );
+    }
+}
@@ -0,0 +1,230 @@
+use chip_8::cpu::quirks::Quirks;
+use chip_8::engine::Engine;
+use chip_8::render::Palette;
+use clap::builder::PossibleValuesParser;
+use clap::{value_parser, Args, Parser, Subcommand, ValueHint};
+use clap_complete::Shell;
+use std::ops::{Range, RangeInclusive};
+use std::path::PathBuf;
+
+/// CHIP-8 emulator
+///
+/// `chip-8 <ROM>` with no command is the same as `chip-8 run <ROM>`.
+#[derive(Parser, Debug)]
+#[command(
+    name = "chip-8",
+    version,
+    about,
+    args_conflicts_with_subcommands = true,
+    arg_required_else_help = true
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// Options of a bare `chip-8 <ROM>`
+    #[command(flatten)]
+    pub run: Option<RunArgs>,
+}
+
+impl Cli {
+    /// The command to carry out, `run` for a bare ROM
+    pub fn into_command(self) -> Command {
+        match (self.command, self.run) {
+            (Some(command), _) => command,
+            (None, Some(run)) => Command::Run(Box::new(run)),
+            // clap requires one or the other
+            (None, None) => unreachable!(),
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Plays a ROM in a window
+    Run(Box<RunArgs>),
+    /// Prints a disassembly of a ROM
+    Disasm {
+        #[arg(value_hint = ValueHint::FilePath)]
+        rom: PathBuf,
+    },
+    /// Prints a ROM's size, CRC-32 and cheat file
+    Info {
+        #[arg(value_hint = ValueHint::FilePath)]
+        rom: PathBuf,
+    },
+    /// Prints a shell completion script
+    ///
+    /// e.g. `chip-8 completions bash > ~/.local/share/bash-completion/completions/chip-8`
+    Completions { shell: Shell },
+}
+
+#[derive(Args, Debug)]
+pub struct RunArgs {
+    /// ROM file to play
+    #[arg(value_hint = ValueHint::FilePath)]
+    pub rom: PathBuf,
+    /// Config file instead of the one in the XDG config directory
+    #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub config: Option<PathBuf>,
+    /// Prints the settings in effect and where each came from, then exits
+    #[arg(long)]
+    pub print_config: bool,
+    /// Instructions per second [default: 600]
+    #[arg(long, value_parser = value_parser!(u32).range(1..=1_000_000))]
+    pub ips: Option<u32>,
+    /// Quirk profile, for ROMs written for a particular interpreter [default: default]
+    #[arg(long, value_parser = PossibleValuesParser::new(Quirks::PROFILES))]
+    pub quirks: Option<String>,
+    /// How instructions are executed, see src/engine; not with --trace, --profile, --coverage or
+    /// --script, which watch every instruction [default: interpreter]
+    #[arg(
+        long,
+        value_parser = PossibleValuesParser::new(Engine::NAMES),
+        conflicts_with_all = ["trace", "profile", "coverage"]
+    )]
+    pub engine: Option<String>,
+    /// Screen pixels per CHIP-8 pixel [default: 15]
+    #[arg(long, value_parser = value_parser!(u32).range(1..=64))]
+    pub scale: Option<u32>,
+    /// classic, green, amber, mono, or lit and unlit colors as RRGGBB:RRGGBB [default: classic]
+    #[arg(long)]
+    pub palette: Option<Palette>,
+    /// Seed for the random numbers of Cxkk, random by default
+    #[arg(long)]
+    pub seed: Option<u64>,
+    /// Borderless window filling the screen
+    #[arg(long)]
+    pub fullscreen: bool,
+    /// Silences the buzzer, a terminal bell
+    #[arg(long)]
+    pub mute: bool,
+    /// Records the keypad to a movie file for --replay
+    #[arg(long, value_name = "MOVIE", value_hint = ValueHint::FilePath, conflicts_with = "replay")]
+    pub record: Option<PathBuf>,
+    /// Plays back a movie from --record, with its seed, quirks and speed, then hands over to the
+    /// keyboard
+    #[arg(long, value_name = "MOVIE", value_hint = ValueHint::FilePath)]
+    pub replay: Option<PathBuf>,
+    /// Writes each instruction run to FILE with the registers it changed
+    #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub trace: Option<PathBuf>,
+    /// Keeps only the last N instructions, written out if one fails
+    #[arg(long, value_name = "N", requires = "trace")]
+    pub trace_last: Option<usize>,
+    /// Traces only instructions whose family starts with FAMILY, e.g. Dxyn or 8, can be repeated
+    #[arg(long, value_name = "FAMILY", requires = "trace")]
+    pub trace_family: Vec<String>,
+    /// Traces only instructions at addresses FIRST to LAST, in hex, e.g. 2A0-2FF
+    #[arg(long, value_name = "FIRST-LAST", value_parser = parse_addresses, requires = "trace")]
+    pub trace_pc: Option<RangeInclusive<u16>>,
+    /// Traces only instructions run during frames FIRST to LAST, e.g. 600-660
+    #[arg(long, value_name = "FIRST-LAST", value_parser = parse_frames, requires = "trace")]
+    pub trace_frames: Option<Range<u64>>,
+    /// Writes an execution profile to FILE on exit: JSON for a .json file, folded stacks for
+    /// flamegraph tools for a .folded file, a report of hot spots and subroutines otherwise
+    #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub profile: Option<PathBuf>,
+    /// Writes a listing of the ROM marking the code run and the data read and written to FILE on
+    /// exit, and the same as an lcov tracefile to FILE.info
+    #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub coverage: Option<PathBuf>,
+    /// Rhai script to automate the game, see src/script.rs; the script steps the interpreter
+    /// itself, so not with --engine
+    #[cfg(feature = "scripting")]
+    #[arg(long, value_hint = ValueHint::FilePath, conflicts_with = "engine")]
+    pub script: Option<PathBuf>,
+}
+
+/// Splits FIRST-LAST, checking that FIRST comes first
+fn parse_range<T: PartialOrd>(
+    text: &str,
+    parse: impl Fn(&str) -> Option<T>,
+) -> Result<(T, T), String> {
+    let (first, last) = text
+        .split_once('-')
+        .and_then(|(first, last)| Some((parse(first)?, parse(last)?)))
+        .ok_or("expected FIRST-LAST")?;
+    if first > last {
+        return Err("FIRST is after LAST".to_string());
+    }
+    Ok((first, last))
+}
+
+fn parse_addresses(text: &str) -> Result<RangeInclusive<u16>, String> {
+    let hex = |a: &str| u16::from_str_radix(a.trim_start_matches("0x"), 16).ok();
+    parse_range(text, hex).map(|(first, last)| first..=last)
+}
+
+fn parse_frames(text: &str) -> Result<Range<u64>, String> {
+    parse_range(text, |f| f.parse::<u64>().ok()).map(|(first, last)| first..last.saturating_add(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn parses_run_options() {
+        Cli::command().debug_assert();
+
+        let cli = Cli::try_parse_from(
+            "chip-8 run pong.ch8 --ips 1000 --quirks schip --palette amber --seed 7 --mute"
+                .split(' '),
+        )
+        .unwrap();
+        let run = match cli.into_command() {
+            Command::Run(run) => run,
+            command => panic!("{:?}", command),
+        };
+        assert_eq!((run.ips, run.scale), (Some(1000), None));
+        assert_eq!(run.palette, Some(Palette::PRESETS[2].1));
+        assert_eq!((run.seed, run.mute, run.fullscreen), (Some(7), true, false));
+        let cached = Cli::try_parse_from("chip-8 run pong.ch8 --engine cached".split(' ')).unwrap();
+        match cached.into_command() {
+            Command::Run(run) => assert_eq!(run.engine.as_deref(), Some("cached")),
+            command => panic!("{:?}", command),
+        }
+        let traced = Cli::try_parse_from(
+            "chip-8 run pong.ch8 --trace t.txt --trace-pc 2a0-0x2FF --trace-frames 600-660"
+                .split(' '),
+        )
+        .unwrap();
+        match traced.into_command() {
+            Command::Run(run) => {
+                assert_eq!(run.trace_pc, Some(0x2A0..=0x2FF));
+                assert_eq!(run.trace_frames, Some(600..661));
+            }
+            command => panic!("{:?}", command),
+        }
+
+        // A bare ROM runs it, with the same options
+        let bare = Cli::try_parse_from("chip-8 pong.ch8 --scale 4".split(' ')).unwrap();
+        match bare.into_command() {
+            Command::Run(run) => assert_eq!((run.rom, run.scale), ("pong.ch8".into(), Some(4))),
+            command => panic!("{:?}", command),
+        }
+        let info = Cli::try_parse_from("chip-8 info pong.ch8".split(' ')).unwrap();
+        assert!(matches!(info.into_command(), Command::Info { .. }));
+
+        for args in [
+            "chip-8 run pong.ch8 --quirks vip",
+            "chip-8 run pong.ch8 --palette fff:000",
+            "chip-8 run pong.ch8 --record a --replay b",
+            "chip-8 run pong.ch8 --engine warp",
+            "chip-8 run pong.ch8 --engine cached --trace t.txt",
+            "chip-8 run pong.ch8 --trace t.txt --trace-pc 300-200",
+            "chip-8 run pong.ch8 --trace t.txt --trace-frames 10",
+            "chip-8 run pong.ch8 --trace-pc 200-300",
+            "chip-8",
+            "chip-8 pong.ch8 info",
+        ] {
+            assert!(Cli::try_parse_from(args.split(' ')).is_err(), "{}", args);
+        }
+        #[cfg(feature = "scripting")]
+        assert!(
+            Cli::try_parse_from("chip-8 run pong.ch8 --engine jit --script s.rhai".split(' '))
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,271 @@
+use crate::cpu::hook::Hook;
+use crate::cpu::instruction::Instruction;
+use crate::cpu::{Error, CPU};
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+/// Address ROMs are loaded at, see `CPU::load_rom`
+const ROM_START: usize = 0x200;
+
+/// The byte was fetched as part of an executed instruction
+pub const EXECUTED: u8 = 0b001;
+/// The byte was read as data by Dxyn or Fx65
+pub const READ: u8 = 0b010;
+/// The byte was written by Fx33 or Fx55
+pub const WRITTEN: u8 = 0b100;
+
+/// One line of the annotated listing: either an instruction or a single data byte
+struct Line {
+    address: usize,
+    instruction: bool,
+}
+
+/// Records how every byte of memory was used during a session
+/// and exports the result as an annotated listing or an lcov tracefile
+pub struct Coverage {
+    /// EXECUTED / READ / WRITTEN bits for every address
+    flags: Vec<u8>,
+
+    /// Number of times an instruction starting at each address was executed
+    counts: Vec<u64>,
+
+    /// [not taken, taken] counts for every skip instruction executed
+    branches: BTreeMap<u16, [u64; 2]>,
+
+    /// Skip instruction that is currently executing
+    pending_skip: Option<u16>,
+
+    /// Start, length and flag of the memory the current instruction accesses, marked once it
+    /// has run so failed instructions don't count
+    pending_access: Option<(usize, usize, u8)>,
+}
+
+impl Default for Coverage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Coverage {
+    /// Returns an empty coverage map
+    pub fn new() -> Coverage {
+        Coverage {
+            flags: vec![0; 4096],
+            counts: vec![0; 4096],
+            branches: BTreeMap::new(),
+            pending_skip: None,
+            pending_access: None,
+        }
+    }
+
+    /// EXECUTED / READ / WRITTEN bits recorded for address
+    pub fn flags(&self, address: u16) -> u8 {
+        self.flags[address as usize & 0xFFF]
+    }
+
+    /// [not taken, taken] counts for the skip instruction at address
+    pub fn branch(&self, address: u16) -> Option<[u64; 2]> {
+        self.branches.get(&address).copied()
+    }
+
+    fn mark(&mut self, start: usize, len: usize, flag: u8) {
+        for address in start..start + len {
+            self.flags[address & 0xFFF] |= flag;
+        }
+    }
+
+    /// Splits the ROM into listing lines
+    /// Executed addresses and untouched even-aligned words are shown as instructions,
+    /// everything else as data bytes, so missed code still shows up as code.
+    fn layout(&self, rom: &[u8]) -> Vec<Line> {
+        let end = ROM_START + loaded(rom).len();
+        let mut lines = Vec::new();
+        let mut address = ROM_START;
+        while address < end {
+            let executed = self.flags[address] & EXECUTED != 0;
+            let untouched = address + 1 < end
+                && (address - ROM_START) & 1 == 0
+                && self.flags[address] == 0
+                && self.flags[address + 1] & !EXECUTED == 0;
+            let instruction = address + 1 < end && (executed || untouched);
+            lines.push(Line {
+                address,
+                instruction,
+            });
+            address += if instruction { 2 } else { 1 };
+        }
+        lines
+    }
+
+    /// Writes a disassembly of rom with one usage marker column per line:
+    /// X executed, R read, W written, - never touched
+    pub fn write_listing(&self, out: &mut dyn Write, rom: &[u8]) -> io::Result<()> {
+        let rom = loaded(rom);
+        for line in self.layout(rom) {
+            let offset = line.address - ROM_START;
+            let mut marks = String::new();
+            let len = if line.instruction { 2 } else { 1 };
+            let flags = self.flags[line.address..line.address + len]
+                .iter()
+                .fold(0, |acc, &f| acc | f);
+            for &(flag, mark) in &[(EXECUTED, 'X'), (READ, 'R'), (WRITTEN, 'W')] {
+                marks.push(if flags & flag != 0 { mark } else { '-' });
+            }
+            if line.instruction {
+                let opcode = ((rom[offset] as u16) << 8) | rom[offset + 1] as u16;
+                let mut text = Instruction::decode(opcode).to_string();
+                if let Some([not_taken, taken]) = self.branch(line.address as u16) {
+                    text = format!("{:<18} ; taken {}, not taken {}", text, taken, not_taken);
+                }
+                writeln!(
+                    out,
+                    "{:03X}: {:04X}  {} {:>8}  {}",
+                    line.address, opcode, marks, self.counts[line.address], text
+                )?;
+            } else {
+                writeln!(
+                    out,
+                    "{:03X}: {:02X}    {} {:>8}  DB 0x{:02X}",
+                    line.address, rom[offset], marks, "", rom[offset]
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes an lcov tracefile against the listing produced by `write_listing`
+    /// source_name is recorded as the SF entry, i.e. the path the listing is saved to.
+    /// Instruction lines become DA records and skip instructions BRDA records.
+    pub fn write_lcov(&self, out: &mut dyn Write, rom: &[u8], source_name: &str) -> io::Result<()> {
+        writeln!(out, "TN:")?;
+        writeln!(out, "SF:{}", source_name)?;
+        let (mut found, mut hit, mut branches_found, mut branches_hit) = (0, 0, 0, 0);
+        for (index, line) in self.layout(rom).iter().enumerate() {
+            if !line.instruction {
+                continue;
+            }
+            let number = index + 1;
+            let count = self.counts[line.address];
+            found += 1;
+            if count > 0 {
+                hit += 1;
+            }
+            if let Some(counts) = self.branch(line.address as u16) {
+                for (branch, &taken) in counts.iter().enumerate() {
+                    writeln!(out, "BRDA:{},0,{},{}", number, branch, taken)?;
+                    branches_found += 1;
+                    if taken > 0 {
+                        branches_hit += 1;
+                    }
+                }
+            }
+            writeln!(out, "DA:{},{}", number, count)?;
+        }
+        writeln!(out, "BRF:{}", branches_found)?;
+        writeln!(out, "BRH:{}", branches_hit)?;
+        writeln!(out, "LF:{}", found)?;
+        writeln!(out, "LH:{}", hit)?;
+        writeln!(out, "end_of_record")
+    }
+}
+
+impl Hook for Coverage {
+    fn before_instruction(&mut self, cpu: &CPU, opcode: u16) {
+        let pc = cpu.pc() as usize;
+        let i = cpu.i() as usize;
+        self.counts[pc & 0xFFF] += 1;
+        self.mark(pc, 2, EXECUTED);
+
+        self.pending_skip = None;
+        self.pending_access = None;
+        match Instruction::decode(opcode) {
+            Instruction::Drw(_, _, n) => self.pending_access = Some((i, n as usize, READ)),
+            Instruction::LoadRegs(x) => self.pending_access = Some((i, x + 1, READ)),
+            Instruction::LdB(_) => self.pending_access = Some((i, 3, WRITTEN)),
+            Instruction::StoreRegs(x) => self.pending_access = Some((i, x + 1, WRITTEN)),
+            Instruction::SeByte(..)
+            | Instruction::SneByte(..)
+            | Instruction::SeReg(..)
+            | Instruction::SneReg(..)
+            | Instruction::Skp(_)
+            | Instruction::Sknp(_) => self.pending_skip = Some(cpu.pc()),
+            _ => (),
+        }
+    }
+
+    fn after_instruction(&mut self, cpu: &CPU, _opcode: u16) {
+        if let Some((start, len, flag)) = self.pending_access.take() {
+            self.mark(start, len, flag);
+        }
+        if let Some(address) = self.pending_skip.take() {
+            let taken = cpu.pc() == address.wrapping_add(4);
+            self.branches.entry(address).or_insert([0, 0])[taken as usize] += 1;
+        }
+    }
+
+    fn on_error(&mut self, _cpu: &CPU, _opcode: Option<u16>, _error: &Error) {
+        self.pending_access = None;
+        self.pending_skip = None;
+    }
+}
+
+/// The part of rom that fits in memory, see `CPU::load_rom`
+fn loaded(rom: &[u8]) -> &[u8] {
+    &rom[..rom.len().min(4096 - ROM_START)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn listing_and_lcov() {
+        #[rustfmt::skip]
+        let rom = [
+            0xA3, 0x00, // 200: LD I, 300
+            0x60, 0x05, // 202: LD V0, 5
+            0xF0, 0x55, // 204: LD [I], V0
+            0x30, 0x05, // 206: SE V0, 5
+            0x12, 0x08, // 208: JP 208, skipped
+            0xAF, 0xFF, // 20A: LD I, FFF
+            0xF1, 0x65, // 20C: LD V1, [I], runs past memory
+        ];
+        let mut cpu = CPU::new();
+        cpu.reset();
+        cpu.load_rom(&rom).unwrap();
+        let mut coverage = Coverage::new();
+        while cpu.execute_cycle_with(&mut coverage).is_ok() {}
+
+        assert_eq!(coverage.flags(0x300), WRITTEN);
+        assert_eq!(coverage.flags(0x208), 0);
+        assert_eq!(coverage.flags(0x20C), EXECUTED);
+        // The failed Fx65 read nothing
+        assert_eq!((coverage.flags(0xFFF), coverage.flags(0x000)), (0, 0));
+        assert_eq!(coverage.branch(0x206), Some([0, 1]));
+
+        let mut listing = Vec::new();
+        coverage.write_listing(&mut listing, &rom).unwrap();
+        let listing = String::from_utf8(listing).unwrap();
+        let lines: Vec<&str> = listing.lines().collect();
+        assert_eq!(lines.len(), 7);
+        assert_eq!(lines[2], "204: F055  X--        1  LD [I], V0");
+        assert_eq!(
+            lines[3],
+            "206: 3005  X--        1  SE V0, 0x05        ; taken 1, not taken 0"
+        );
+        assert_eq!(lines[4], "208: 1208  ---        0  JP 0x208");
+
+        let mut lcov = Vec::new();
+        coverage.write_lcov(&mut lcov, &rom, "rom.lst").unwrap();
+        let lcov = String::from_utf8(lcov).unwrap();
+        assert!(lcov.starts_with("TN:\nSF:rom.lst\nDA:1,1\n"));
+        assert!(lcov.contains("BRDA:4,0,0,0\nBRDA:4,0,1,1\nDA:4,1\nDA:5,0\n"));
+        assert!(lcov.ends_with("BRF:2\nBRH:1\nLF:7\nLH:6\nend_of_record\n"));
+
+        // A ROM too big for memory is listed as far as it was loaded
+        let mut listing = Vec::new();
+        let big = [0x12; 4000];
+        Coverage::new().write_listing(&mut listing, &big).unwrap();
+        assert_eq!(String::from_utf8(listing).unwrap().lines().count(), 1792);
+    }
+}
@@ -0,0 +1,671 @@
+// Protocol reference: https://microsoft.github.io/debug-adapter-protocol/specification
+
+use super::symbols::Symbols;
+use crate::cpu::instruction::Instruction;
+use crate::cpu::{Error, CPU};
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, Write};
+use std::sync::mpsc::{self, TryRecvError};
+use std::thread;
+
+/// CHIP-8 has a single thread of execution
+const THREAD_ID: i64 = 1;
+
+/// variablesReference values handed out by the scopes request
+const REGISTERS: i64 = 1;
+const STACK: i64 = 2;
+const TIMERS: i64 = 3;
+
+/// Cycles run between checks for new requests while the program is running
+const SLICE: u32 = 4096;
+
+/// Largest request body accepted, far more than any real request needs
+const MAX_MESSAGE: usize = 1 << 20;
+
+/// Most instructions a disassemble request gets, enough for all of memory
+const MAX_INSTRUCTIONS: i64 = 4096 / 2;
+
+/// What the adapter is doing between requests
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Run {
+    Stopped,
+    Continue,
+    /// Running a CALL until it returns to return_pc with the stack back at sp
+    StepOver {
+        sp: u8,
+        return_pc: u16,
+    },
+    /// Running until the current subroutine returns below sp
+    StepOut {
+        sp: u8,
+    },
+}
+
+/// Debug Adapter Protocol server for a single CHIP-8 program
+/// Requests are passed to `handle` and responses and events are written to out.
+pub struct DapServer<W: Write> {
+    out: W,
+    seq: i64,
+    cpu: CPU,
+    symbols: Symbols,
+    breakpoints: BTreeSet<u16>,
+    run: Run,
+    stop_on_entry: bool,
+    /// Timers tick every cycles_per_frame instructions, 10 by default as in the window
+    cycles_per_frame: u32,
+}
+
+impl<W: Write> DapServer<W> {
+    pub fn new(out: W) -> DapServer<W> {
+        let mut cpu = CPU::new();
+        cpu.reset();
+        cpu.seed(rand::random());
+        DapServer {
+            out,
+            seq: 0,
+            cpu,
+            symbols: Symbols::new(),
+            breakpoints: BTreeSet::new(),
+            run: Run::Stopped,
+            stop_on_entry: false,
+            cycles_per_frame: 10,
+        }
+    }
+
+    /// True while the program runs and `run_slice` should be called
+    pub fn running(&self) -> bool {
+        self.run != Run::Stopped
+    }
+
+    /// Handles one request, returning false once the client disconnects
+    pub fn handle(&mut self, request: &Value) -> io::Result<bool> {
+        let command = request["command"].as_str().unwrap_or_default();
+        let args = &request["arguments"];
+        let result = match command {
+            "initialize" => Ok(json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsReadMemoryRequest": true,
+                "supportsDisassembleRequest": true,
+            })),
+            "launch" => self.launch(args),
+            "configurationDone" => Ok(Value::Null),
+            "setBreakpoints" => Ok(self.set_breakpoints(args)),
+            "setExceptionBreakpoints" => Ok(json!({ "breakpoints": [] })),
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "CHIP-8" }] })),
+            "stackTrace" => Ok(self.stack_trace()),
+            "scopes" => Ok(json!({ "scopes": [
+                { "name": "Registers", "variablesReference": REGISTERS, "expensive": false },
+                { "name": "Stack", "variablesReference": STACK, "expensive": false },
+                { "name": "Timers", "variablesReference": TIMERS, "expensive": false },
+            ]})),
+            "variables" => Ok(self.variables(args["variablesReference"].as_i64().unwrap_or(0))),
+            "readMemory" => self.read_memory(args),
+            "disassemble" => self.disassemble(args),
+            "continue" => {
+                self.run = Run::Continue;
+                Ok(json!({ "allThreadsContinued": true }))
+            }
+            "next" => {
+                self.next();
+                Ok(Value::Null)
+            }
+            "stepIn" => {
+                self.run = Run::Stopped;
+                Ok(Value::Null)
+            }
+            "stepOut" => {
+                self.run = Run::StepOut { sp: self.cpu.sp() };
+                Ok(Value::Null)
+            }
+            "pause" => {
+                self.run = Run::Stopped;
+                Ok(Value::Null)
+            }
+            "disconnect" | "terminate" => Ok(Value::Null),
+            _ => Err(format!("unsupported request '{}'", command)),
+        };
+        self.respond(request, result)?;
+
+        match command {
+            "initialize" => self.event("initialized", Value::Null)?,
+            "configurationDone" if self.stop_on_entry => self.stopped("entry")?,
+            "configurationDone" => self.run = Run::Continue,
+            "stepIn" => self.step_and_stop()?,
+            "next" if !self.running() => self.step_and_stop()?,
+            "pause" => self.stopped("pause")?,
+            "disconnect" | "terminate" => {
+                self.event("terminated", Value::Null)?;
+                return Ok(false);
+            }
+            _ => (),
+        }
+        Ok(true)
+    }
+
+    /// Runs the program for a while, sending a stopped event if it reaches a breakpoint
+    /// or finishes stepping
+    pub fn run_slice(&mut self) -> io::Result<()> {
+        for _ in 0..SLICE {
+            if !self.running() {
+                break;
+            }
+            if let Err(error) = self.step() {
+                self.run = Run::Stopped;
+                return self.fault(error);
+            }
+            let reason = match self.run {
+                Run::StepOver { sp, return_pc }
+                    if self.cpu.sp() == sp && self.cpu.pc() == return_pc =>
+                {
+                    Some("step")
+                }
+                Run::StepOut { sp } if self.cpu.sp() < sp => Some("step"),
+                _ if self.breakpoints.contains(&self.cpu.pc()) => Some("breakpoint"),
+                _ => None,
+            };
+            if let Some(reason) = reason {
+                self.run = Run::Stopped;
+                self.stopped(reason)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn launch(&mut self, args: &Value) -> Result<Value, String> {
+        let program = args["program"]
+            .as_str()
+            .ok_or("launch needs a 'program' path")?;
+        let rom = std::fs::read(program).map_err(|e| format!("{}: {}", program, e))?;
+        if let Some(path) = args["symbols"].as_str() {
+            self.symbols = Symbols::load(path).map_err(|e| format!("{}: {}", path, e))?;
+        }
+        if let Some(cycles) = args["cyclesPerFrame"].as_u64() {
+            self.cycles_per_frame = cycles.max(1) as u32;
+        }
+        self.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+        self.cpu.reset();
+        self.cpu
+            .load_rom(&rom)
+            .map_err(|e| format!("{}: {}", program, e))?;
+        Ok(Value::Null)
+    }
+
+    fn set_breakpoints(&mut self, args: &Value) -> Value {
+        let path = args["source"]["path"].as_str().unwrap_or_default();
+
+        // Breakpoints are replaced per source file
+        let symbols = &self.symbols;
+        self.breakpoints
+            .retain(|&address| match symbols.location(address) {
+                Some(location) => !location.in_file(path),
+                None => true,
+            });
+
+        let requested = args["breakpoints"].as_array().cloned().unwrap_or_default();
+        let breakpoints: Vec<Value> = requested
+            .iter()
+            .map(|breakpoint| {
+                let line = breakpoint["line"].as_u64().unwrap_or(0) as u32;
+                match self.symbols.address(path, line) {
+                    Some((address, actual_line)) => {
+                        self.breakpoints.insert(address);
+                        json!({
+                            "verified": true,
+                            "line": actual_line,
+                            "instructionReference": format!("0x{:03X}", address),
+                        })
+                    }
+                    None => json!({
+                        "verified": false,
+                        "line": line,
+                        "message": "no instruction on or after this line",
+                    }),
+                }
+            })
+            .collect();
+        json!({ "breakpoints": breakpoints })
+    }
+
+    /// The current instruction followed by the return address of every active call
+    fn stack_trace(&self) -> Value {
+        let sp = self.cpu.sp() as usize;
+        let mut addresses = vec![self.cpu.pc()];
+        addresses.extend(self.cpu.stack()[..sp.min(16)].iter().rev());
+        let frames: Vec<Value> = addresses
+            .iter()
+            .enumerate()
+            .map(|(id, &address)| {
+                let mut frame = json!({
+                    "id": id,
+                    "name": format!("0x{:03X}", address),
+                    "line": 0,
+                    "column": 0,
+                    "instructionPointerReference": format!("0x{:03X}", address),
+                });
+                if let Some(location) = self.symbols.location(address) {
+                    frame["source"] = json!({ "path": location.file });
+                    frame["line"] = json!(location.line);
+                }
+                frame
+            })
+            .collect();
+        json!({ "stackFrames": frames, "totalFrames": addresses.len() })
+    }
+
+    fn variables(&self, reference: i64) -> Value {
+        fn variable(name: String, value: String) -> Value {
+            json!({ "name": name, "value": value, "variablesReference": 0 })
+        }
+        let variables: Vec<Value> = match reference {
+            REGISTERS => {
+                let mut registers: Vec<Value> = self
+                    .cpu
+                    .v()
+                    .iter()
+                    .enumerate()
+                    .map(|(x, &value)| variable(format!("V{:X}", x), format!("0x{:02X}", value)))
+                    .collect();
+                let mut i = variable("I".to_string(), format!("0x{:03X}", self.cpu.i()));
+                i["memoryReference"] = json!(format!("0x{:03X}", self.cpu.i()));
+                let mut pc = variable("PC".to_string(), format!("0x{:03X}", self.cpu.pc()));
+                pc["memoryReference"] = json!(format!("0x{:03X}", self.cpu.pc()));
+                registers.push(i);
+                registers.push(pc);
+                registers.push(variable("SP".to_string(), self.cpu.sp().to_string()));
+                registers
+            }
+            STACK => self.cpu.stack()[..(self.cpu.sp() as usize).min(16)]
+                .iter()
+                .enumerate()
+                .map(|(level, &address)| {
+                    variable(format!("[{}]", level), format!("0x{:03X}", address))
+                })
+                .collect(),
+            TIMERS => vec![
+                variable("DT".to_string(), self.cpu.dt().to_string()),
+                variable("ST".to_string(), self.cpu.st().to_string()),
+            ],
+            _ => Vec::new(),
+        };
+        json!({ "variables": variables })
+    }
+
+    fn read_memory(&self, args: &Value) -> Result<Value, String> {
+        // Offsets and counts come straight from the client, so nothing here may overflow
+        let start = (parse_address(&args["memoryReference"])? as i64)
+            .saturating_add(args["offset"].as_i64().unwrap_or(0));
+        let count = args["count"].as_i64().unwrap_or(0).max(0);
+        let end = start.saturating_add(count).clamp(0, 4096) as usize;
+        let start = start.clamp(0, 4096) as usize;
+        let data = &self.cpu.memory()[start..end.max(start)];
+        Ok(json!({
+            "address": format!("0x{:03X}", start),
+            "data": base64(data),
+            "unreadableBytes": count as usize - data.len(),
+        }))
+    }
+
+    fn disassemble(&self, args: &Value) -> Result<Value, String> {
+        let base = (parse_address(&args["memoryReference"])? as i64)
+            .saturating_add(args["offset"].as_i64().unwrap_or(0))
+            .saturating_add(
+                args["instructionOffset"]
+                    .as_i64()
+                    .unwrap_or(0)
+                    .saturating_mul(2),
+            );
+        let count = args["instructionCount"]
+            .as_i64()
+            .unwrap_or(0)
+            .clamp(0, MAX_INSTRUCTIONS);
+        let memory = self.cpu.memory();
+        let instructions: Vec<Value> = (0..count)
+            .map(|n| {
+                let address = base.saturating_add(n * 2);
+                if !(0..4095).contains(&address) {
+                    return json!({ "address": format!("0x{:X}", address), "instruction": "??" });
+                }
+                let address = address as usize;
+                let opcode = ((memory[address] as u16) << 8) | memory[address + 1] as u16;
+                let mut instruction = json!({
+                    "address": format!("0x{:03X}", address),
+                    "instructionBytes": format!("{:04X}", opcode),
+                    "instruction": Instruction::decode(opcode).to_string(),
+                });
+                if let Some(location) = self.symbols.location(address as u16) {
+                    instruction["location"] = json!({ "path": location.file });
+                    instruction["line"] = json!(location.line);
+                }
+                instruction
+            })
+            .collect();
+        Ok(json!({ "instructions": instructions }))
+    }
+
+    /// Steps over CALL instructions, single steps anything else
+    fn next(&mut self) {
+        let opcode = ((self.cpu.memory()[self.cpu.pc() as usize & 0xFFF] as u16) << 8)
+            | self.cpu.memory()[(self.cpu.pc() as usize + 1) & 0xFFF] as u16;
+        self.run = match Instruction::decode(opcode) {
+            Instruction::Call(_) => Run::StepOver {
+                sp: self.cpu.sp(),
+                return_pc: self.cpu.pc() + 2,
+            },
+            _ => Run::Stopped,
+        };
+    }
+
+    fn step(&mut self) -> Result<(), Error> {
+        self.cpu.execute_cycle()?;
+        if self
+            .cpu
+            .cycles()
+            .is_multiple_of(self.cycles_per_frame as u64)
+        {
+            self.cpu.decrement_timers();
+        }
+        Ok(())
+    }
+
+    /// Single steps, then reports where execution stopped
+    fn step_and_stop(&mut self) -> io::Result<()> {
+        match self.step() {
+            Ok(()) => self.stopped("step"),
+            Err(error) => self.fault(error),
+        }
+    }
+
+    /// Reports that the CPU stopped on an instruction it couldn't execute
+    fn fault(&mut self, error: Error) -> io::Result<()> {
+        self.event(
+            "stopped",
+            json!({
+                "reason": "exception",
+                "description": error.to_string(),
+                "threadId": THREAD_ID,
+                "allThreadsStopped": true,
+            }),
+        )
+    }
+
+    fn stopped(&mut self, reason: &str) -> io::Result<()> {
+        self.event(
+            "stopped",
+            json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true }),
+        )
+    }
+
+    fn respond(&mut self, request: &Value, result: Result<Value, String>) -> io::Result<()> {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(Value::Null) => (),
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        self.send(response)
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        let mut message = json!({ "type": "event", "event": event });
+        if !body.is_null() {
+            message["body"] = body;
+        }
+        self.send(message)
+    }
+
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        write_message(&mut self.out, &message)
+    }
+}
+
+/// Reads one Content-Length framed message, or None at the end of input
+pub fn read_message<R: BufRead>(input: &mut R) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+    let length = length
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length"))?;
+    if length > MAX_MESSAGE {
+        let message = format!("Content-Length {} is too long", length);
+        return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+    }
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Writes one Content-Length framed message
+pub fn write_message<W: Write>(out: &mut W, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(out, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    out.flush()
+}
+
+/// Serves requests from input until the client disconnects or input ends
+/// Requests are read on a separate thread so a running program can still be paused.
+pub fn serve<R, W>(mut input: R, output: W) -> io::Result<()>
+where
+    R: BufRead + Send + 'static,
+    W: Write,
+{
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        while let Ok(Some(message)) = read_message(&mut input) {
+            if sender.send(message).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut server = DapServer::new(output);
+    loop {
+        let request = if server.running() {
+            match receiver.try_recv() {
+                Ok(request) => Some(request),
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => return Ok(()),
+            }
+        } else {
+            match receiver.recv() {
+                Ok(request) => Some(request),
+                Err(_) => return Ok(()),
+            }
+        };
+        if let Some(request) = request {
+            if !server.handle(&request)? {
+                return Ok(());
+            }
+        }
+        server.run_slice()?;
+    }
+}
+
+/// Accepts "0x200", "200" (hex) or a plain JSON number
+fn parse_address(reference: &Value) -> Result<u16, String> {
+    if let Some(number) = reference.as_u64() {
+        return Ok(number as u16);
+    }
+    let text = reference.as_str().ok_or("missing memoryReference")?;
+    u16::from_str_radix(text.trim_start_matches("0x"), 16)
+        .map_err(|_| format!("invalid memoryReference '{}'", text))
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let triple = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(triple >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn request(
+        server: &mut DapServer<Vec<u8>>,
+        seq: i64,
+        command: &str,
+        arguments: Value,
+    ) -> Vec<Value> {
+        let request = json!({
+            "seq": seq,
+            "type": "request",
+            "command": command,
+            "arguments": arguments,
+        });
+        server.handle(&request).unwrap();
+        drain(server)
+    }
+
+    /// Takes every message written so far
+    fn drain(server: &mut DapServer<Vec<u8>>) -> Vec<Value> {
+        let mut input = Cursor::new(std::mem::take(&mut server.out));
+        let mut messages = Vec::new();
+        while let Some(message) = read_message(&mut input).unwrap() {
+            messages.push(message);
+        }
+        messages
+    }
+
+    #[test]
+    fn breakpoint_stack_and_variables() {
+        let dir = std::env::temp_dir().join(format!("chip8-dap-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let rom = dir.join("test.ch8");
+        let symbols = dir.join("test.sym");
+        // 200: LD V0, 5; 202: CALL 206; 204: JP 204; 206: LD V1, 7; 208: RET
+        std::fs::write(
+            &rom,
+            [0x60, 0x05, 0x22, 0x06, 0x12, 0x04, 0x61, 0x07, 0x00, 0xEE],
+        )
+        .unwrap();
+        std::fs::write(
+            &symbols,
+            "0x200 test.8o 1\n0x202 test.8o 2\n0x204 test.8o 3\n0x206 test.8o 6\n0x208 test.8o 7\n",
+        )
+        .unwrap();
+
+        let mut server = DapServer::new(Vec::new());
+        let messages = request(
+            &mut server,
+            1,
+            "initialize",
+            json!({ "adapterID": "chip8" }),
+        );
+        assert_eq!(messages[0]["body"]["supportsReadMemoryRequest"], true);
+        assert_eq!(messages[1]["event"], "initialized");
+
+        let launch = json!({ "program": rom, "symbols": symbols, "stopOnEntry": true });
+        assert_eq!(
+            request(&mut server, 2, "launch", launch)[0]["success"],
+            true
+        );
+
+        let breakpoints = request(
+            &mut server,
+            3,
+            "setBreakpoints",
+            json!({
+                "source": { "path": "/home/me/game/test.8o" },
+                "breakpoints": [{ "line": 5 }],
+            }),
+        );
+        assert_eq!(breakpoints[0]["body"]["breakpoints"][0]["verified"], true);
+        assert_eq!(breakpoints[0]["body"]["breakpoints"][0]["line"], 6);
+
+        let entry = request(&mut server, 4, "configurationDone", Value::Null);
+        assert_eq!(entry[1]["body"]["reason"], "entry");
+
+        request(&mut server, 5, "continue", json!({ "threadId": 1 }));
+        while server.running() {
+            server.run_slice().unwrap();
+        }
+        assert_eq!(drain(&mut server)[0]["body"]["reason"], "breakpoint");
+
+        let trace = request(&mut server, 6, "stackTrace", json!({ "threadId": 1 }));
+        let frames = &trace[0]["body"]["stackFrames"];
+        assert_eq!(frames[0]["line"], 6);
+        assert_eq!(frames[1]["line"], 3);
+
+        let registers = request(
+            &mut server,
+            7,
+            "variables",
+            json!({ "variablesReference": REGISTERS }),
+        );
+        assert_eq!(registers[0]["body"]["variables"][0]["value"], "0x05");
+
+        let memory = request(
+            &mut server,
+            8,
+            "readMemory",
+            json!({ "memoryReference": "0x200", "count": 4 }),
+        );
+        assert_eq!(memory[0]["body"]["data"], "YAUiBg==");
+        let beyond = request(
+            &mut server,
+            8,
+            "readMemory",
+            json!({ "memoryReference": "0xFFF", "offset": i64::MAX, "count": i64::MAX }),
+        );
+        assert_eq!(beyond[0]["body"]["data"], "");
+        let listing = request(
+            &mut server,
+            8,
+            "disassemble",
+            json!({ "memoryReference": "0x200", "instructionOffset": i64::MIN, "instructionCount": i64::MAX }),
+        );
+        let instructions = listing[0]["body"]["instructions"].as_array().unwrap();
+        assert_eq!(instructions.len() as i64, MAX_INSTRUCTIONS);
+
+        request(&mut server, 9, "stepOut", json!({ "threadId": 1 }));
+        while server.running() {
+            server.run_slice().unwrap();
+        }
+        drain(&mut server);
+        assert_eq!(server.cpu.pc(), 0x204);
+
+        let disconnect = json!({ "seq": 10, "type": "request", "command": "disconnect" });
+        assert!(!server.handle(&disconnect).unwrap());
+        std::fs::remove_dir_all(dir).unwrap();
+
+        let huge = format!("Content-Length: {}\r\n\r\n{{}}", usize::MAX);
+        assert!(read_message(&mut Cursor::new(huge)).is_err());
+    }
+}
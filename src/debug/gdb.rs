@@ -0,0 +1,563 @@
+// Protocol reference: https://sourceware.org/gdb/current/onlinedocs/gdb.html/Remote-Protocol.html
+
+use crate::cpu::{Error, CPU};
+use std::collections::{BTreeSet, VecDeque};
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+
+/// Register layout reported to the debugger
+/// Numbers: V0-VF are 0-15, then I 16, PC 17, SP 18, DT 19, ST 20
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.chip8.core">
+    <reg name="v0" bitsize="8" type="uint8" regnum="0"/>
+    <reg name="v1" bitsize="8" type="uint8"/>
+    <reg name="v2" bitsize="8" type="uint8"/>
+    <reg name="v3" bitsize="8" type="uint8"/>
+    <reg name="v4" bitsize="8" type="uint8"/>
+    <reg name="v5" bitsize="8" type="uint8"/>
+    <reg name="v6" bitsize="8" type="uint8"/>
+    <reg name="v7" bitsize="8" type="uint8"/>
+    <reg name="v8" bitsize="8" type="uint8"/>
+    <reg name="v9" bitsize="8" type="uint8"/>
+    <reg name="va" bitsize="8" type="uint8"/>
+    <reg name="vb" bitsize="8" type="uint8"/>
+    <reg name="vc" bitsize="8" type="uint8"/>
+    <reg name="vd" bitsize="8" type="uint8"/>
+    <reg name="ve" bitsize="8" type="uint8"/>
+    <reg name="vf" bitsize="8" type="uint8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="8" type="uint8"/>
+    <reg name="dt" bitsize="8" type="uint8"/>
+    <reg name="st" bitsize="8" type="uint8"/>
+  </feature>
+</target>
+"#;
+
+const REGISTER_COUNT: usize = 21;
+
+/// Sent by the debugger (outside of a packet) to interrupt a running target
+const INTERRUPT: u8 = 0x03;
+
+/// Cycles run between checks for an interrupt while continuing
+const POLL_INTERVAL: u32 = 1024;
+
+/// Why the target stopped running
+enum Stop {
+    Step,
+    Breakpoint,
+    Interrupt,
+    /// The CPU couldn't execute the next instruction
+    Fault,
+}
+
+impl Stop {
+    fn reply(&self) -> String {
+        match self {
+            Stop::Step => "S05".to_string(),
+            Stop::Breakpoint => "T05swbreak:;".to_string(),
+            Stop::Interrupt => "S02".to_string(),
+            Stop::Fault => "S0b".to_string(),
+        }
+    }
+}
+
+/// What to do after handling a packet
+enum Action {
+    Reply(String),
+    ReplyAndClose(String),
+    Close,
+}
+
+/// Packet framing over a TCP stream
+struct Connection {
+    stream: TcpStream,
+    /// Bytes read while polling for an interrupt that belong to the next packet
+    pending: VecDeque<u8>,
+    no_ack: bool,
+}
+
+impl Connection {
+    fn new(stream: TcpStream) -> Connection {
+        Connection {
+            stream,
+            pending: VecDeque::new(),
+            no_ack: false,
+        }
+    }
+
+    fn read_byte(&mut self) -> io::Result<u8> {
+        if let Some(byte) = self.pending.pop_front() {
+            return Ok(byte);
+        }
+        let mut byte = [0u8];
+        self.stream.read_exact(&mut byte)?;
+        Ok(byte[0])
+    }
+
+    /// Returns the next packet's contents, or None once the debugger disconnects
+    fn read_packet(&mut self) -> io::Result<Option<String>> {
+        loop {
+            // Skip acks and interrupts received while the target was already stopped
+            match self.read_byte() {
+                Ok(b'$') => (),
+                Ok(_) => continue,
+                Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+                Err(e) => return Err(e),
+            }
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    b'#' => break,
+                    byte => data.push(byte),
+                }
+            }
+            let checksum = [self.read_byte()?, self.read_byte()?];
+            let expected = std::str::from_utf8(&checksum)
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+            if !self.no_ack {
+                let ok = expected == Some(checksum_of(&data));
+                self.stream.write_all(if ok { b"+" } else { b"-" })?;
+                if !ok {
+                    continue;
+                }
+            }
+            return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+        }
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let packet = format!("${}#{:02x}", data, checksum_of(data.as_bytes()));
+        loop {
+            self.stream.write_all(packet.as_bytes())?;
+            self.stream.flush()?;
+            if self.no_ack || self.read_byte()? != b'-' {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Checks, without blocking, whether the debugger sent an interrupt
+    fn poll_interrupt(&mut self) -> io::Result<bool> {
+        let mut buffer = [0u8; 64];
+        self.stream.set_nonblocking(true)?;
+        let read = self.stream.read(&mut buffer);
+        self.stream.set_nonblocking(false)?;
+        match read {
+            Ok(n) => self.pending.extend(&buffer[..n]),
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => (),
+            Err(e) => return Err(e),
+        }
+        match self.pending.iter().position(|&b| b == INTERRUPT) {
+            Some(index) => {
+                self.pending.remove(index);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+/// GDB remote serial protocol server driving a CPU
+/// The CPU runs headless: the delay and sound timers tick every cycles_per_frame instructions.
+pub struct GdbStub {
+    pub cpu: CPU,
+    pub cycles_per_frame: u32,
+    breakpoints: BTreeSet<u16>,
+}
+
+impl GdbStub {
+    /// Returns a stub for the given CPU, ticking the timers every 10 instructions, as often as
+    /// the window loop does at its default 600 instructions a second
+    pub fn new(cpu: CPU) -> GdbStub {
+        GdbStub {
+            cpu,
+            cycles_per_frame: 10,
+            breakpoints: BTreeSet::new(),
+        }
+    }
+
+    /// Waits for a debugger to connect on addr (e.g. "127.0.0.1:1234") and serves it
+    pub fn listen<A: ToSocketAddrs>(&mut self, addr: A) -> io::Result<()> {
+        let listener = TcpListener::bind(addr)?;
+        let (stream, _) = listener.accept()?;
+        self.serve(stream)
+    }
+
+    /// Serves one debugger session until it detaches, kills the target or disconnects
+    pub fn serve(&mut self, stream: TcpStream) -> io::Result<()> {
+        stream.set_nodelay(true)?;
+        let mut connection = Connection::new(stream);
+        while let Some(packet) = connection.read_packet()? {
+            match self.handle(&packet, &mut connection)? {
+                Action::Reply(reply) => {
+                    connection.send(&reply)?;
+                    if packet == "QStartNoAckMode" {
+                        connection.no_ack = true;
+                    }
+                }
+                Action::ReplyAndClose(reply) => {
+                    connection.send(&reply)?;
+                    break;
+                }
+                Action::Close => break,
+            }
+        }
+        Ok(())
+    }
+
+    fn handle(&mut self, packet: &str, connection: &mut Connection) -> io::Result<Action> {
+        // Commands are one ASCII character, so anything else is an unsupported packet
+        let (command, args) = match packet.as_bytes().first() {
+            Some(byte) if byte.is_ascii() => packet.split_at(1),
+            Some(_) => return Ok(Action::Reply(String::new())),
+            None => ("", ""),
+        };
+        let reply = match command {
+            "?" => Stop::Step.reply(),
+            "q" => self.query(args),
+            "Q" if args == "StartNoAckMode" => "OK".to_string(),
+            "H" | "T" => "OK".to_string(),
+            "g" => (0..REGISTER_COUNT)
+                .map(|n| to_hex(&self.register(n)))
+                .collect(),
+            "G" => self.write_registers(args),
+            "p" => match usize::from_str_radix(args, 16) {
+                Ok(n) if n < REGISTER_COUNT => to_hex(&self.register(n)),
+                _ => "E01".to_string(),
+            },
+            "P" => self.write_register(args),
+            "m" => self.read_memory(args),
+            "M" => self.write_memory(args),
+            "Z" | "z" => self.breakpoint(command == "Z", args),
+            "s" => {
+                self.jump(args);
+                match self.step() {
+                    Ok(()) => Stop::Step.reply(),
+                    Err(_) => Stop::Fault.reply(),
+                }
+            }
+            "c" => {
+                self.jump(args);
+                self.resume(connection)?.reply()
+            }
+            "D" => return Ok(Action::ReplyAndClose("OK".to_string())),
+            "k" => return Ok(Action::Close),
+            _ => String::new(),
+        };
+        Ok(Action::Reply(reply))
+    }
+
+    fn query(&self, args: &str) -> String {
+        if args.starts_with("Supported") {
+            return "PacketSize=4000;qXfer:features:read+;QStartNoAckMode+;swbreak+".to_string();
+        }
+        if let Some(range) = args.strip_prefix("Xfer:features:read:target.xml:") {
+            let (offset, length) = match parse_pair(range, ',') {
+                Some(pair) => pair,
+                None => return "E01".to_string(),
+            };
+            let xml = TARGET_XML.as_bytes();
+            let start = offset.min(xml.len());
+            let end = match start.checked_add(length) {
+                Some(end) => end.min(xml.len()),
+                None => return "E01".to_string(),
+            };
+            let chunk = String::from_utf8_lossy(&xml[start..end]);
+            return format!("{}{}", if end == xml.len() { 'l' } else { 'm' }, chunk);
+        }
+        match args {
+            "Attached" => "1".to_string(),
+            "C" => "QC1".to_string(),
+            "fThreadInfo" => "m1".to_string(),
+            "sThreadInfo" => "l".to_string(),
+            _ => String::new(),
+        }
+    }
+
+    /// Register contents in target (little endian) byte order
+    fn register(&self, n: usize) -> Vec<u8> {
+        match n {
+            0..=15 => vec![self.cpu.v()[n]],
+            16 => self.cpu.i().to_le_bytes().to_vec(),
+            17 => self.cpu.pc().to_le_bytes().to_vec(),
+            18 => vec![self.cpu.sp()],
+            19 => vec![self.cpu.dt() as u8],
+            _ => vec![self.cpu.st() as u8],
+        }
+    }
+
+    /// Returns false for an unknown register, a value of the wrong size or an SP past the stack
+    fn set_register(&mut self, n: usize, bytes: &[u8]) -> bool {
+        let word = || u16::from_le_bytes([bytes[0], bytes[1]]);
+        match (n, bytes.len()) {
+            (0..=15, 1) => self.cpu.set_v(n, bytes[0]),
+            (16, 2) => self.cpu.set_i(word()),
+            (17, 2) => self.cpu.set_pc(word()),
+            (18, 1) => return self.cpu.set_sp(bytes[0]).is_ok(),
+            (19, 1) => self.cpu.set_dt(bytes[0] as u16),
+            (20, 1) => self.cpu.set_st(bytes[0] as u16),
+            _ => return false,
+        }
+        true
+    }
+
+    fn write_registers(&mut self, args: &str) -> String {
+        let bytes = match from_hex(args) {
+            Some(bytes) => bytes,
+            None => return "E01".to_string(),
+        };
+        let mut values = Vec::with_capacity(REGISTER_COUNT);
+        let mut offset = 0;
+        for n in 0..REGISTER_COUNT {
+            let size = self.register(n).len();
+            if offset + size > bytes.len() {
+                return "E01".to_string();
+            }
+            values.push(&bytes[offset..offset + size]);
+            offset += size;
+        }
+        // SP is the only register with values it can't take, check it before writing any
+        if values[18][0] as usize > self.cpu.stack().len() {
+            return "E01".to_string();
+        }
+        for (n, value) in values.into_iter().enumerate() {
+            self.set_register(n, value);
+        }
+        "OK".to_string()
+    }
+
+    fn write_register(&mut self, args: &str) -> String {
+        let mut parts = args.splitn(2, '=');
+        let n = parts.next().and_then(|n| usize::from_str_radix(n, 16).ok());
+        let bytes = parts.next().and_then(from_hex);
+        match (n, bytes) {
+            (Some(n), Some(bytes)) if self.set_register(n, &bytes) => "OK".to_string(),
+            _ => "E01".to_string(),
+        }
+    }
+
+    fn read_memory(&self, args: &str) -> String {
+        let range = parse_pair(args, ',')
+            .and_then(|(address, length)| Some((address, address.checked_add(length)?)));
+        match range {
+            Some((address, end)) if address < 4096 => {
+                to_hex(&self.cpu.memory()[address..end.min(4096)])
+            }
+            _ => "E01".to_string(),
+        }
+    }
+
+    fn write_memory(&mut self, args: &str) -> String {
+        let mut parts = args.splitn(2, ':');
+        let range = parts.next().and_then(|range| parse_pair(range, ','));
+        let bytes = parts.next().and_then(from_hex);
+        match (range, bytes) {
+            (Some((address, length)), Some(bytes))
+                if bytes.len() == length
+                    && address.checked_add(length).is_some_and(|end| end <= 4096) =>
+            {
+                for (offset, &byte) in bytes.iter().enumerate() {
+                    self.cpu.poke((address + offset) as u16, byte);
+                }
+                "OK".to_string()
+            }
+            _ => "E01".to_string(),
+        }
+    }
+
+    /// Handles Z (insert) and z (remove) packets for software and hardware breakpoints
+    fn breakpoint(&mut self, insert: bool, args: &str) -> String {
+        let mut parts = args.split(',');
+        let kind = parts.next();
+        let address = parts.next().and_then(|a| u16::from_str_radix(a, 16).ok());
+        match (kind, address) {
+            (Some("0"), Some(address)) | (Some("1"), Some(address)) => {
+                if insert {
+                    self.breakpoints.insert(address);
+                } else {
+                    self.breakpoints.remove(&address);
+                }
+                "OK".to_string()
+            }
+            // Watchpoints are not supported
+            _ => String::new(),
+        }
+    }
+
+    /// Moves the PC to the optional address argument of s and c packets
+    fn jump(&mut self, args: &str) {
+        if let Ok(address) = u16::from_str_radix(args, 16) {
+            self.cpu.set_pc(address);
+        }
+    }
+
+    fn step(&mut self) -> Result<(), Error> {
+        self.cpu.execute_cycle()?;
+        if self
+            .cpu
+            .cycles()
+            .is_multiple_of(self.cycles_per_frame.max(1) as u64)
+        {
+            self.cpu.decrement_timers();
+        }
+        Ok(())
+    }
+
+    /// Runs until a breakpoint is reached or the debugger interrupts
+    fn resume(&mut self, connection: &mut Connection) -> io::Result<Stop> {
+        let mut since_poll = 0;
+        loop {
+            if self.step().is_err() {
+                return Ok(Stop::Fault);
+            }
+            if self.breakpoints.contains(&self.cpu.pc()) {
+                return Ok(Stop::Breakpoint);
+            }
+            since_poll += 1;
+            if since_poll == POLL_INTERVAL {
+                since_poll = 0;
+                if connection.poll_interrupt()? {
+                    return Ok(Stop::Interrupt);
+                }
+            }
+        }
+    }
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Parses "a<separator>b" where both are hex numbers
+fn parse_pair(text: &str, separator: char) -> Option<(usize, usize)> {
+    let mut parts = text.splitn(2, separator);
+    let a = usize::from_str_radix(parts.next()?, 16).ok()?;
+    let b = usize::from_str_radix(parts.next()?, 16).ok()?;
+    Some((a, b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    /// Minimal debugger side of the protocol
+    struct Client {
+        stream: TcpStream,
+    }
+
+    impl Client {
+        fn request(&mut self, data: &str) -> String {
+            let packet = format!("${}#{:02x}", data, checksum_of(data.as_bytes()));
+            self.stream.write_all(packet.as_bytes()).unwrap();
+            assert_eq!(self.byte(), b'+');
+            assert_eq!(self.byte(), b'$');
+            let mut reply = Vec::new();
+            loop {
+                match self.byte() {
+                    b'#' => break,
+                    byte => reply.push(byte),
+                }
+            }
+            self.byte();
+            self.byte();
+            self.stream.write_all(b"+").unwrap();
+            String::from_utf8(reply).unwrap()
+        }
+
+        fn byte(&mut self) -> u8 {
+            let mut byte = [0u8];
+            self.stream.read_exact(&mut byte).unwrap();
+            byte[0]
+        }
+    }
+
+    /// Starts a stub for rom on a free local port and connects to it
+    fn connect(rom: Vec<u8>) -> (Client, thread::JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let mut cpu = CPU::new();
+            cpu.reset();
+            cpu.load_rom(&rom).unwrap();
+            let mut stub = GdbStub::new(cpu);
+            let (stream, _) = listener.accept().unwrap();
+            stub.serve(stream).unwrap();
+        });
+        let stream = TcpStream::connect(address).unwrap();
+        (Client { stream }, server)
+    }
+
+    #[test]
+    fn breakpoints_registers_and_memory() {
+        // 200: LD V0, 5; 202: LD V1, 7; 204: JP 202
+        let (mut client, server) = connect(vec![0x60, 0x05, 0x61, 0x07, 0x12, 0x02]);
+
+        assert!(client
+            .request("qSupported:swbreak+")
+            .contains("qXfer:features:read+"));
+        assert!(client
+            .request("qXfer:features:read:target.xml:0,fff")
+            .starts_with("l<?xml"));
+        assert_eq!(client.request("?"), "S05");
+        assert_eq!(client.request("Z0,204,2"), "OK");
+        assert_eq!(client.request("c"), "T05swbreak:;");
+        assert_eq!(client.request("p11"), "0402");
+        assert_eq!(client.request("p0"), "05");
+        assert_eq!(client.request("m200,4"), "60056107");
+        assert_eq!(client.request("M300,2:abcd"), "OK");
+        assert_eq!(client.request("m300,2"), "abcd");
+        assert_eq!(client.request("m2,ffffffffffffffff"), "E01");
+        assert_eq!(client.request("M2,ffffffffffffffff:00"), "E01");
+        assert_eq!(client.request("\u{e9}"), "");
+        assert_eq!(client.request("P1=2a"), "OK");
+        let registers = client.request("g");
+        assert_eq!(&registers[..4], "052a");
+        // SP 17 is past the stack, whether written alone or with the rest
+        assert_eq!(client.request("P12=11"), "E01");
+        let bad = format!("G{}11{}", &registers[..40], &registers[42..]);
+        assert_eq!(client.request(&bad), "E01");
+        assert_eq!(client.request("p12"), "00");
+        assert_eq!(client.request("s"), "S05");
+        assert_eq!(client.request("p11"), "0202");
+        assert_eq!(client.request("D"), "OK");
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn interrupt_stops_a_running_target() {
+        // 200: JP 200
+        let (mut client, server) = connect(vec![0x12, 0x00]);
+
+        let packet = format!("$c#{:02x}", checksum_of(b"c"));
+        client.stream.write_all(packet.as_bytes()).unwrap();
+        assert_eq!(client.byte(), b'+');
+        client.stream.write_all(&[INTERRUPT]).unwrap();
+        let mut reply = [0u8; 7];
+        client.stream.read_exact(&mut reply).unwrap();
+        assert_eq!(&reply, b"$S02#b5");
+        client.stream.write_all(b"+").unwrap();
+
+        assert_eq!(client.request("p11"), "0002");
+        drop(client);
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,8 @@
+// Debugging tools that observe the CPU through `cpu::hook::Hook`
+
+pub mod coverage;
+pub mod dap;
+pub mod gdb;
+pub mod profile;
+pub mod symbols;
+pub mod trace;
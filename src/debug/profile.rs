@@ -0,0 +1,340 @@
+use crate::cpu::hook::Hook;
+use crate::cpu::instruction::Instruction;
+use crate::cpu::{Error, CPU};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+
+/// Name of the outermost stack frame in folded stack output
+const ROOT: &str = "main";
+
+/// Totals for one subroutine, keyed by its entry address
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Subroutine {
+    /// Number of completed calls (2nnn matched by a 00EE)
+    pub calls: u64,
+
+    /// Cycles spent between the call and its return, including nested calls
+    pub total_cycles: u64,
+
+    /// Cycles spent in the subroutine itself, excluding nested calls
+    pub self_cycles: u64,
+}
+
+/// A subroutine call that has not returned yet
+struct Frame {
+    address: u16,
+    entered: u64,
+    child_cycles: u64,
+}
+
+/// Counts how often each address and opcode family executes and how long subroutines take
+/// Time is measured in cycles (instructions executed), so results do not depend on the host.
+pub struct Profiler {
+    pc_counts: Vec<u64>,
+    family_counts: BTreeMap<&'static str, u64>,
+    subroutines: BTreeMap<u16, Subroutine>,
+    calls: Vec<Frame>,
+
+    /// Instructions executed per call stack, for flamegraph tools
+    folded: HashMap<String, u64>,
+    /// Folded representation of `calls`, kept up to date on every call and return
+    stack_key: String,
+
+    /// Address, cycle count and instruction of the current instruction, recorded once it has
+    /// run so failed instructions don't count
+    pending: Option<(u16, u64, Instruction)>,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Profiler {
+    /// Returns a profiler with every count at zero
+    pub fn new() -> Profiler {
+        Profiler {
+            pc_counts: vec![0; 4096],
+            family_counts: BTreeMap::new(),
+            subroutines: BTreeMap::new(),
+            calls: Vec::new(),
+            folded: HashMap::new(),
+            stack_key: ROOT.to_string(),
+            pending: None,
+        }
+    }
+
+    /// Number of times the instruction at address was executed
+    pub fn pc_count(&self, address: u16) -> u64 {
+        self.pc_counts[address as usize & 0xFFF]
+    }
+
+    /// Number of times each opcode family (e.g. "8xy4") was executed
+    pub fn family_counts(&self) -> &BTreeMap<&'static str, u64> {
+        &self.family_counts
+    }
+
+    /// Totals for every subroutine that returned at least once
+    pub fn subroutines(&self) -> &BTreeMap<u16, Subroutine> {
+        &self.subroutines
+    }
+
+    /// Addresses sorted by execution count, most executed first
+    pub fn hot_spots(&self) -> Vec<(u16, u64)> {
+        let mut spots: Vec<(u16, u64)> = self
+            .pc_counts
+            .iter()
+            .enumerate()
+            .filter(|(_, &count)| count > 0)
+            .map(|(address, &count)| (address as u16, count))
+            .collect();
+        spots.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        spots
+    }
+
+    /// Writes a human readable report listing the top hot spots, families and subroutines
+    pub fn write_report(&self, out: &mut dyn Write, cpu: &CPU, top: usize) -> io::Result<()> {
+        let total: u64 = self.family_counts.values().sum();
+        writeln!(out, "Instructions executed: {}", total)?;
+
+        writeln!(out, "\nHot spots:")?;
+        writeln!(out, "{:>6} {:>12} {:>7}  instruction", "addr", "count", "%")?;
+        for &(address, count) in self.hot_spots().iter().take(top) {
+            let opcode = fetch(cpu, address);
+            writeln!(
+                out,
+                "{:>6} {:>12} {:>6.2}%  {}",
+                format!("{:03X}", address),
+                count,
+                percent(count, total),
+                Instruction::decode(opcode),
+            )?;
+        }
+
+        writeln!(out, "\nOpcode families:")?;
+        let mut families: Vec<(&&str, &u64)> = self.family_counts.iter().collect();
+        families.sort_by_key(|&(_, &count)| Reverse(count));
+        for (family, &count) in families {
+            writeln!(
+                out,
+                "{:>6} {:>12} {:>6.2}%",
+                family,
+                count,
+                percent(count, total)
+            )?;
+        }
+
+        writeln!(out, "\nSubroutines:")?;
+        writeln!(
+            out,
+            "{:>6} {:>8} {:>12} {:>12} {:>10}",
+            "addr", "calls", "total", "self", "avg"
+        )?;
+        let mut subroutines: Vec<(&u16, &Subroutine)> = self.subroutines.iter().collect();
+        subroutines.sort_by_key(|&(_, sub)| Reverse(sub.total_cycles));
+        for (address, sub) in subroutines {
+            writeln!(
+                out,
+                "{:>6} {:>8} {:>12} {:>12} {:>10.1}",
+                format!("{:03X}", address),
+                sub.calls,
+                sub.total_cycles,
+                sub.self_cycles,
+                sub.total_cycles as f64 / sub.calls as f64,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Writes the same data as `write_report` as a single JSON object
+    pub fn write_json(&self, out: &mut dyn Write) -> io::Result<()> {
+        write!(out, "{{\"pc\":{{")?;
+        let mut first = true;
+        for (address, &count) in self.pc_counts.iter().enumerate() {
+            if count > 0 {
+                write!(out, "{}\"0x{:03X}\":{}", comma(&mut first), address, count)?;
+            }
+        }
+        write!(out, "}},\"families\":{{")?;
+        let mut first = true;
+        for (family, count) in &self.family_counts {
+            write!(out, "{}\"{}\":{}", comma(&mut first), family, count)?;
+        }
+        write!(out, "}},\"subroutines\":{{")?;
+        let mut first = true;
+        for (address, sub) in &self.subroutines {
+            write!(
+                out,
+                "{}\"0x{:03X}\":{{\"calls\":{},\"total_cycles\":{},\"self_cycles\":{}}}",
+                comma(&mut first),
+                address,
+                sub.calls,
+                sub.total_cycles,
+                sub.self_cycles
+            )?;
+        }
+        writeln!(out, "}}}}")
+    }
+
+    /// Writes one "main;0x300;0x350 count" line per call stack seen,
+    /// the folded format read by flamegraph.pl and inferno
+    pub fn write_folded(&self, out: &mut dyn Write) -> io::Result<()> {
+        let mut stacks: Vec<(&String, &u64)> = self.folded.iter().collect();
+        stacks.sort();
+        for (stack, count) in stacks {
+            writeln!(out, "{} {}", stack, count)?;
+        }
+        Ok(())
+    }
+
+    fn update_stack_key(&mut self) {
+        self.stack_key = ROOT.to_string();
+        for frame in &self.calls {
+            self.stack_key
+                .push_str(&format!(";0x{:03X}", frame.address));
+        }
+    }
+}
+
+impl Hook for Profiler {
+    fn before_instruction(&mut self, cpu: &CPU, opcode: u16) {
+        self.pending = Some((cpu.pc(), cpu.cycles(), Instruction::decode(opcode)));
+    }
+
+    fn after_instruction(&mut self, _cpu: &CPU, _opcode: u16) {
+        let (pc, cycles, instruction) = match self.pending.take() {
+            Some(pending) => pending,
+            None => return,
+        };
+        self.pc_counts[pc as usize & 0xFFF] += 1;
+        *self.family_counts.entry(instruction.family()).or_insert(0) += 1;
+        *self.folded.entry(self.stack_key.clone()).or_insert(0) += 1;
+
+        match instruction {
+            Instruction::Call(address) => {
+                self.calls.push(Frame {
+                    address,
+                    entered: cycles,
+                    child_cycles: 0,
+                });
+                self.update_stack_key();
+            }
+            Instruction::Ret => {
+                // A return without a matching call is left for the CPU to deal with
+                if let Some(frame) = self.calls.pop() {
+                    // Count the RET itself as part of the subroutine
+                    let elapsed = cycles + 1 - frame.entered;
+                    let sub = self.subroutines.entry(frame.address).or_default();
+                    sub.calls += 1;
+                    sub.total_cycles += elapsed;
+                    sub.self_cycles += elapsed - frame.child_cycles;
+                    if let Some(parent) = self.calls.last_mut() {
+                        parent.child_cycles += elapsed;
+                    }
+                    self.update_stack_key();
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn on_error(&mut self, _cpu: &CPU, _opcode: Option<u16>, _error: &Error) {
+        self.pending = None;
+    }
+}
+
+fn fetch(cpu: &CPU, address: u16) -> u16 {
+    let memory = cpu.memory();
+    let address = address as usize & 0xFFF;
+    ((memory[address] as u16) << 8) | (memory[(address + 1) & 0xFFF] as u16)
+}
+
+fn percent(count: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 * 100.0 / total as f64
+    }
+}
+
+/// Returns the separator to put before the next JSON member
+fn comma(first: &mut bool) -> &'static str {
+    if std::mem::replace(first, false) {
+        ""
+    } else {
+        ","
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn times_subroutines_and_folds_stacks() {
+        #[rustfmt::skip]
+        let rom = [
+            0x22, 0x08, // 200: CALL 208
+            0x12, 0x02, // 202: JP 202
+            0x00, 0x00,
+            0x00, 0x00,
+            0x60, 0x01, // 208: LD V0, 1
+            0x22, 0x0E, // 20A: CALL 20E
+            0x00, 0xEE, // 20C: RET
+            0x61, 0x02, // 20E: LD V1, 2
+            0x00, 0xEE, // 210: RET
+        ];
+        let mut cpu = CPU::new();
+        cpu.reset();
+        cpu.load_rom(&rom).unwrap();
+        let mut profiler = Profiler::new();
+        for _ in 0..10 {
+            cpu.execute_cycle_with(&mut profiler).unwrap();
+        }
+
+        // The inner call runs CALL, LD and RET, the outer one 3 of its own around it
+        let inner = Subroutine {
+            calls: 1,
+            total_cycles: 3,
+            self_cycles: 3,
+        };
+        let outer = Subroutine {
+            calls: 1,
+            total_cycles: 6,
+            self_cycles: 3,
+        };
+        assert_eq!(profiler.subroutines()[&0x20E], inner);
+        assert_eq!(profiler.subroutines()[&0x208], outer);
+        assert_eq!(profiler.hot_spots()[0], (0x202, 4));
+        assert_eq!(profiler.pc_count(0x20C), 1);
+        assert_eq!(profiler.family_counts()["2nnn"], 2);
+
+        let mut folded = Vec::new();
+        profiler.write_folded(&mut folded).unwrap();
+        assert_eq!(
+            String::from_utf8(folded).unwrap(),
+            "main 5\nmain;0x208 3\nmain;0x208;0x20E 2\n"
+        );
+        let mut json = Vec::new();
+        profiler.write_json(&mut json).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(json["pc"]["0x202"], 4);
+        assert_eq!(json["subroutines"]["0x208"]["self_cycles"], 3);
+        let mut report = Vec::new();
+        profiler.write_report(&mut report, &cpu, 3).unwrap();
+        let report = String::from_utf8(report).unwrap();
+        assert!(report.starts_with("Instructions executed: 10\n"));
+        assert!(report.contains("   202            4  40.00%  JP 0x202"));
+
+        // A CALL on a full stack fails, and neither counts nor enters a subroutine
+        let mut cpu = CPU::new();
+        cpu.reset();
+        cpu.load_rom(&[0x22, 0x00]).unwrap(); // 200: CALL 200
+        let mut profiler = Profiler::new();
+        while cpu.execute_cycle_with(&mut profiler).is_ok() {}
+        assert_eq!(profiler.pc_count(0x200), 16);
+        assert_eq!(profiler.calls.len(), 16);
+    }
+}
@@ -0,0 +1,101 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// A source location an address was assembled from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Location {
+    pub file: String,
+    pub line: u32,
+}
+
+impl Location {
+    /// True if this location is in file
+    /// Files match if either path ends with the other, so relative paths in the symbol
+    /// file still match the absolute paths editors send.
+    pub fn in_file(&self, file: &str) -> bool {
+        let a = self.file.replace('\\', "/");
+        let b = file.replace('\\', "/");
+        a == b || a.ends_with(&format!("/{}", b)) || b.ends_with(&format!("/{}", a))
+    }
+}
+
+/// Address to source line table read from a debug symbol file
+///
+/// The file has one instruction per line: a hex address, the source file and the line number,
+/// separated by whitespace. Blank lines and lines starting with '#' are ignored.
+///
+/// ```text
+/// # address  file       line
+/// 0x200      pong.8o    12
+/// 0x202      pong.8o    13
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Symbols {
+    locations: BTreeMap<u16, Location>,
+}
+
+impl Symbols {
+    /// Returns an empty table
+    pub fn new() -> Symbols {
+        Symbols::default()
+    }
+
+    /// Reads a symbol file from disk
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Symbols> {
+        Symbols::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Parses the contents of a symbol file
+    pub fn parse(text: &str) -> io::Result<Symbols> {
+        let mut symbols = Symbols::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid symbol on line {}: {}", number + 1, line),
+                )
+            };
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() != 3 {
+                return Err(invalid());
+            }
+            let address = fields[0].trim_start_matches("0x").trim_start_matches("0X");
+            let address = u16::from_str_radix(address, 16).map_err(|_| invalid())?;
+            let source_line = fields[2].parse().map_err(|_| invalid())?;
+            symbols.insert(address, fields[1], source_line);
+        }
+        Ok(symbols)
+    }
+
+    /// Records that the instruction at address comes from file:line
+    pub fn insert(&mut self, address: u16, file: &str, line: u32) {
+        self.locations.insert(
+            address,
+            Location {
+                file: file.to_string(),
+                line,
+            },
+        );
+    }
+
+    /// Source location of the instruction at address
+    pub fn location(&self, address: u16) -> Option<&Location> {
+        self.locations.get(&address)
+    }
+
+    /// Address of the first instruction on the first line at or after line in file,
+    /// along with the line actually used
+    pub fn address(&self, file: &str, line: u32) -> Option<(u16, u32)> {
+        self.locations
+            .iter()
+            .filter(|(_, location)| location.in_file(file) && location.line >= line)
+            .min_by_key(|(&address, location)| (location.line, address))
+            .map(|(&address, location)| (address, location.line))
+    }
+}
@@ -0,0 +1,341 @@
+use crate::cpu::hook::Hook;
+use crate::cpu::instruction::Instruction;
+use crate::cpu::{Error, CPU};
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::ops::{Range, RangeInclusive};
+use std::path::Path;
+
+/// Selects which instructions get traced
+/// Every filter that is left unset lets all instructions through
+#[derive(Clone, Debug, Default)]
+pub struct Filter {
+    /// Only trace instructions located in this address range
+    pub pc: Option<RangeInclusive<u16>>,
+
+    /// Only trace instructions whose family starts with one of these patterns,
+    /// e.g. "Dxyn" for draws only, or "8" for every 8xy_ arithmetic instruction
+    pub families: Vec<String>,
+
+    /// Only trace instructions executed during this window of frames
+    pub frames: Option<Range<u64>>,
+}
+
+impl Filter {
+    fn matches(&self, pc: u16, instruction: &Instruction, frame: u64) -> bool {
+        if let Some(range) = &self.pc {
+            if !range.contains(&pc) {
+                return false;
+            }
+        }
+        if let Some(window) = &self.frames {
+            if !window.contains(&frame) {
+                return false;
+            }
+        }
+        self.families.is_empty()
+            || self
+                .families
+                .iter()
+                .any(|family| instruction.family().starts_with(family.as_str()))
+    }
+}
+
+/// Machine state captured before an instruction runs, used to report what it changed
+struct Before {
+    cycle: u64,
+    frame: u64,
+    pc: u16,
+    v: [u8; 16],
+}
+
+/// Writes one line per executed instruction:
+/// cycle count, frame, PC, opcode, mnemonic, changed registers, I and the timers
+pub struct Tracer {
+    out: Box<dyn Write>,
+    filter: Filter,
+
+    /// In ring buffer mode, only the last `capacity` lines are kept and
+    /// they are only written out when `dump` is called or an instruction fails
+    ring: Option<VecDeque<String>>,
+    capacity: usize,
+
+    before: Option<Before>,
+
+    /// First write error encountered, reported by `finish`
+    error: Option<io::Error>,
+}
+
+impl Tracer {
+    /// Returns a tracer that writes every matching instruction to out
+    pub fn new(out: Box<dyn Write>, filter: Filter) -> Tracer {
+        Tracer {
+            out,
+            filter,
+            ring: None,
+            capacity: 0,
+            before: None,
+            error: None,
+        }
+    }
+
+    /// Returns a tracer that keeps only the last capacity matching instructions in memory
+    pub fn ring_buffer(out: Box<dyn Write>, filter: Filter, capacity: usize) -> Tracer {
+        let mut tracer = Tracer::new(out, filter);
+        tracer.ring = Some(VecDeque::with_capacity(capacity));
+        tracer.capacity = capacity;
+        tracer
+    }
+
+    /// Creates (or truncates) the file at path and traces into it
+    pub fn to_file<P: AsRef<Path>>(path: P, filter: Filter) -> io::Result<Tracer> {
+        let file = File::create(path)?;
+        Ok(Tracer::new(Box::new(BufWriter::new(file)), filter))
+    }
+
+    /// Writes out and clears the lines held in the ring buffer
+    /// Does nothing when not in ring buffer mode
+    pub fn dump(&mut self) -> io::Result<()> {
+        if let Some(ring) = &mut self.ring {
+            for line in ring.drain(..) {
+                self.out.write_all(line.as_bytes())?;
+            }
+        }
+        self.out.flush()
+    }
+
+    /// Flushes the output, returning the first error hit while tracing, if any
+    pub fn finish(&mut self) -> io::Result<()> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        self.out.flush()
+    }
+
+    fn format_line(before: &Before, cpu: &CPU, opcode: u16, instruction: &Instruction) -> String {
+        let mut changes = String::new();
+        for (r, (old, new)) in before.v.iter().zip(cpu.v().iter()).enumerate() {
+            if old != new {
+                let _ = write!(changes, "V{:X}={:02X}->{:02X} ", r, old, new);
+            }
+        }
+        if changes.is_empty() {
+            changes.push_str("- ");
+        }
+        format!(
+            "{:>10} {:>6} {:03X}: {:04X}  {:<18} {}I={:03X} DT={:02X} ST={:02X}\n",
+            before.cycle,
+            before.frame,
+            before.pc,
+            opcode,
+            instruction.to_string(),
+            changes,
+            cpu.i(),
+            cpu.dt(),
+            cpu.st(),
+        )
+    }
+
+    /// Keeps line in the ring buffer, or writes it out when there isn't one
+    fn emit(&mut self, line: String) {
+        match &mut self.ring {
+            Some(ring) => {
+                if ring.len() == self.capacity {
+                    ring.pop_front();
+                }
+                if self.capacity > 0 {
+                    ring.push_back(line);
+                }
+            }
+            None => {
+                if let Err(e) = self.out.write_all(line.as_bytes()) {
+                    self.error.get_or_insert(e);
+                }
+            }
+        }
+    }
+}
+
+impl Hook for Tracer {
+    fn before_instruction(&mut self, cpu: &CPU, opcode: u16) {
+        let instruction = Instruction::decode(opcode);
+        self.before = if self.filter.matches(cpu.pc(), &instruction, cpu.frames()) {
+            Some(Before {
+                cycle: cpu.cycles(),
+                frame: cpu.frames(),
+                pc: cpu.pc(),
+                v: *cpu.v(),
+            })
+        } else {
+            None
+        };
+    }
+
+    fn after_instruction(&mut self, cpu: &CPU, opcode: u16) {
+        let before = match self.before.take() {
+            Some(before) => before,
+            None => return,
+        };
+        let line = Tracer::format_line(&before, cpu, opcode, &Instruction::decode(opcode));
+        self.emit(line);
+    }
+
+    /// Logs the failing instruction whatever the filter, then dumps the ring buffer
+    fn on_error(&mut self, cpu: &CPU, opcode: Option<u16>, error: &Error) {
+        self.before = None;
+        let (opcode, mnemonic) = match opcode {
+            Some(opcode) => (
+                format!("{:04X}", opcode),
+                Instruction::decode(opcode).to_string(),
+            ),
+            None => ("????".to_string(), String::new()),
+        };
+        let line = format!(
+            "{:>10} {:>6} {:03X}: {}  {:<18} error: {}\n",
+            cpu.cycles(),
+            cpu.frames(),
+            cpu.pc(),
+            opcode,
+            mnemonic,
+            error,
+        );
+        self.emit(line);
+        if let Err(e) = self.dump() {
+            self.error.get_or_insert(e);
+        }
+    }
+}
+
+/// A ring buffer is also dumped if the emulator panics, e.g. in a script
+impl Drop for Tracer {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            let _ = self.dump();
+        } else {
+            let _ = self.out.flush();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// Output that the test can still read after handing it to a tracer
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Shared {
+        fn lines(&self) -> Vec<String> {
+            let text = String::from_utf8(self.0.borrow().clone()).unwrap();
+            text.lines().map(str::to_string).collect()
+        }
+    }
+
+    fn cpu(rom: &[u8]) -> CPU {
+        let mut cpu = CPU::new();
+        cpu.reset();
+        cpu.load_rom(rom).unwrap();
+        cpu
+    }
+
+    #[test]
+    fn filters_and_ring_buffer() {
+        // 200: LD V0, 1; 202: ADD V0, 1; 204: LD I, 300; 206: JP 202
+        let rom = [0x60, 0x01, 0x70, 0x01, 0xA3, 0x00, 0x12, 0x02];
+        let run = |tracer: &mut Tracer| {
+            let mut cpu = cpu(&rom);
+            for _ in 0..3 {
+                for _ in 0..4 {
+                    cpu.execute_cycle_with(&mut *tracer).unwrap();
+                }
+                cpu.decrement_timers();
+            }
+        };
+
+        let out = Shared::default();
+        let mut tracer = Tracer::new(Box::new(out.clone()), Filter::default());
+        run(&mut tracer);
+        let lines = out.lines();
+        assert_eq!(lines.len(), 12);
+        assert!(lines[1].contains("202: 7001") && lines[1].contains("V0=01->02"));
+        assert!(lines[2].contains("- I=300"));
+
+        let filters = [
+            Filter {
+                pc: Some(0x202..=0x204),
+                ..Filter::default()
+            },
+            Filter {
+                families: vec!["7".to_string(), "Annn".to_string()],
+                ..Filter::default()
+            },
+            Filter {
+                frames: Some(1..2),
+                ..Filter::default()
+            },
+        ];
+        let expected: [&[&str]; 3] = [
+            &["202", "204", "202", "204", "202", "204", "202", "204"],
+            &["202", "204", "202", "204", "202", "204", "202", "204"],
+            &["202", "204", "206", "202"],
+        ];
+        for (filter, expected) in filters.iter().zip(expected.iter()) {
+            let out = Shared::default();
+            run(&mut Tracer::new(Box::new(out.clone()), filter.clone()));
+            let pcs: Vec<String> = out.lines().iter().map(|l| l[18..21].to_string()).collect();
+            assert_eq!(pcs, *expected, "{:?}", filter);
+        }
+
+        // Only the last lines are kept, and only written out on dump
+        let out = Shared::default();
+        let mut tracer = Tracer::ring_buffer(Box::new(out.clone()), Filter::default(), 3);
+        run(&mut tracer);
+        assert!(out.lines().is_empty());
+        tracer.dump().unwrap();
+        assert_eq!(out.lines(), lines[9..]);
+        let mut tracer = Tracer::ring_buffer(Box::new(out.clone()), Filter::default(), 0);
+        run(&mut tracer);
+        tracer.dump().unwrap();
+        assert_eq!(out.lines().len(), 3);
+    }
+
+    #[test]
+    fn errors_dump_the_ring_buffer() {
+        // LD V0, 1; ADD V0, 1; ADD V0, 1; RET with nothing to return to
+        let mut cpu = cpu(&[0x60, 0x01, 0x70, 0x01, 0x70, 0x01, 0x00, 0xEE]);
+        let out = Shared::default();
+        let mut tracer = Tracer::ring_buffer(Box::new(out.clone()), Filter::default(), 2);
+        for _ in 0..3 {
+            cpu.execute_cycle_with(&mut tracer).unwrap();
+        }
+        assert!(out.lines().is_empty());
+
+        assert_eq!(
+            cpu.execute_cycle_with(&mut tracer),
+            Err(Error::StackUnderflow)
+        );
+        let lines = out.lines();
+        assert_eq!(lines.len(), 2, "{:?}", lines);
+        assert!(lines[0].contains("204: 7001") && lines[0].contains("V0=02->03"));
+        assert!(
+            lines[1].contains("206: 00EE")
+                && lines[1].ends_with("error: return with an empty stack")
+        );
+    }
+}
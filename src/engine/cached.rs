@@ -0,0 +1,127 @@
+use super::writes;
+use crate::cpu::instruction::Instruction;
+use crate::cpu::{Error, CPU};
+
+/// Most instructions decoded into one block
+const MAX_BLOCK: usize = 32;
+
+/// A run of instructions that always execute one after the other
+struct Block {
+    /// Address just past the last instruction
+    end: u16,
+    instructions: Vec<Instruction>,
+}
+
+/// Cached interpreter: decodes straight-line code once and replays the decoded instructions
+///
+/// Blocks end at any instruction that may not fall through to the next one (jumps, calls,
+/// returns, skips and Fx0A) and at instructions that write memory (Fx33 and Fx55). Writes
+/// drop every block they overlap, so self-modifying code is decoded again before it runs.
+pub struct Cache {
+    /// Decoded blocks by start address
+    blocks: Vec<Option<Block>>,
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Cache {
+    pub fn new() -> Cache {
+        let mut blocks = Vec::new();
+        blocks.resize_with(4096, || None);
+        Cache { blocks }
+    }
+
+    /// Forgets every decoded block
+    pub fn flush(&mut self) {
+        for block in self.blocks.iter_mut() {
+            *block = None;
+        }
+    }
+
+    /// Executes up to cycles instructions, stopping at the first one that fails
+    pub fn run(&mut self, cpu: &mut CPU, cycles: u64) -> Result<(), Error> {
+        let mut remaining = cycles;
+        while remaining > 0 {
+            let pc = cpu.pc() as usize;
+            if pc + 1 >= cpu.memory().len() {
+                return Err(Error::AddressOutOfRange(cpu.pc()));
+            }
+            if self.blocks[pc].is_none() {
+                self.blocks[pc] = Some(decode_block(cpu.memory(), pc));
+            }
+            let block = self.blocks[pc].as_ref().unwrap();
+
+            let mut written = None;
+            for &instruction in block.instructions.iter() {
+                written = writes(instruction, cpu);
+                cpu.execute_cycle_decoded(instruction)?;
+                remaining -= 1;
+                if remaining == 0 {
+                    break;
+                }
+            }
+            if let Some((start, end)) = written {
+                self.invalidate(start, end);
+            }
+        }
+        Ok(())
+    }
+
+    /// Drops the blocks that overlap memory from start up to end
+    pub fn invalidate(&mut self, start: usize, end: usize) {
+        let first = start.saturating_sub(2 * MAX_BLOCK);
+        for address in first..end.min(self.blocks.len()) {
+            let overlaps = match &self.blocks[address] {
+                Some(block) => block.end as usize > start,
+                None => false,
+            };
+            if overlaps {
+                self.blocks[address] = None;
+            }
+        }
+    }
+}
+
+/// Decodes the block starting at pc
+fn decode_block(memory: &[u8; 4096], pc: usize) -> Block {
+    let mut instructions = Vec::new();
+    let mut address = pc;
+    while instructions.len() < MAX_BLOCK && address + 1 < memory.len() {
+        let opcode = (memory[address] as u16) << 8 | memory[address + 1] as u16;
+        let instruction = Instruction::decode(opcode);
+        instructions.push(instruction);
+        address += 2;
+        if ends_block(instruction) {
+            break;
+        }
+    }
+    Block {
+        end: address as u16,
+        instructions,
+    }
+}
+
+fn ends_block(instruction: Instruction) -> bool {
+    match instruction {
+        Instruction::Ret
+        | Instruction::Jp(_)
+        | Instruction::Call(_)
+        | Instruction::SeByte(..)
+        | Instruction::SneByte(..)
+        | Instruction::SeReg(..)
+        | Instruction::SneReg(..)
+        | Instruction::JpV0(_)
+        | Instruction::Skp(_)
+        | Instruction::Sknp(_)
+        | Instruction::LdVxK(_) => true,
+        _ => writes_memory(instruction),
+    }
+}
+
+fn writes_memory(instruction: Instruction) -> bool {
+    matches!(instruction, Instruction::LdB(_) | Instruction::StoreRegs(_))
+}
@@ -0,0 +1,288 @@
+// x86-64 dynamic recompiler, enabled with the "jit" feature
+// Register arithmetic is translated to native code; everything else goes through the CPU.
+
+use super::writes;
+use crate::cpu::instruction::Instruction;
+use crate::cpu::quirks::Quirks;
+use crate::cpu::{Error, CPU};
+use dynasmrt::x64::Assembler;
+use dynasmrt::{dynasm, AssemblyOffset, DynasmApi, ExecutableBuffer};
+
+/// Most instructions translated into one native block
+const MAX_BLOCK: usize = 32;
+
+/// The registers native code works on, passed to it in rdi
+#[repr(C)]
+struct Regs {
+    v: [u8; 16],
+    i: u16,
+}
+
+/// Offset of `Regs::i`
+const I: i32 = 16;
+
+type Entry = extern "sysv64" fn(*mut Regs);
+
+/// Native code for a run of instructions
+struct Native {
+    /// Address just past the last instruction
+    end: u16,
+    /// Number of instructions translated
+    length: u64,
+    code: ExecutableBuffer,
+    entry: AssemblyOffset,
+}
+
+enum Block {
+    Native(Native),
+    /// The instruction at this address can't be translated, so the CPU executes it
+    Interpreted,
+}
+
+impl Block {
+    /// Address just past the code this block was built from
+    fn end(&self, start: usize) -> usize {
+        match self {
+            Block::Native(native) => native.end as usize,
+            Block::Interpreted => start + 2,
+        }
+    }
+}
+
+/// Recompiler: translates runs of register arithmetic (6xkk, 7xkk, 8xyn, Annn, Fx1E and Fx29)
+/// to x86-64 and hands every other instruction to `CPU::execute_cycle_decoded`
+///
+/// Quirks are baked into the native code, so blocks are dropped whenever `CPU::quirks`
+/// changes. Native code never writes memory; the CPU's Fx33 and Fx55 writes drop every block
+/// they overlap, so self-modifying code is translated again before it runs.
+pub struct Jit {
+    /// Translated blocks by start address
+    blocks: Vec<Option<Block>>,
+    /// Quirks the blocks were translated with
+    quirks: Quirks,
+}
+
+impl Default for Jit {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Jit {
+    pub fn new() -> Jit {
+        let mut blocks = Vec::new();
+        blocks.resize_with(4096, || None);
+        Jit {
+            blocks,
+            quirks: Quirks::default(),
+        }
+    }
+
+    /// Forgets every translated block
+    pub fn flush(&mut self) {
+        for block in self.blocks.iter_mut() {
+            *block = None;
+        }
+    }
+
+    /// Executes up to cycles instructions, stopping at the first one that fails
+    pub fn run(&mut self, cpu: &mut CPU, cycles: u64) -> Result<(), Error> {
+        if cpu.quirks != self.quirks {
+            self.flush();
+            self.quirks = cpu.quirks;
+        }
+
+        let mut remaining = cycles;
+        while remaining > 0 {
+            let pc = cpu.pc() as usize;
+            if pc + 1 >= cpu.memory().len() {
+                return Err(Error::AddressOutOfRange(cpu.pc()));
+            }
+            if self.blocks[pc].is_none() {
+                self.blocks[pc] = Some(translate(cpu.memory(), pc, self.quirks));
+            }
+
+            match self.blocks[pc].as_ref().unwrap() {
+                // Blocks run to completion, so a short budget falls back to the CPU
+                Block::Native(native) if native.length <= remaining => {
+                    let mut regs = Regs {
+                        v: *cpu.v(),
+                        i: cpu.i(),
+                    };
+                    // The buffer holds a complete function taking a pointer to Regs
+                    let entry: Entry =
+                        unsafe { std::mem::transmute(native.code.ptr(native.entry)) };
+                    entry(&mut regs);
+                    for (x, &value) in regs.v.iter().enumerate() {
+                        cpu.set_v(x, value);
+                    }
+                    cpu.set_i(regs.i);
+                    cpu.set_pc(native.end);
+                    cpu.add_cycles(native.length);
+                    remaining -= native.length;
+                }
+                _ => {
+                    let memory = cpu.memory();
+                    let opcode = (memory[pc] as u16) << 8 | memory[pc + 1] as u16;
+                    let instruction = Instruction::decode(opcode);
+                    let written = writes(instruction, cpu);
+                    cpu.execute_cycle_decoded(instruction)?;
+                    remaining -= 1;
+                    if let Some((start, end)) = written {
+                        self.invalidate(start, end);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Drops the blocks that overlap memory from start up to end
+    pub fn invalidate(&mut self, start: usize, end: usize) {
+        let first = start.saturating_sub(2 * MAX_BLOCK);
+        for address in first..end.min(self.blocks.len()) {
+            let overlaps = match &self.blocks[address] {
+                Some(block) => block.end(address) > start,
+                None => false,
+            };
+            if overlaps {
+                self.blocks[address] = None;
+            }
+        }
+    }
+}
+
+/// Translates the longest run of supported instructions starting at pc
+fn translate(memory: &[u8; 4096], pc: usize, quirks: Quirks) -> Block {
+    let mut ops = Assembler::new().unwrap();
+    let entry = ops.offset();
+    let mut address = pc;
+    let mut length = 0;
+    while length < MAX_BLOCK && address + 1 < memory.len() {
+        let opcode = (memory[address] as u16) << 8 | memory[address + 1] as u16;
+        if !emit(&mut ops, Instruction::decode(opcode), quirks) {
+            break;
+        }
+        address += 2;
+        length += 1;
+    }
+    if length == 0 {
+        return Block::Interpreted;
+    }
+    dynasm!(ops
+        ; .arch x64
+        ; ret
+    );
+    Block::Native(Native {
+        end: address as u16,
+        length: length as u64,
+        code: ops.finalize().unwrap(),
+        entry,
+    })
+}
+
+/// Appends native code for instruction, or returns false if it isn't supported
+/// Only al and cl are used as scratch registers, and VF is always written last.
+fn emit(ops: &mut Assembler, instruction: Instruction, quirks: Quirks) -> bool {
+    let vf = 0xF;
+    match instruction {
+        Instruction::Sys(_) | Instruction::Unknown(_) => (),
+        Instruction::LdByte(x, kk) => dynasm!(ops
+            ; .arch x64
+            ; mov BYTE [rdi + x as i32], kk as i8
+        ),
+        Instruction::AddByte(x, kk) => dynasm!(ops
+            ; .arch x64
+            ; add BYTE [rdi + x as i32], kk as i8
+        ),
+        Instruction::LdReg(x, y) => dynasm!(ops
+            ; .arch x64
+            ; mov al, BYTE [rdi + y as i32]
+            ; mov BYTE [rdi + x as i32], al
+        ),
+        Instruction::Or(x, y) | Instruction::And(x, y) | Instruction::Xor(x, y) => {
+            dynasm!(ops
+                ; .arch x64
+                ; mov al, BYTE [rdi + y as i32]
+            );
+            match instruction {
+                Instruction::Or(..) => dynasm!(ops ; .arch x64 ; or BYTE [rdi + x as i32], al),
+                Instruction::And(..) => dynasm!(ops ; .arch x64 ; and BYTE [rdi + x as i32], al),
+                _ => dynasm!(ops ; .arch x64 ; xor BYTE [rdi + x as i32], al),
+            }
+            if quirks.vf_reset {
+                dynasm!(ops
+                    ; .arch x64
+                    ; mov BYTE [rdi + vf], 0
+                );
+            }
+        }
+        Instruction::AddReg(x, y) => dynasm!(ops
+            ; .arch x64
+            ; mov al, BYTE [rdi + x as i32]
+            ; add al, BYTE [rdi + y as i32]
+            ; setc cl
+            ; mov BYTE [rdi + x as i32], al
+            ; mov BYTE [rdi + vf], cl
+        ),
+        Instruction::Sub(x, y) => dynasm!(ops
+            ; .arch x64
+            ; mov al, BYTE [rdi + x as i32]
+            ; sub al, BYTE [rdi + y as i32]
+            ; setnc cl
+            ; mov BYTE [rdi + x as i32], al
+            ; mov BYTE [rdi + vf], cl
+        ),
+        Instruction::Subn(x, y) => dynasm!(ops
+            ; .arch x64
+            ; mov al, BYTE [rdi + y as i32]
+            ; sub al, BYTE [rdi + x as i32]
+            ; setnc cl
+            ; mov BYTE [rdi + x as i32], al
+            ; mov BYTE [rdi + vf], cl
+        ),
+        Instruction::Shr(x, y) => {
+            let source = if quirks.shift_vy { y } else { x };
+            dynasm!(ops
+                ; .arch x64
+                ; mov al, BYTE [rdi + source as i32]
+                ; mov cl, al
+                ; and cl, 1
+                ; shr al, 1
+                ; mov BYTE [rdi + x as i32], al
+                ; mov BYTE [rdi + vf], cl
+            )
+        }
+        Instruction::Shl(x, y) => {
+            let source = if quirks.shift_vy { y } else { x };
+            dynasm!(ops
+                ; .arch x64
+                ; mov al, BYTE [rdi + source as i32]
+                ; mov cl, al
+                ; shr cl, 7
+                ; shl al, 1
+                ; mov BYTE [rdi + x as i32], al
+                ; mov BYTE [rdi + vf], cl
+            )
+        }
+        Instruction::LdI(nnn) => dynasm!(ops
+            ; .arch x64
+            ; mov WORD [rdi + I], nnn as i16
+        ),
+        Instruction::AddI(x) => dynasm!(ops
+            ; .arch x64
+            ; movzx eax, BYTE [rdi + x as i32]
+            ; add WORD [rdi + I], ax
+        ),
+        // Font sprites are 5 bytes each
+        Instruction::LdF(x) => dynasm!(ops
+            ; .arch x64
+            ; movzx eax, BYTE [rdi + x as i32]
+            ; and eax, 0xF
+            ; imul eax, eax, 5
+            ; mov WORD [rdi + I], ax
+        ),
+        _ => return false,
+    }
+    true
+}
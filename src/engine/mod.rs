@@ -0,0 +1,99 @@
+// Engines run the CPU many cycles at a time, for batch runs where per-cycle hooks aren't needed
+
+pub mod cached;
+#[cfg(all(feature = "jit", target_arch = "x86_64"))]
+pub mod jit;
+
+use crate::cpu::instruction::Instruction;
+use crate::cpu::{Error, CPU};
+use cached::Cache;
+#[cfg(all(feature = "jit", target_arch = "x86_64"))]
+use jit::Jit;
+
+/// How instructions get executed
+/// Every engine must leave the CPU exactly as `CPU::execute_cycle` would.
+pub enum Engine {
+    /// Fetches and decodes every instruction, `CPU::execute_cycle` in a loop
+    Interpreter,
+    /// Decodes each basic block once and replays the decoded instructions
+    Cached(Cache),
+    /// Recompiles straight-line arithmetic to native x86-64 code
+    #[cfg(all(feature = "jit", target_arch = "x86_64"))]
+    Jit(Jit),
+}
+
+impl Engine {
+    /// Engine names accepted by `Engine::from_name`
+    #[cfg(not(all(feature = "jit", target_arch = "x86_64")))]
+    pub const NAMES: &'static [&'static str] = &["interpreter", "cached"];
+    /// Engine names accepted by `Engine::from_name`
+    #[cfg(all(feature = "jit", target_arch = "x86_64"))]
+    pub const NAMES: &'static [&'static str] = &["interpreter", "cached", "jit"];
+
+    /// Looks up an engine by name
+    pub fn from_name(name: &str) -> Option<Engine> {
+        match name {
+            "interpreter" => Some(Engine::Interpreter),
+            "cached" => Some(Engine::Cached(Cache::new())),
+            #[cfg(all(feature = "jit", target_arch = "x86_64"))]
+            "jit" => Some(Engine::Jit(Jit::new())),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Engine::Interpreter => "interpreter",
+            Engine::Cached(_) => "cached",
+            #[cfg(all(feature = "jit", target_arch = "x86_64"))]
+            Engine::Jit(_) => "jit",
+        }
+    }
+
+    /// Executes up to cycles instructions, stopping at the first one that fails
+    pub fn run(&mut self, cpu: &mut CPU, cycles: u64) -> Result<(), Error> {
+        match self {
+            Engine::Interpreter => {
+                for _ in 0..cycles {
+                    cpu.execute_cycle()?;
+                }
+                Ok(())
+            }
+            Engine::Cached(cache) => cache.run(cpu, cycles),
+            #[cfg(all(feature = "jit", target_arch = "x86_64"))]
+            Engine::Jit(jit) => jit.run(cpu, cycles),
+        }
+    }
+
+    /// Forgets any code decoded so far
+    /// Engines only notice memory written by instructions, so call this after changing memory
+    /// any other way: loading a ROM or save state, `CPU::poke`, cheats or scripts.
+    pub fn flush(&mut self) {
+        match self {
+            Engine::Interpreter => (),
+            Engine::Cached(cache) => cache.flush(),
+            #[cfg(all(feature = "jit", target_arch = "x86_64"))]
+            Engine::Jit(jit) => jit.flush(),
+        }
+    }
+
+    /// `flush` for only the code decoded from memory from start up to end
+    pub fn invalidate(&mut self, start: usize, end: usize) {
+        match self {
+            Engine::Interpreter => (),
+            Engine::Cached(cache) => cache.invalidate(start, end),
+            #[cfg(all(feature = "jit", target_arch = "x86_64"))]
+            Engine::Jit(jit) => jit.invalidate(start, end),
+        }
+    }
+}
+
+/// Memory range instruction is about to write, if any
+fn writes(instruction: Instruction, cpu: &CPU) -> Option<(usize, usize)> {
+    let start = cpu.i() as usize;
+    match instruction {
+        Instruction::LdB(_) => Some((start, start + 3)),
+        Instruction::StoreRegs(x) => Some((start, start + x + 1)),
+        _ => None,
+    }
+}
@@ -0,0 +1,304 @@
+// Gym-style environment: one ROM as a reinforcement learning task
+// Rewards and episode ends come from the game's own memory, described per ROM in a TOML file.
+
+use crate::cpu::display::HEIGHT;
+use crate::cpu::quirks::Quirks;
+use crate::cpu::{Error, CPU};
+use serde::Deserialize;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// How a ROM is played as an environment
+///
+/// ```toml
+/// frame_skip = 4
+/// sticky_actions = 0.25
+///
+/// # The score is the byte at 0x2F0
+/// [[reward]]
+/// address = 0x2F0
+///
+/// # The game is over once the lives counter at 0x2F1 hits 0
+/// [[terminal]]
+/// address = 0x2F1
+/// equals = 0
+/// ```
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Frames each step runs for, with the same action
+    #[serde(default = "default_frame_skip")]
+    pub frame_skip: u32,
+    /// Chance each frame that the previous frame's keys stay held instead of the step's
+    #[serde(default)]
+    pub sticky_actions: f64,
+    /// Instructions per 60Hz frame
+    #[serde(default = "default_cycles_per_frame")]
+    pub cycles_per_frame: u32,
+    /// Quirk profile, see `Quirks::profile`
+    #[serde(default = "default_quirks")]
+    pub quirks: String,
+    /// Episodes end after this many frames even if no terminal condition was met
+    #[serde(default)]
+    pub max_frames: Option<u64>,
+    #[serde(default)]
+    pub reward: Vec<Reward>,
+    #[serde(default)]
+    pub terminal: Vec<Terminal>,
+}
+
+/// Reward for the change of the byte at address during a step, times scale
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Reward {
+    pub address: u16,
+    #[serde(default = "default_scale")]
+    pub scale: f64,
+}
+
+/// The episode ends when the byte at address equals value
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Terminal {
+    pub address: u16,
+    pub equals: u8,
+}
+
+fn default_frame_skip() -> u32 {
+    4
+}
+
+fn default_cycles_per_frame() -> u32 {
+    10
+}
+
+fn default_quirks() -> String {
+    "default".to_string()
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            frame_skip: default_frame_skip(),
+            sticky_actions: 0.0,
+            cycles_per_frame: default_cycles_per_frame(),
+            quirks: default_quirks(),
+            max_frames: None,
+            reward: Vec::new(),
+            terminal: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Reads a config file
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Config> {
+        Config::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Parses the contents of a config file
+    pub fn parse(text: &str) -> io::Result<Config> {
+        let config: Config =
+            toml::from_str(text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if config.quirks_profile().is_none() {
+            let message = format!("unknown quirk profile {}", config.quirks);
+            return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+        }
+        if config.reward.iter().any(|reward| reward.address > 0xFFF)
+            || config
+                .terminal
+                .iter()
+                .any(|terminal| terminal.address > 0xFFF)
+        {
+            let message = "addresses must be below 0x1000";
+            return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+        }
+        Ok(config)
+    }
+
+    fn quirks_profile(&self) -> Option<Quirks> {
+        Quirks::profile(&self.quirks)
+    }
+}
+
+/// What a step or reset saw
+#[derive(Clone, Debug, PartialEq)]
+pub struct Observation {
+    /// The screen after the last frame, rows packed as in `Display::rows`
+    pub screen: [u64; HEIGHT],
+    pub reward: f64,
+    /// Whether the episode is over, by a terminal condition or `max_frames`
+    pub done: bool,
+    /// Frames since the episode started
+    pub frame: u64,
+}
+
+/// An episode of a ROM, driven one step at a time
+/// Everything, including sticky actions and Cxkk, depends only on the seed passed to `reset`
+/// and the actions, so environments can run rollouts in parallel and replay them.
+pub struct Env {
+    rom: Vec<u8>,
+    config: Config,
+    cpu: CPU,
+    /// xorshift state for sticky actions
+    rng: u64,
+    /// Keys held during the last frame
+    held: u16,
+    frame: u64,
+}
+
+impl Env {
+    /// Creates an environment, with an episode started with seed 0
+    pub fn new(rom: &[u8], config: Config) -> Result<Env, Error> {
+        let mut env = Env {
+            rom: rom.to_vec(),
+            config,
+            cpu: CPU::new(),
+            rng: 0,
+            held: 0,
+            frame: 0,
+        };
+        env.cpu.load_rom(rom)?;
+        env.reset(0);
+        Ok(env)
+    }
+
+    /// Starts a new episode
+    pub fn reset(&mut self, seed: u64) -> Observation {
+        self.cpu.reset();
+        self.cpu.quirks = self.config.quirks_profile().unwrap_or_default();
+        self.cpu.seed(seed);
+        // new checked that the ROM fits
+        self.cpu.load_rom(&self.rom).unwrap();
+        // A different stream from the CPU's, and never zero
+        self.rng = seed ^ 0x9E37_79B9_7F4A_7C15 | 1;
+        self.held = 0;
+        self.frame = 0;
+        self.observe(0.0)
+    }
+
+    /// Holds the keys in action, bit n for key n, for `frame_skip` frames
+    /// Errors leave the machine at the failing instruction; `reset` to carry on.
+    pub fn step(&mut self, action: u16) -> Result<Observation, Error> {
+        let before = *self.cpu.memory();
+        for _ in 0..self.config.frame_skip {
+            if self.config.sticky_actions == 0.0 || self.random() >= self.config.sticky_actions {
+                self.held = action;
+            }
+            for key in 0..16 {
+                if self.held & 1 << key != 0 {
+                    self.cpu.keyboard.key_down(key);
+                } else {
+                    self.cpu.keyboard.key_up(key);
+                }
+            }
+            for _ in 0..self.config.cycles_per_frame {
+                self.cpu.execute_cycle()?;
+            }
+            self.cpu.decrement_timers();
+            self.frame += 1;
+        }
+
+        let memory = self.cpu.memory();
+        let reward = self
+            .config
+            .reward
+            .iter()
+            .map(|reward| {
+                let address = reward.address as usize;
+                (memory[address] as f64 - before[address] as f64) * reward.scale
+            })
+            .sum();
+        Ok(self.observe(reward))
+    }
+
+    pub fn cpu(&self) -> &CPU {
+        &self.cpu
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Frames since the episode started
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    fn observe(&self, reward: f64) -> Observation {
+        let memory = self.cpu.memory();
+        let terminal = self
+            .config
+            .terminal
+            .iter()
+            .any(|terminal| memory[terminal.address as usize] == terminal.equals);
+        let truncated = self
+            .config
+            .max_frames
+            .is_some_and(|max_frames| self.frame >= max_frames);
+        Observation {
+            screen: *self.cpu.display.rows(),
+            reward,
+            done: terminal || truncated,
+            frame: self.frame,
+        }
+    }
+
+    /// Uniform in [0, 1), xorshift64
+    fn random(&mut self) -> f64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        (self.rng >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rewards_terminals_and_determinism() {
+        let config = Config::parse(
+            "frame_skip = 2\n\
+             sticky_actions = 0.5\n\
+             cycles_per_frame = 2\n\
+             max_frames = 1000\n\
+             [[reward]]\naddress = 0x300\nscale = 0.5\n\
+             [[terminal]]\naddress = 0x300\nequals = 5\n",
+        )
+        .unwrap();
+        #[rustfmt::skip]
+        let rom = [
+            0xE1, 0xA1, // 200: SKNP V1, skip unless key 0 is held
+            0x70, 0x01, // 202: ADD V0, 1
+            0xA3, 0x00, // 204: LD I, 0x300
+            0xF0, 0x55, // 206: LD [I], V0
+            0x12, 0x00, // 208: JP 0x200
+        ];
+        let mut env = Env::new(&rom, config).unwrap();
+
+        let mut episode = |seed| {
+            env.reset(seed);
+            let mut steps = Vec::new();
+            loop {
+                let observation = env.step(1).unwrap();
+                steps.push((observation.reward, observation.frame));
+                if observation.done {
+                    return steps;
+                }
+            }
+        };
+        let first = episode(7);
+        assert_eq!(first, episode(7));
+        assert_eq!(first.iter().map(|&(reward, _)| reward).sum::<f64>(), 2.5);
+
+        assert!(Config::parse("quirks = \"nope\"").is_err());
+        assert!(Config::parse("frame_skip = 4\nframes = 2").is_err());
+    }
+}
@@ -0,0 +1,320 @@
+// C ABI for embedding the emulator, enabled with the "ffi" feature
+// include/chip8.h is generated from this file, regenerate it after changing the API with
+// `cbindgen --config cbindgen.toml --output include/chip8.h src/ffi.rs`. See examples/c/host.c.
+
+use crate::cpu::display::{HEIGHT, WIDTH};
+use crate::cpu::{Error, CPU, STATE_SIZE};
+use std::convert::TryFrom;
+use std::os::raw::{c_char, c_int};
+use std::slice;
+
+/// Screen width in pixels
+pub const CHIP8_WIDTH: usize = 64;
+/// Screen height in pixels
+pub const CHIP8_HEIGHT: usize = 32;
+/// Size of a save state in bytes
+pub const CHIP8_STATE_SIZE: usize = 4441;
+
+// cbindgen only copies literals into the header, so check they match
+const _: () = assert!(CHIP8_WIDTH == WIDTH && CHIP8_HEIGHT == HEIGHT);
+const _: () = assert!(CHIP8_STATE_SIZE == STATE_SIZE);
+
+/// Instructions executed per frame unless changed with `chip8_set_cycles_per_frame`
+const CYCLES_PER_FRAME: u32 = 10;
+
+/// Result of every fallible call, 0 on success
+/// Values are part of the ABI: new ones may be added, existing ones never change.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Chip8Error {
+    Ok = 0,
+    /// A pointer argument was null
+    Null = 1,
+    /// A buffer argument is too small
+    BufferTooSmall = 2,
+    StackOverflow = 3,
+    StackUnderflow = 4,
+    AddressOutOfRange = 5,
+    InvalidKey = 6,
+    RomTooLarge = 7,
+    InvalidState = 8,
+}
+
+impl From<Error> for Chip8Error {
+    fn from(error: Error) -> Chip8Error {
+        match error {
+            Error::StackOverflow => Chip8Error::StackOverflow,
+            Error::StackUnderflow => Chip8Error::StackUnderflow,
+            Error::AddressOutOfRange(_) => Chip8Error::AddressOutOfRange,
+            Error::InvalidKey(_) => Chip8Error::InvalidKey,
+            Error::RomTooLarge(_) => Chip8Error::RomTooLarge,
+            Error::InvalidState => Chip8Error::InvalidState,
+        }
+    }
+}
+
+/// An emulator instance, only ever handled through a pointer
+pub struct Chip8 {
+    cpu: CPU,
+    cycles_per_frame: u32,
+    /// One byte per pixel, 1 for lit and 0 for unlit
+    frame: [u8; WIDTH * HEIGHT],
+}
+
+impl Chip8 {
+    fn refresh(&mut self) {
+        if self.cpu.display.take_dirty() != 0 {
+            self.frame = self.cpu.display.screen();
+        }
+    }
+}
+
+/// Creates an emulator with nothing loaded, free it with `chip8_free`
+/// seed feeds the random numbers of Cxkk.
+#[no_mangle]
+pub extern "C" fn chip8_new(seed: u64) -> *mut Chip8 {
+    let mut cpu = CPU::new();
+    cpu.reset();
+    cpu.seed(seed);
+    let mut chip8 = Chip8 {
+        cpu,
+        cycles_per_frame: CYCLES_PER_FRAME,
+        frame: [0; WIDTH * HEIGHT],
+    };
+    chip8.refresh();
+    Box::into_raw(Box::new(chip8))
+}
+
+/// Frees an emulator from `chip8_new`, does nothing for null
+///
+/// # Safety
+/// chip8 must be null or come from `chip8_new`, and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_free(chip8: *mut Chip8) {
+    if !chip8.is_null() {
+        drop(Box::from_raw(chip8));
+    }
+}
+
+/// Resets the machine and copies len bytes of rom to 0x200
+///
+/// # Safety
+/// chip8 must come from `chip8_new` and rom must point to len readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_load_rom(
+    chip8: *mut Chip8,
+    rom: *const u8,
+    len: usize,
+) -> Chip8Error {
+    let chip8 = match chip8.as_mut() {
+        Some(chip8) if !rom.is_null() => chip8,
+        _ => return Chip8Error::Null,
+    };
+    chip8.cpu.reset();
+    let result = chip8.cpu.load_rom(slice::from_raw_parts(rom, len));
+    chip8.refresh();
+    result.map_or_else(Chip8Error::from, |()| Chip8Error::Ok)
+}
+
+/// Runs one 60Hz frame: the instructions, then a timer tick
+/// On an error the machine stops at the failing instruction, as it was before it ran.
+///
+/// # Safety
+/// chip8 must come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_run_frame(chip8: *mut Chip8) -> Chip8Error {
+    let chip8 = match chip8.as_mut() {
+        Some(chip8) => chip8,
+        None => return Chip8Error::Null,
+    };
+    let mut result = Ok(());
+    for _ in 0..chip8.cycles_per_frame {
+        result = chip8.cpu.execute_cycle();
+        if result.is_err() {
+            break;
+        }
+    }
+    if result.is_ok() {
+        chip8.cpu.decrement_timers();
+    }
+    chip8.refresh();
+    result.map_or_else(Chip8Error::from, |()| Chip8Error::Ok)
+}
+
+/// Sets how many instructions `chip8_run_frame` executes
+///
+/// # Safety
+/// chip8 must come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_set_cycles_per_frame(chip8: *mut Chip8, cycles: u32) {
+    if let Some(chip8) = chip8.as_mut() {
+        chip8.cycles_per_frame = cycles;
+    }
+}
+
+/// Presses (down is true) or releases key 0-F
+///
+/// # Safety
+/// chip8 must come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_key(chip8: *mut Chip8, key: u8, down: bool) -> Chip8Error {
+    let chip8 = match chip8.as_mut() {
+        Some(chip8) => chip8,
+        None => return Chip8Error::Null,
+    };
+    if key > 0xF {
+        return Chip8Error::InvalidKey;
+    }
+    if down {
+        chip8.cpu.keyboard.key_down(key as usize);
+    } else {
+        chip8.cpu.keyboard.key_up(key as usize);
+    }
+    Chip8Error::Ok
+}
+
+/// The screen as of the last call, `CHIP8_WIDTH * CHIP8_HEIGHT` bytes row by row, 1 for a lit
+/// pixel and 0 for an unlit one
+/// The pointer stays valid until chip8 is freed.
+///
+/// # Safety
+/// chip8 must come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_framebuffer(chip8: *const Chip8) -> *const u8 {
+    match chip8.as_ref() {
+        Some(chip8) => chip8.frame.as_ptr(),
+        None => std::ptr::null(),
+    }
+}
+
+/// Whether the buzzer should sound
+///
+/// # Safety
+/// chip8 must come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_sound(chip8: *const Chip8) -> bool {
+    chip8.as_ref().is_some_and(|chip8| chip8.cpu.st() > 0)
+}
+
+/// Writes a snapshot of the machine, `CHIP8_STATE_SIZE` bytes, into out
+///
+/// # Safety
+/// chip8 must come from `chip8_new` and out must point to len writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_save_state(
+    chip8: *const Chip8,
+    out: *mut u8,
+    len: usize,
+) -> Chip8Error {
+    let chip8 = match chip8.as_ref() {
+        Some(chip8) if !out.is_null() => chip8,
+        _ => return Chip8Error::Null,
+    };
+    if len < STATE_SIZE {
+        return Chip8Error::BufferTooSmall;
+    }
+    slice::from_raw_parts_mut(out, STATE_SIZE).copy_from_slice(&chip8.cpu.save_state());
+    Chip8Error::Ok
+}
+
+/// Restores a snapshot from `chip8_save_state`, leaving the machine untouched if it's invalid
+///
+/// # Safety
+/// chip8 must come from `chip8_new` and state must point to len readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_load_state(
+    chip8: *mut Chip8,
+    state: *const u8,
+    len: usize,
+) -> Chip8Error {
+    let chip8 = match chip8.as_mut() {
+        Some(chip8) if !state.is_null() => chip8,
+        _ => return Chip8Error::Null,
+    };
+    let result = chip8.cpu.load_state(slice::from_raw_parts(state, len));
+    chip8.refresh();
+    result.map_or_else(Chip8Error::from, |()| Chip8Error::Ok)
+}
+
+/// Describes an error, as a static NUL-terminated string
+/// Any int is accepted: values that aren't a `Chip8Error`, e.g. from a newer library, are
+/// described as "unknown error".
+#[no_mangle]
+pub extern "C" fn chip8_error_message(error: c_int) -> *const c_char {
+    // Indexed by the Chip8Error values
+    const MESSAGES: [&[u8]; 9] = [
+        b"no error\0",
+        b"null pointer\0",
+        b"buffer too small\0",
+        b"call with a full stack\0",
+        b"return with an empty stack\0",
+        b"memory access out of range\0",
+        b"no such key\0",
+        b"ROM is too large\0",
+        b"invalid save state\0",
+    ];
+    let message = usize::try_from(error)
+        .ok()
+        .and_then(|error| MESSAGES.get(error))
+        .copied()
+        .unwrap_or(b"unknown error\0");
+    message.as_ptr() as *const c_char
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    #[test]
+    fn run_save_and_restore_through_the_c_api() {
+        unsafe {
+            let chip8 = chip8_new(1);
+            // LD V0, 0x0A; LD F, V0; DRW V1, V1, 5; JP 0x206
+            let rom = [0x60, 0x0A, 0xF0, 0x29, 0xD1, 0x15, 0x12, 0x06];
+            assert_eq!(
+                chip8_load_rom(chip8, rom.as_ptr(), rom.len()),
+                Chip8Error::Ok
+            );
+            assert_eq!(chip8_run_frame(chip8), Chip8Error::Ok);
+            let frame = slice::from_raw_parts(chip8_framebuffer(chip8), CHIP8_WIDTH * CHIP8_HEIGHT);
+            assert_eq!(frame[..5], [1, 1, 1, 1, 0]);
+
+            let mut state = [0; CHIP8_STATE_SIZE];
+            assert_eq!(
+                chip8_save_state(chip8, state.as_mut_ptr(), state.len() - 1),
+                Chip8Error::BufferTooSmall
+            );
+            assert_eq!(
+                chip8_save_state(chip8, state.as_mut_ptr(), state.len()),
+                Chip8Error::Ok
+            );
+            assert_eq!(
+                chip8_load_rom(chip8, [0x00, 0xEE].as_ptr(), 2),
+                Chip8Error::Ok
+            );
+            assert_eq!(chip8_run_frame(chip8), Chip8Error::StackUnderflow);
+            assert_eq!(
+                chip8_load_state(chip8, state.as_ptr(), state.len()),
+                Chip8Error::Ok
+            );
+            let frame = slice::from_raw_parts(chip8_framebuffer(chip8), CHIP8_WIDTH * CHIP8_HEIGHT);
+            assert_eq!(frame[..5], [1, 1, 1, 1, 0]);
+            assert_eq!(chip8_key(chip8, 0x10, true), Chip8Error::InvalidKey);
+            chip8_free(chip8);
+        }
+    }
+
+    #[test]
+    fn error_messages_cover_any_int() {
+        let message = |error| unsafe { CStr::from_ptr(chip8_error_message(error)) };
+        assert_eq!(message(Chip8Error::Ok as c_int).to_str(), Ok("no error"));
+        assert_eq!(
+            message(Chip8Error::InvalidState as c_int).to_str(),
+            Ok("invalid save state")
+        );
+        for error in [-1, Chip8Error::InvalidState as c_int + 1, c_int::MAX] {
+            assert_eq!(message(error).to_str(), Ok("unknown error"));
+        }
+    }
+}
@@ -1,4 +1,23 @@
-pub mod cpu;
+pub mod cheat;
+pub use chip_8_core::cpu;
+pub mod debug;
+pub mod engine;
+pub mod env;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "libretro")]
+pub mod libretro;
+pub mod machine;
+pub mod movie;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod render;
+pub mod rom;
+#[cfg(feature = "scripting")]
+pub mod script;
+pub mod settings;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,444 @@
+// libretro core, enabled with the "libretro" feature
+// `cargo build --release --features libretro` produces a core that RetroArch and other frontends
+// load like any other, e.g. `retroarch -L target/release/libchip_8.so pong.ch8`.
+// libretro cores are global by design: a frontend loads one game per core at a time and calls in
+// from one thread, so the machine and the frontend's callbacks live in statics.
+
+use crate::cpu::display::{HEIGHT, WIDTH};
+use crate::cpu::{CPU, STATE_SIZE};
+use crate::render;
+use std::os::raw::{c_char, c_uint, c_void};
+use std::ptr;
+use std::slice;
+use std::sync::Mutex;
+
+const RETRO_API_VERSION: c_uint = 1;
+const RETRO_ENVIRONMENT_SET_PIXEL_FORMAT: c_uint = 10;
+const RETRO_ENVIRONMENT_SET_INPUT_DESCRIPTORS: c_uint = 11;
+const RETRO_PIXEL_FORMAT_XRGB8888: c_uint = 1;
+const RETRO_DEVICE_JOYPAD: c_uint = 1;
+const RETRO_REGION_NTSC: c_uint = 0;
+
+/// Colors as XRGB8888, the same as the native window's
+const ON: u32 = 0x0000_FFFF;
+const OFF: u32 = 0x0033_3333;
+
+const CYCLES_PER_FRAME: u32 = 10;
+const FPS: f64 = 60.0;
+const SAMPLE_RATE: u32 = 44100;
+const SAMPLES_PER_FRAME: usize = SAMPLE_RATE as usize / FPS as usize;
+/// Buzzer pitch in Hz
+const TONE: u32 = 440;
+const VOLUME: i16 = 0x1000;
+
+/// Joypad buttons to CHIP-8 keys, with what the frontend shows for them
+/// The d-pad is 2/4/6/8, the usual directions on the hex keypad, and A is 5, the usual action key.
+const KEYS: [(c_uint, usize, &str); 16] = [
+    (4, 0x2, "Up (2)\0"),
+    (5, 0x8, "Down (8)\0"),
+    (6, 0x4, "Left (4)\0"),
+    (7, 0x6, "Right (6)\0"),
+    (8, 0x5, "5\0"),
+    (0, 0x0, "0\0"),
+    (9, 0x1, "1\0"),
+    (1, 0x3, "3\0"),
+    (10, 0x7, "7\0"),
+    (11, 0x9, "9\0"),
+    (12, 0xA, "A\0"),
+    (13, 0xB, "B\0"),
+    (14, 0xC, "C\0"),
+    (15, 0xD, "D\0"),
+    (2, 0xE, "E\0"),
+    (3, 0xF, "F\0"),
+];
+
+#[repr(C)]
+pub struct RetroSystemInfo {
+    pub library_name: *const c_char,
+    pub library_version: *const c_char,
+    pub valid_extensions: *const c_char,
+    pub need_fullpath: bool,
+    pub block_extract: bool,
+}
+
+#[repr(C)]
+pub struct RetroGameGeometry {
+    pub base_width: c_uint,
+    pub base_height: c_uint,
+    pub max_width: c_uint,
+    pub max_height: c_uint,
+    pub aspect_ratio: f32,
+}
+
+#[repr(C)]
+pub struct RetroSystemTiming {
+    pub fps: f64,
+    pub sample_rate: f64,
+}
+
+#[repr(C)]
+pub struct RetroSystemAvInfo {
+    pub geometry: RetroGameGeometry,
+    pub timing: RetroSystemTiming,
+}
+
+#[repr(C)]
+pub struct RetroGameInfo {
+    pub path: *const c_char,
+    pub data: *const c_void,
+    pub size: usize,
+    pub meta: *const c_char,
+}
+
+#[repr(C)]
+struct RetroInputDescriptor {
+    port: c_uint,
+    device: c_uint,
+    index: c_uint,
+    id: c_uint,
+    description: *const c_char,
+}
+
+pub type RetroEnvironment = extern "C" fn(cmd: c_uint, data: *mut c_void) -> bool;
+pub type RetroVideoRefresh =
+    extern "C" fn(data: *const c_void, width: c_uint, height: c_uint, pitch: usize);
+pub type RetroAudioSample = extern "C" fn(left: i16, right: i16);
+pub type RetroAudioSampleBatch = extern "C" fn(data: *const i16, frames: usize) -> usize;
+pub type RetroInputPoll = extern "C" fn();
+pub type RetroInputState =
+    extern "C" fn(port: c_uint, device: c_uint, index: c_uint, id: c_uint) -> i16;
+
+/// What the frontend hands over through the retro_set_* calls
+struct Callbacks {
+    environment: Option<RetroEnvironment>,
+    video_refresh: Option<RetroVideoRefresh>,
+    audio_sample_batch: Option<RetroAudioSampleBatch>,
+    input_poll: Option<RetroInputPoll>,
+    input_state: Option<RetroInputState>,
+}
+
+static CALLBACKS: Mutex<Callbacks> = Mutex::new(Callbacks {
+    environment: None,
+    video_refresh: None,
+    audio_sample_batch: None,
+    input_poll: None,
+    input_state: None,
+});
+
+/// The loaded game
+struct Core {
+    rom: Vec<u8>,
+    cpu: CPU,
+    /// XRGB8888 pixels, row by row
+    frame: Vec<u32>,
+    /// Interleaved stereo samples for one frame
+    audio: Vec<i16>,
+    /// Where the buzzer's square wave is, in SAMPLE_RATE ticks of TONE
+    phase: u32,
+}
+
+static CORE: Mutex<Option<Core>> = Mutex::new(None);
+
+impl Core {
+    /// Runs one 60Hz frame
+    /// A failing instruction stops the frame there, so a crashed game freezes at it.
+    fn run_frame(&mut self) {
+        for _ in 0..CYCLES_PER_FRAME {
+            if self.cpu.execute_cycle().is_err() {
+                return;
+            }
+        }
+        self.cpu.decrement_timers();
+    }
+
+    /// Redraws the rows that changed since the last paint
+    fn paint(&mut self) {
+        let rows = self.cpu.display.take_dirty();
+        render::scale(&self.cpu.display, rows, &mut self.frame, WIDTH, 1, ON, OFF);
+    }
+
+    /// Fills audio with a frame of square wave while the sound timer runs, silence otherwise
+    fn buzz(&mut self) {
+        let sounding = self.cpu.st() > 0;
+        for sample in self.audio.chunks_mut(2) {
+            let level = if !sounding {
+                0
+            } else if self.phase < SAMPLE_RATE / 2 {
+                VOLUME
+            } else {
+                -VOLUME
+            };
+            sample.fill(level);
+            self.phase = (self.phase + TONE) % SAMPLE_RATE;
+        }
+    }
+}
+
+fn environment(cmd: c_uint, data: *mut c_void) -> bool {
+    let environment = CALLBACKS.lock().unwrap().environment;
+    environment.is_some_and(|environment| environment(cmd, data))
+}
+
+#[no_mangle]
+pub extern "C" fn retro_api_version() -> c_uint {
+    RETRO_API_VERSION
+}
+
+/// # Safety
+/// info must point to a writable `retro_system_info`.
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_info(info: *mut RetroSystemInfo) {
+    if let Some(info) = info.as_mut() {
+        *info = RetroSystemInfo {
+            library_name: b"chip-8\0".as_ptr() as *const c_char,
+            library_version: concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char,
+            valid_extensions: b"ch8|c8\0".as_ptr() as *const c_char,
+            need_fullpath: false,
+            block_extract: false,
+        };
+    }
+}
+
+/// # Safety
+/// info must point to a writable `retro_system_av_info`.
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut RetroSystemAvInfo) {
+    if let Some(info) = info.as_mut() {
+        *info = RetroSystemAvInfo {
+            geometry: RetroGameGeometry {
+                base_width: WIDTH as c_uint,
+                base_height: HEIGHT as c_uint,
+                max_width: WIDTH as c_uint,
+                max_height: HEIGHT as c_uint,
+                aspect_ratio: WIDTH as f32 / HEIGHT as f32,
+            },
+            timing: RetroSystemTiming {
+                fps: FPS,
+                sample_rate: SAMPLE_RATE as f64,
+            },
+        };
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_environment(callback: RetroEnvironment) {
+    CALLBACKS.lock().unwrap().environment = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_video_refresh(callback: RetroVideoRefresh) {
+    CALLBACKS.lock().unwrap().video_refresh = Some(callback);
+}
+
+/// Unused, audio goes through the batch callback
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample(_callback: RetroAudioSample) {}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample_batch(callback: RetroAudioSampleBatch) {
+    CALLBACKS.lock().unwrap().audio_sample_batch = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_poll(callback: RetroInputPoll) {
+    CALLBACKS.lock().unwrap().input_poll = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_state(callback: RetroInputState) {
+    CALLBACKS.lock().unwrap().input_state = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_init() {}
+
+#[no_mangle]
+pub extern "C" fn retro_deinit() {
+    *CORE.lock().unwrap() = None;
+}
+
+/// Every port is a joypad
+#[no_mangle]
+pub extern "C" fn retro_set_controller_port_device(_port: c_uint, _device: c_uint) {}
+
+/// Loads a ROM from memory, the frontend never passes just a path since need_fullpath is false
+/// Cxkk always starts from the same seed so netplay and replays stay in sync.
+///
+/// # Safety
+/// game must point to a `retro_game_info` whose data points to size readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn retro_load_game(game: *const RetroGameInfo) -> bool {
+    let game = match game.as_ref() {
+        Some(game) if !game.data.is_null() => game,
+        _ => return false,
+    };
+    let rom = slice::from_raw_parts(game.data as *const u8, game.size).to_vec();
+
+    let mut format = RETRO_PIXEL_FORMAT_XRGB8888;
+    if !environment(
+        RETRO_ENVIRONMENT_SET_PIXEL_FORMAT,
+        &mut format as *mut c_uint as *mut c_void,
+    ) {
+        return false;
+    }
+    let mut descriptors: Vec<RetroInputDescriptor> = KEYS
+        .iter()
+        .map(|&(id, _, description)| RetroInputDescriptor {
+            port: 0,
+            device: RETRO_DEVICE_JOYPAD,
+            index: 0,
+            id,
+            description: description.as_ptr() as *const c_char,
+        })
+        .collect();
+    // The list ends at an entry with a null description
+    descriptors.push(RetroInputDescriptor {
+        port: 0,
+        device: 0,
+        index: 0,
+        id: 0,
+        description: ptr::null(),
+    });
+    environment(
+        RETRO_ENVIRONMENT_SET_INPUT_DESCRIPTORS,
+        descriptors.as_mut_ptr() as *mut c_void,
+    );
+
+    let mut cpu = CPU::new();
+    cpu.reset();
+    if cpu.load_rom(&rom).is_err() {
+        return false;
+    }
+    let mut core = Core {
+        rom,
+        cpu,
+        frame: vec![OFF; WIDTH * HEIGHT],
+        audio: vec![0; SAMPLES_PER_FRAME * 2],
+        phase: 0,
+    };
+    core.paint();
+    *CORE.lock().unwrap() = Some(core);
+    true
+}
+
+#[no_mangle]
+pub extern "C" fn retro_load_game_special(
+    _game_type: c_uint,
+    _info: *const RetroGameInfo,
+    _num_info: usize,
+) -> bool {
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn retro_unload_game() {
+    *CORE.lock().unwrap() = None;
+}
+
+/// Restarts the loaded ROM
+#[no_mangle]
+pub extern "C" fn retro_reset() {
+    if let Some(core) = CORE.lock().unwrap().as_mut() {
+        core.cpu.reset();
+        // It loaded once, so it fits
+        core.cpu.load_rom(&core.rom).unwrap();
+        core.paint();
+    }
+}
+
+/// Reads the joypad, runs a frame and hands the frontend its picture and sound
+#[no_mangle]
+pub extern "C" fn retro_run() {
+    let callbacks = CALLBACKS.lock().unwrap();
+    let mut core = CORE.lock().unwrap();
+    let core = match core.as_mut() {
+        Some(core) => core,
+        None => return,
+    };
+
+    if let Some(input_poll) = callbacks.input_poll {
+        input_poll();
+    }
+    if let Some(input_state) = callbacks.input_state {
+        for &(id, key, _) in KEYS.iter() {
+            if input_state(0, RETRO_DEVICE_JOYPAD, 0, id) != 0 {
+                core.cpu.keyboard.key_down(key);
+            } else {
+                core.cpu.keyboard.key_up(key);
+            }
+        }
+    }
+
+    core.run_frame();
+    core.paint();
+    core.buzz();
+
+    if let Some(video_refresh) = callbacks.video_refresh {
+        video_refresh(
+            core.frame.as_ptr() as *const c_void,
+            WIDTH as c_uint,
+            HEIGHT as c_uint,
+            WIDTH * 4,
+        );
+    }
+    if let Some(audio_sample_batch) = callbacks.audio_sample_batch {
+        audio_sample_batch(core.audio.as_ptr(), SAMPLES_PER_FRAME);
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_serialize_size() -> usize {
+    STATE_SIZE
+}
+
+/// # Safety
+/// data must point to size writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn retro_serialize(data: *mut c_void, size: usize) -> bool {
+    match CORE.lock().unwrap().as_ref() {
+        Some(core) if !data.is_null() && size >= STATE_SIZE => {
+            slice::from_raw_parts_mut(data as *mut u8, STATE_SIZE)
+                .copy_from_slice(&core.cpu.save_state());
+            true
+        }
+        _ => false,
+    }
+}
+
+/// # Safety
+/// data must point to size readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn retro_unserialize(data: *const c_void, size: usize) -> bool {
+    match CORE.lock().unwrap().as_mut() {
+        Some(core) if !data.is_null() => {
+            let state = slice::from_raw_parts(data as *const u8, size.min(STATE_SIZE));
+            let loaded = core.cpu.load_state(state).is_ok();
+            core.paint();
+            loaded
+        }
+        _ => false,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_reset() {}
+
+/// Frontend cheats aren't supported
+#[no_mangle]
+pub extern "C" fn retro_cheat_set(_index: c_uint, _enabled: bool, _code: *const c_char) {}
+
+#[no_mangle]
+pub extern "C" fn retro_get_region() -> c_uint {
+    RETRO_REGION_NTSC
+}
+
+/// No memory is exposed to the frontend
+#[no_mangle]
+pub extern "C" fn retro_get_memory_data(_id: c_uint) -> *mut c_void {
+    ptr::null_mut()
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_size(_id: c_uint) -> usize {
+    0
+}
@@ -0,0 +1,280 @@
+// Run controller: pause, frame advance and speed for frontends that call in once per host frame
+// The frontend calls `tick` every time it redraws, 60 times a second unless fast-forwarding, and
+// runs an emulated frame whenever it says one is due.
+
+use crate::cpu::hook::Hook;
+use crate::cpu::{Error, CPU};
+use crate::engine::Engine;
+
+/// How fast the machine runs compared to real time
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Speed {
+    Quarter,
+    Half,
+    Normal,
+    /// As fast as `tick` is called, the frontend stops throttling its host frames
+    FastForward,
+}
+
+impl Speed {
+    /// Host frames per emulated frame
+    fn divider(self) -> u32 {
+        match self {
+            Speed::Quarter => 4,
+            Speed::Half => 2,
+            Speed::Normal | Speed::FastForward => 1,
+        }
+    }
+
+    /// Short text for an on-screen indicator, None at normal speed
+    pub fn label(self) -> Option<&'static str> {
+        match self {
+            Speed::Quarter => Some("0.25X"),
+            Speed::Half => Some("0.5X"),
+            Speed::Normal => None,
+            Speed::FastForward => Some("FAST"),
+        }
+    }
+
+    /// The slow-motion cycle: normal, half, quarter, back to normal
+    pub fn slower(self) -> Speed {
+        match self {
+            Speed::Normal | Speed::FastForward => Speed::Half,
+            Speed::Half => Speed::Quarter,
+            Speed::Quarter => Speed::Normal,
+        }
+    }
+}
+
+/// A machine and when to run it
+pub struct Machine {
+    pub cpu: CPU,
+    /// Instructions per second
+    ips: u32,
+    /// Instructions per second that didn't make a whole instruction in the last frame, in 60ths
+    carry: u32,
+    paused: bool,
+    /// A frame was asked for while paused
+    advance: bool,
+    speed: Speed,
+    /// Host frames since the last emulated frame, for slow motion
+    waited: u32,
+    /// Runs the instructions of `run_frame`
+    engine: Engine,
+}
+
+impl Machine {
+    pub fn new(cpu: CPU, ips: u32) -> Machine {
+        Machine {
+            cpu,
+            ips,
+            carry: 0,
+            paused: false,
+            advance: false,
+            speed: Speed::Normal,
+            waited: 0,
+            engine: Engine::Interpreter,
+        }
+    }
+
+    pub fn engine(&self) -> &Engine {
+        &self.engine
+    }
+
+    /// Has `run_frame` execute through engine, `run_frame_with` interprets regardless
+    pub fn set_engine(&mut self, engine: Engine) {
+        self.engine = engine;
+    }
+
+    /// Call after changing the CPU's memory other than through an instruction, see
+    /// `Engine::flush`
+    pub fn flush(&mut self) {
+        self.engine.flush();
+    }
+
+    /// `flush` for a single byte, e.g. after `CPU::poke`
+    pub fn invalidate(&mut self, address: u16) {
+        let address = address as usize & 0xFFF;
+        self.engine.invalidate(address, address + 1);
+    }
+
+    pub fn ips(&self) -> u32 {
+        self.ips
+    }
+
+    pub fn set_ips(&mut self, ips: u32) {
+        self.ips = ips;
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        self.advance = false;
+        self.waited = 0;
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.set_paused(!self.paused);
+    }
+
+    /// Pauses, and has the next `tick` run exactly one frame
+    pub fn advance_frame(&mut self) {
+        self.paused = true;
+        self.advance = true;
+    }
+
+    pub fn speed(&self) -> Speed {
+        self.speed
+    }
+
+    pub fn set_speed(&mut self, speed: Speed) {
+        self.speed = speed;
+        self.waited = 0;
+    }
+
+    /// Whether the frontend should hold its host frames to 60 a second
+    pub fn throttled(&self) -> bool {
+        self.paused || self.speed != Speed::FastForward
+    }
+
+    /// What an on-screen indicator should show, None while running at normal speed
+    pub fn status(&self) -> Option<&'static str> {
+        if self.paused {
+            Some("PAUSED")
+        } else {
+            self.speed.label()
+        }
+    }
+
+    /// Called once per host frame, returns whether an emulated frame is due
+    pub fn tick(&mut self) -> bool {
+        if self.paused {
+            let advance = self.advance;
+            self.advance = false;
+            return advance;
+        }
+        self.waited += 1;
+        if self.waited < self.speed.divider() {
+            return false;
+        }
+        self.waited = 0;
+        true
+    }
+
+    /// Instructions for a due frame, spreading the instructions per second over 60 frames
+    pub fn cycles(&mut self) -> u32 {
+        self.carry += self.ips;
+        let cycles = self.carry / 60;
+        self.carry %= 60;
+        cycles
+    }
+
+    /// Runs a due frame: its instructions, then a timer tick
+    /// On an error the machine pauses at the failing instruction, as it was before it ran.
+    pub fn run_frame(&mut self) -> Result<(), Error> {
+        let cycles = self.cycles();
+        if let Err(error) = self.engine.run(&mut self.cpu, cycles as u64) {
+            self.set_paused(true);
+            return Err(error);
+        }
+        self.cpu.decrement_timers();
+        Ok(())
+    }
+
+    /// `run_frame`, calling hook around every instruction
+    /// Hooks need to see every instruction, so this always interprets, whatever the engine.
+    pub fn run_frame_with<H: Hook>(&mut self, hook: &mut H) -> Result<(), Error> {
+        for _ in 0..self.cycles() {
+            if let Err(error) = self.cpu.execute_cycle_with(hook) {
+                self.set_paused(true);
+                return Err(error);
+            }
+        }
+        self.cpu.decrement_timers();
+        Ok(())
+    }
+
+    /// `tick`, then `run_frame` if a frame is due, returns whether one ran
+    pub fn update(&mut self) -> Result<bool, Error> {
+        if !self.tick() {
+            return Ok(false);
+        }
+        self.run_frame().map(|()| true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Counts up V0 forever: ADD V0, 1; JP 0x200
+    fn counter(ips: u32) -> Machine {
+        let mut cpu = CPU::new();
+        cpu.reset();
+        cpu.load_rom(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        Machine::new(cpu, ips)
+    }
+
+    #[test]
+    fn pause_advance_and_speed() {
+        let mut machine = counter(90);
+        // 90 instructions a second is one and a half a frame
+        let ran: Vec<_> = (0..4).map(|_| machine.cycles()).collect();
+        assert_eq!(ran, [1, 2, 1, 2]);
+
+        machine.toggle_pause();
+        assert_eq!(machine.status(), Some("PAUSED"));
+        assert!(!machine.tick() && !machine.tick());
+        machine.advance_frame();
+        assert!(machine.tick());
+        assert!(!machine.tick());
+        assert!(machine.paused());
+
+        machine.toggle_pause();
+        machine.set_speed(Speed::Normal.slower().slower());
+        assert_eq!(machine.status(), Some("0.25X"));
+        let due: Vec<_> = (0..8).map(|_| machine.tick()).collect();
+        assert_eq!(due.iter().filter(|&&due| due).count(), 2);
+        assert!(machine.throttled());
+        machine.set_speed(Speed::FastForward);
+        assert!(!machine.throttled() && machine.tick());
+    }
+
+    #[test]
+    fn errors_pause_the_machine() {
+        let mut machine = counter(600);
+        assert_eq!(machine.update(), Ok(true));
+        assert_eq!(machine.cpu.cycles(), 10);
+
+        // RET with nothing to return to
+        machine.cpu.poke(0x200, 0x00);
+        machine.cpu.poke(0x201, 0xEE);
+        machine.cpu.set_pc(0x200);
+        assert_eq!(machine.update(), Err(Error::StackUnderflow));
+        assert!(machine.paused());
+        assert_eq!(machine.cpu.pc(), 0x200);
+        assert_eq!(machine.update(), Ok(false));
+    }
+
+    #[test]
+    fn frames_run_through_the_engine() {
+        for name in Engine::NAMES.iter() {
+            let mut machine = counter(600);
+            machine.set_engine(Engine::from_name(name).unwrap());
+            assert_eq!(machine.update(), Ok(true), "{}", name);
+            assert_eq!(machine.cpu.cycles(), 10, "{}", name);
+            assert_eq!(machine.cpu.v()[0], 5, "{}", name);
+
+            machine.cpu.poke(0x200, 0x00);
+            machine.cpu.poke(0x201, 0xEE);
+            machine.invalidate(0x200);
+            machine.invalidate(0x201);
+            machine.cpu.set_pc(0x200);
+            assert_eq!(machine.update(), Err(Error::StackUnderflow), "{}", name);
+            assert_eq!(machine.cpu.pc(), 0x200, "{}", name);
+        }
+    }
+}
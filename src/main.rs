@@ -1,117 +1,515 @@
-use chip_8::cpu::display::{HEIGHT, WIDTH};
-use minifb::{Key, KeyRepeat, Window, WindowOptions};
-fn main() {
-    // TODO: Check that arguments were passed correctly
-    let game_path = std::env::args().nth(1).unwrap();
-
-    let mut cpu = chip_8::cpu::CPU::new();
-    cpu.reset();
+// The native frontend; in the browser src/wasm.rs takes its place, so wasm32 builds, e.g. for
+// `wasm-pack test`, get an empty binary
+#![cfg_attr(target_arch = "wasm32", no_main)]
+#![cfg(not(target_arch = "wasm32"))]
 
-    let rom = std::fs::read(std::path::Path::new(&game_path)).unwrap();
+mod cli;
+mod overlay;
 
-    cpu.load_rom(&rom);
+use chip_8::cheat::Cheats;
+use chip_8::cpu::display::{ALL_ROWS, HEIGHT, WIDTH};
+use chip_8::cpu::hook::Hook;
+use chip_8::cpu::CPU;
+use chip_8::debug::coverage::Coverage;
+use chip_8::debug::profile::Profiler;
+use chip_8::debug::trace::{Filter, Tracer};
+use chip_8::engine::Engine;
+use chip_8::machine::{Machine, Speed};
+use chip_8::movie::Movie;
+use chip_8::rom;
+use chip_8::settings::{Layer, Settings};
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser};
+use cli::{Cli, Command, RunArgs};
+use minifb::{Key, KeyRepeat, Scale, Window, WindowOptions};
+use overlay::{Overlay, PANEL_WIDTH};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
 
-    const COLOR: u32 = 0x00FFFF;
-    const NO_COLOR: u32 = 0x333333;
-    const SCALE: usize = 15;
-    const SCREEN_WIDTH: usize = WIDTH * SCALE;
-    const SCREEN_HEIGHT: usize = HEIGHT * SCALE;
-    let mut buffer: Vec<u32> = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT];
-
-    let mut window = Window::new(
-        "Chip 8 Emulator",
-        SCREEN_WIDTH,
-        SCREEN_HEIGHT,
-        WindowOptions::default(),
-    )
-    .unwrap_or_else(|e| {
+/// Opens the emulator window, wide enough for the debug panel if it is shown
+fn open_window(width: usize, height: usize, fullscreen: bool) -> Window {
+    let options = if fullscreen {
+        WindowOptions {
+            borderless: true,
+            scale: Scale::FitScreen,
+            ..WindowOptions::default()
+        }
+    } else {
+        WindowOptions::default()
+    };
+    let mut window = Window::new("Chip 8 Emulator", width, height, options).unwrap_or_else(|e| {
         panic!("{}", e);
     });
 
     // Limit to max ~60 fps update rate
     // window.limit_update_rate(Some(std::time::Duration::from_micros(5000)));
     window.limit_update_rate(Some(std::time::Duration::from_millis(1000 / 60)));
+    window
+}
+
+/// Looks up a key for the keymap by the name of its minifb `Key` variant
+/// Escape and F1-F5, the debugger and run controls, keep their own jobs so they can't be mapped.
+fn key_named(name: &str) -> Option<Key> {
+    macro_rules! keys {
+        ($($key:ident)*) => {
+            match name {
+                $(stringify!($key) => Some(Key::$key),)*
+                _ => None,
+            }
+        };
+    }
+    keys!(
+        Key0 Key1 Key2 Key3 Key4 Key5 Key6 Key7 Key8 Key9
+        A B C D E F G H I J K L M N O P Q R S T U V W X Y Z
+        F6 F7 F8 F9 F10 F11 F12
+        Up Down Left Right
+        Apostrophe Backquote Backslash Comma Equal LeftBracket Minus Period RightBracket
+        Semicolon Slash Backspace Delete End Enter Home Insert PageDown PageUp Space Tab
+        LeftShift RightShift LeftCtrl RightCtrl LeftAlt RightAlt
+        NumPad0 NumPad1 NumPad2 NumPad3 NumPad4 NumPad5 NumPad6 NumPad7 NumPad8 NumPad9
+        NumPadDot NumPadSlash NumPadAsterisk NumPadMinus NumPadPlus NumPadEnter
+    )
+}
+
+/// Exits with a usage-style error
+fn fail<E: fmt::Display>(kind: ErrorKind, error: E) -> ! {
+    Cli::command().error(kind, error).exit()
+}
+
+/// The kind of error to exit with for a file that couldn't be loaded: a bad value when the
+/// file was read but its contents are invalid, an I/O error otherwise
+fn load_error(error: &io::Error) -> ErrorKind {
+    match error.kind() {
+        io::ErrorKind::InvalidData | io::ErrorKind::InvalidInput => ErrorKind::ValueValidation,
+        _ => ErrorKind::Io,
+    }
+}
+
+fn read_rom(path: &Path) -> Vec<u8> {
+    rom::load(path).unwrap_or_else(|e| fail(load_error(&e), format!("{}: {}", path.display(), e)))
+}
+
+/// Runs a due frame, through the debugging tools' hook if they're on and the engine otherwise,
+/// describing an error with where it happened
+fn run_frame<H: Hook>(machine: &mut Machine, hook: Option<&mut H>) -> Result<(), String> {
+    let result = match hook {
+        Some(hook) => machine.run_frame_with(hook),
+        None => machine.run_frame(),
+    };
+    result.map_err(|e| format!("{} at {:#05x}", e, machine.cpu.pc()))
+}
+
+fn main() {
+    let cli = Cli::parse();
+    match cli.into_command() {
+        Command::Run(args) => run(*args),
+        Command::Disasm { rom } => {
+            let data = read_rom(&rom);
+            rom::write_disassembly(&mut io::stdout().lock(), &data)
+                .unwrap_or_else(|e| fail(ErrorKind::Io, e));
+        }
+        Command::Info { rom } => {
+            let data = read_rom(&rom);
+            let cheats = Cheats::path_for(&cheat_dir(&rom), &data);
+            println!("file    {}", rom.display());
+            println!(
+                "size    {} bytes, {} free",
+                data.len(),
+                rom::MAX_SIZE - data.len()
+            );
+            println!("crc32   {:08X}", rom::hash(&data));
+            if cheats.exists() {
+                println!("cheats  {}", cheats.display());
+            } else {
+                println!("cheats  none, would be {}", cheats.display());
+            }
+        }
+        Command::Completions { shell } => {
+            clap_complete::generate(shell, &mut Cli::command(), "chip-8", &mut io::stdout());
+        }
+    }
+}
+
+/// Writes a debugging tool's output to path on exit, reporting rather than failing on an error
+fn save<F>(path: &Path, write: F)
+where
+    F: FnOnce(&mut dyn Write) -> io::Result<()>,
+{
+    let result = File::create(path).and_then(|file| {
+        let mut out = BufWriter::new(file);
+        write(&mut out)?;
+        out.flush()
+    });
+    if let Err(e) = result {
+        eprintln!("{}: {}", path.display(), e);
+    }
+}
+
+/// Cheats live in a "cheats" directory next to the ROM, see src/cheat.rs
+fn cheat_dir(rom: &Path) -> std::path::PathBuf {
+    rom.parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join("cheats")
+}
+
+fn run(args: RunArgs) {
+    let rom = read_rom(&args.rom);
+
+    // Defaults, then the config file and its sections for this ROM, then the command line
+    let config_path = args.config.clone().or_else(Settings::config_path);
+    let mut settings = match &config_path {
+        Some(path) => {
+            Settings::load(path, &args.rom, &rom).unwrap_or_else(|e| fail(load_error(&e), e))
+        }
+        None => Settings::default(),
+    };
+    let command_line = Layer {
+        scale: args.scale,
+        palette: args.palette.map(|palette| palette.to_string()),
+        quirks: args.quirks.clone(),
+        ips: args.ips,
+        mute: if args.mute { Some(true) } else { None },
+        ..Layer::default()
+    };
+    settings
+        .apply(&command_line, "command line")
+        .unwrap_or_else(|e| fail(ErrorKind::InvalidValue, e));
+
+    // A replay brings the settings it was recorded with
+    let replay = args.replay.as_ref().map(|path| {
+        let movie = Movie::load(path)
+            .unwrap_or_else(|e| fail(load_error(&e), format!("{}: {}", path.display(), e)));
+        if movie.rom_hash != rom::hash(&rom) {
+            fail(
+                ErrorKind::ValueValidation,
+                format!(
+                    "{}: was recorded on a different ROM than {}",
+                    path.display(),
+                    args.rom.display()
+                ),
+            );
+        }
+        let recorded = Layer {
+            quirks: Some(movie.quirks.clone()),
+            ips: Some(movie.ips),
+            ..Layer::default()
+        };
+        let origin = format!("--replay {}", path.display());
+        settings
+            .apply(&recorded, &origin)
+            .unwrap_or_else(|e| fail(ErrorKind::ValueValidation, e));
+        movie
+    });
+
+    // Key names are checked here, the library doesn't know the window's keys
+    let mut keymap = Vec::new();
+    for (name, &key) in settings.keymap.iter() {
+        match key_named(name) {
+            Some(host) => keymap.push((host, key as usize)),
+            None => fail(
+                ErrorKind::ValueValidation,
+                format!(
+                    "{}: no key named {} to map",
+                    settings.origin(&format!("keymap.{}", name)),
+                    name
+                ),
+            ),
+        }
+    }
+
+    if args.print_config {
+        match &config_path {
+            Some(path) if path.exists() => println!("# Config file {}", path.display()),
+            Some(path) => println!("# No config file at {}", path.display()),
+            None => println!("# No config file, neither XDG_CONFIG_HOME nor HOME is set"),
+        }
+        print!("{}", settings.to_toml());
+        return;
+    }
+
+    let seed = match &replay {
+        Some(movie) => movie.seed,
+        None => args.seed.unwrap_or_else(rand::random),
+    };
+    let ips = settings.ips;
+    let mut recording = args.record.as_ref().map(|_| Movie {
+        rom_hash: rom::hash(&rom),
+        seed,
+        quirks: settings.quirks_profile.clone(),
+        ips,
+        frames: Vec::new(),
+    });
+
+    let mut cpu = CPU::new();
+    cpu.reset();
+    cpu.seed(seed);
+    cpu.quirks = settings.quirks;
+    // read_rom checked that it fits
+    cpu.load_rom(&rom).unwrap();
+
+    let cheat_path = Cheats::path_for(&cheat_dir(&args.rom), &rom);
+    let mut cheats = if cheat_path.exists() {
+        Cheats::load(&cheat_path)
+            .unwrap_or_else(|e| fail(load_error(&e), format!("{}: {}", cheat_path.display(), e)))
+    } else {
+        Cheats::new()
+    };
+    if cheat_path.exists() && (replay.is_some() || recording.is_some()) {
+        eprintln!(
+            "{}: cheats are off while a movie records or plays",
+            cheat_path.display()
+        );
+    }
+
+    // Optional Rhai script to automate the game, see src/script.rs
+    #[cfg(feature = "scripting")]
+    let mut script = args.script.as_ref().map(|path| {
+        chip_8::script::Script::load(path, &mut cpu).unwrap_or_else(|e| {
+            fail(
+                ErrorKind::ValueValidation,
+                format!("{}: {}", path.display(), e),
+            )
+        })
+    });
+
+    let mut machine = Machine::new(cpu, settings.ips);
+    if let Some(name) = &args.engine {
+        // clap checked the name
+        machine.set_engine(Engine::from_name(name).unwrap());
+    }
+    // The debugging tools watch every instruction, see src/debug
+    let tracer = args.trace.as_ref().map(|path| {
+        let file = File::create(path)
+            .unwrap_or_else(|e| fail(ErrorKind::Io, format!("{}: {}", path.display(), e)));
+        let out = Box::new(BufWriter::new(file));
+        let filter = Filter {
+            pc: args.trace_pc.clone(),
+            families: args.trace_family.clone(),
+            frames: args.trace_frames.clone(),
+        };
+        match args.trace_last {
+            Some(last) => Tracer::ring_buffer(out, filter, last),
+            None => Tracer::new(out, filter),
+        }
+    });
+    let profiler = args.profile.as_ref().map(|_| Profiler::new());
+    let coverage = args.coverage.as_ref().map(|_| Coverage::new());
+    let watching = tracer.is_some() || profiler.is_some() || coverage.is_some();
+    let mut tools = (tracer, (profiler, coverage));
+    let scale = settings.scale as usize;
+    let screen_width = WIDTH * scale;
+    let screen_height = HEIGHT * scale;
+    let palette = settings.palette;
+
+    let mut overlay = Overlay::new();
+    let mut stride = if overlay.visible {
+        screen_width + PANEL_WIDTH
+    } else {
+        screen_width
+    };
+    let mut window = open_window(stride, screen_height, args.fullscreen);
+    let mut throttled = true;
+    let mut buffer: Vec<u32> = vec![0; screen_width * screen_height];
+    let mut frame = 0;
+    let mut sounding = false;
+    let mut shown_status = None;
 
     while window.is_open() && !window.is_key_down(Key::Escape) {
-        // Get input
-        for &key in window.get_keys_released().unwrap_or(vec![]).iter() {
-            let btn: usize = match key {
-                Key::Key1 => 1,
-                Key::Key2 => 2,
-                Key::Key3 => 3,
-                Key::Key4 => 0xC,
-                Key::Q => 4,
-                Key::W => 5,
-                Key::F => 6,
-                Key::P => 0xD,
-                Key::A => 7,
-                Key::R => 8,
-                Key::S => 9,
-                Key::T => 0xE,
-                Key::Z => 0xA,
-                Key::X => 0,
-                Key::C => 0xB,
-                Key::V => 0xF,
-                _ => 16,
+        #[cfg(feature = "scripting")]
+        if script.as_ref().is_some_and(|script| script.stopped()) {
+            break;
+        }
+
+        // Debugger hotkeys
+        if window.is_key_pressed(Key::F1, KeyRepeat::No) {
+            overlay.visible = !overlay.visible;
+            stride = if overlay.visible {
+                screen_width + PANEL_WIDTH
+            } else {
+                screen_width
+            };
+            window = open_window(stride, screen_height, args.fullscreen);
+            throttled = true;
+        }
+        // Run controls
+        if window.is_key_pressed(Key::F2, KeyRepeat::No) {
+            machine.toggle_pause();
+            overlay.pausing(&machine.cpu);
+        }
+        if window.is_key_pressed(Key::F3, KeyRepeat::Yes) {
+            if !machine.paused() {
+                overlay.pausing(&machine.cpu);
+            }
+            machine.advance_frame();
+        }
+        if window.is_key_pressed(Key::F4, KeyRepeat::No) {
+            machine.set_speed(machine.speed().slower());
+        }
+        if window.is_key_pressed(Key::F5, KeyRepeat::No) {
+            let speed = if machine.speed() == Speed::FastForward {
+                Speed::Normal
+            } else {
+                Speed::FastForward
             };
-            if btn <= 0xF_usize {
-                cpu.keyboard.key_up(btn);
+            machine.set_speed(speed);
+        }
+        if machine.throttled() != throttled {
+            throttled = machine.throttled();
+            let rate = std::time::Duration::from_millis(1000 / 60);
+            window.limit_update_rate(if throttled { Some(rate) } else { None });
+        }
+        // The old frame is laid out for the old stride, so it has to be redrawn in full
+        let resized = buffer.len() != stride * screen_height;
+        buffer.resize(stride * screen_height, 0);
+
+        // Get input, unless a movie is playing it
+        let replaying = replay
+            .as_ref()
+            .is_some_and(|movie| frame < movie.frames.len());
+        // A movie only holds the keypad, so nothing else may change memory while it runs
+        let in_movie = replaying || recording.is_some();
+        overlay.locked = in_movie;
+        for &key in window.get_keys_released().unwrap_or_default().iter() {
+            for &(_, btn) in keymap.iter().filter(|&&(host, _)| host == key) {
+                if !replaying {
+                    machine.cpu.keyboard.key_up(btn);
+                }
             }
         }
 
         for &key in window
             .get_keys_pressed(KeyRepeat::Yes)
-            .unwrap_or(vec![])
+            .unwrap_or_default()
             .iter()
         {
-            let btn: usize = match key {
-                Key::Key1 => 1,
-                Key::Key2 => 2,
-                Key::Key3 => 3,
-                Key::Key4 => 0xC,
-                Key::Q => 4,
-                Key::W => 5,
-                Key::F => 6,
-                Key::P => 0xD,
-                Key::A => 7,
-                Key::R => 8,
-                Key::S => 9,
-                Key::T => 0xE,
-                Key::Z => 0xA,
-                Key::X => 0,
-                Key::C => 0xB,
-                Key::V => 0xF,
-                _ => 16,
-            };
-            if btn <= 0xF_usize {
-                println!("{}", btn);
-                cpu.keyboard.key_down(btn);
+            let paused = machine.paused();
+            if overlay.handle_key(key, &mut machine.cpu, paused) {
+                // The memory editor may have changed code
+                machine.flush();
+                continue;
+            }
+            for &(_, btn) in keymap.iter().filter(|&&(host, _)| host == key) {
+                if !replaying {
+                    machine.cpu.keyboard.key_down(btn);
+                }
             }
         }
 
         // Update game
-        cpu.execute_cycle();
-        cpu.decrement_timers();
+        if machine.tick() {
+            if let Some(movie) = &replay {
+                movie.play(frame, &mut machine.cpu.keyboard);
+            }
+            if let Some(movie) = &mut recording {
+                movie.record(&machine.cpu.keyboard);
+            }
+            frame += 1;
 
-        // Draw pixels
-        for (i, &val) in cpu.display.screen_buffer().iter().enumerate() {
-            for r in 0..SCALE {
-                let row_offset = ((i / WIDTH) * SCALE + r) * SCREEN_WIDTH;
-                let col_start = (i % WIDTH) * SCALE;
-                let col_end = (i % WIDTH) * SCALE + SCALE;
-                buffer[row_offset + col_start..row_offset + col_end].copy_from_slice(if val == 1 {
-                    &[COLOR; SCALE]
-                } else {
-                    &[NO_COLOR; SCALE]
-                });
+            #[cfg(feature = "scripting")]
+            let result = match &mut script {
+                // Always interpreted: clap rejects --script with --engine
+                Some(script) => {
+                    let cycles = machine.cycles();
+                    let cpu = &mut machine.cpu;
+                    let result = (0..cycles)
+                        .try_for_each(|_| script.execute_cycle_with(cpu, &mut tools))
+                        .and_then(|()| {
+                            cpu.decrement_timers();
+                            script.end_frame(cpu)
+                        });
+                    if result.is_err() {
+                        machine.set_paused(true);
+                    }
+                    result.map_err(|e| e.to_string())
+                }
+                None => run_frame(&mut machine, watching.then_some(&mut tools)),
+            };
+            #[cfg(not(feature = "scripting"))]
+            let result = run_frame(&mut machine, watching.then_some(&mut tools));
+            if !in_movie {
+                for address in cheats.apply(&mut machine.cpu) {
+                    machine.invalidate(address);
+                }
             }
+
+            // The machine stops rather than crash, so the state can be inspected in the debugger
+            if let Err(error) = result {
+                eprintln!("{}", error);
+                overlay.pausing(&machine.cpu);
+            }
+        }
+
+        // The buzzer is the terminal bell, rung as the sound timer starts
+        let cpu = &mut machine.cpu;
+        if cpu.st() > 0 && !sounding && !settings.mute {
+            eprint!("\x07");
+            io::stderr().flush().ok();
+        }
+        sounding = cpu.st() > 0;
+
+        // Draw pixels
+        // Only the rows that changed since the last frame, or all of them to clear the status
+        let mut rows = cpu.display.take_dirty();
+        let status = machine.status();
+        if resized || status != shown_status {
+            rows = ALL_ROWS;
+            shown_status = status;
+        }
+        let cpu = &machine.cpu;
+        chip_8::render::scale(
+            &cpu.display,
+            rows,
+            &mut buffer,
+            stride,
+            scale,
+            palette.on,
+            palette.off,
+        );
+        if let Some(status) = status {
+            overlay::draw_status(status, &mut buffer, stride, screen_width);
+        }
+        if overlay.visible {
+            overlay.draw(cpu, machine.paused(), &mut buffer, stride, screen_width);
         }
 
         // NOTE: Keys assume QWERTY layout! Changing to Colemak doesn't change this!
 
         // We unwrap here as we want this code to exit if it fails. Real applications may want to handle this in a different way
         window
-            .update_with_buffer(&buffer, SCREEN_WIDTH, SCREEN_HEIGHT)
+            .update_with_buffer(&buffer, stride, screen_height)
             .unwrap();
     }
+
+    let (mut tracer, (profiler, coverage)) = tools;
+    if let (Some(path), Some(tracer)) = (&args.trace, &mut tracer) {
+        // A ring buffer that saw no errors stays unwritten
+        if let Err(e) = tracer.finish() {
+            eprintln!("{}: {}", path.display(), e);
+        }
+    }
+    if let (Some(path), Some(profiler)) = (&args.profile, &profiler) {
+        save(path, |out| {
+            match path.extension().and_then(|e| e.to_str()) {
+                Some("json") => profiler.write_json(out),
+                Some("folded") => profiler.write_folded(out),
+                _ => profiler.write_report(out, &machine.cpu, 20),
+            }
+        });
+    }
+    if let (Some(path), Some(coverage)) = (&args.coverage, &coverage) {
+        save(path, |out| coverage.write_listing(out, &rom));
+        let mut lcov = path.clone().into_os_string();
+        lcov.push(".info");
+        let name = path.display().to_string();
+        save(Path::new(&lcov), |out| {
+            coverage.write_lcov(out, &rom, &name)
+        });
+    }
+    if let (Some(path), Some(movie)) = (&args.record, &recording) {
+        if let Err(e) = movie.save(path) {
+            eprintln!("{}: {}", path.display(), e);
+        }
+    }
 }
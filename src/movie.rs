@@ -0,0 +1,91 @@
+// Input movies: the keypad state of every frame of a session, for replaying it exactly
+// A run is deterministic given the ROM, the seed, the quirks and the instructions per second, so
+// those are recorded alongside the keys.
+
+use crate::cpu::keyboard::Keyboard;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Movie {
+    /// `rom::hash` of the ROM it was recorded on
+    pub rom_hash: u32,
+    pub seed: u64,
+    /// Quirk profile, see `Quirks::profile`
+    pub quirks: String,
+    /// Instructions per second
+    pub ips: u32,
+    /// Held keys of each frame, bit n for key n
+    pub frames: Vec<u16>,
+}
+
+impl Movie {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Movie> {
+        let text = fs::read_to_string(path)?;
+        serde_json::from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, serde_json::to_string(self)?)
+    }
+
+    /// Appends a frame with the keys held on keyboard
+    pub fn record(&mut self, keyboard: &Keyboard) {
+        let keys = keyboard.keys();
+        let mask = (0..16).fold(0, |mask, key| mask | (keys[key] as u16) << key);
+        self.frames.push(mask);
+    }
+
+    /// Sets keyboard to the keys of frame, returns false once the movie has run out
+    pub fn play(&self, frame: usize, keyboard: &mut Keyboard) -> bool {
+        let mask = match self.frames.get(frame) {
+            Some(&mask) => mask,
+            None => return false,
+        };
+        for key in 0..16 {
+            if mask & 1 << key != 0 {
+                keyboard.key_down(key);
+            } else {
+                keyboard.key_up(key);
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_saves_and_plays_back() {
+        let mut keyboard = Keyboard::new();
+        let mut movie = Movie {
+            rom_hash: 0x1234_5678,
+            seed: 7,
+            quirks: "schip".to_string(),
+            ips: 600,
+            frames: Vec::new(),
+        };
+        movie.record(&keyboard);
+        keyboard.key_down(0x1);
+        keyboard.key_down(0xF);
+        movie.record(&keyboard);
+        assert_eq!(movie.frames, [0, 0x8002]);
+
+        let path = std::env::temp_dir().join(format!("chip8-movie-{}.json", std::process::id()));
+        movie.save(&path).unwrap();
+        let loaded = Movie::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, movie);
+
+        let mut keyboard = Keyboard::new();
+        assert!(loaded.play(1, &mut keyboard));
+        assert!(keyboard.key_pressed(0x1) && keyboard.key_pressed(0xF) && !keyboard.key_pressed(0));
+        assert!(loaded.play(0, &mut keyboard));
+        assert_eq!(keyboard.keys(), [false; 16]);
+        assert!(!loaded.play(2, &mut keyboard));
+    }
+}
@@ -0,0 +1,446 @@
+use chip_8::cheat::{Comparison, Search};
+use chip_8::cpu::CPU;
+use minifb::Key;
+
+/// Width in pixels of the debug side panel
+pub const PANEL_WIDTH: usize = 440;
+
+const GLYPH_SCALE: usize = 2;
+/// Glyphs are 3x5 pixels, drawn at GLYPH_SCALE with one pixel column/row of spacing
+const CELL_WIDTH: usize = 4 * GLYPH_SCALE;
+const CELL_HEIGHT: usize = 6 * GLYPH_SCALE;
+const MARGIN: usize = 8;
+
+const BACKGROUND: u32 = 0x1E1E1E;
+const TEXT: u32 = 0xCCCCCC;
+const DIM: u32 = 0x666666;
+const PC_HIGHLIGHT: u32 = 0x00FFFF;
+const I_HIGHLIGHT: u32 = 0xFF66CC;
+const CURSOR: u32 = 0xFFCC00;
+
+/// Bytes shown per row of a hex view
+const ROW_BYTES: u16 = 8;
+
+/// Debug side panel showing registers, stack, keyboard and memory
+/// While the machine is paused and the panel visible, memory can be edited at the cursor by
+/// typing hex digits, and searched for a value such as a lives counter: F6 starts a search,
+/// F7 to F10 keep the addresses that decreased, increased, changed or stayed the same since,
+/// and Tab moves the cursor through what's left.
+pub struct Overlay {
+    pub visible: bool,
+
+    /// Address being edited
+    cursor: u16,
+
+    /// High nibble typed for the byte at the cursor, waiting for the low nibble
+    pending_nibble: Option<u8>,
+
+    search: Option<Search>,
+
+    /// Set while a movie records or plays: it only holds the keypad, so pokes are refused
+    pub locked: bool,
+}
+
+impl Overlay {
+    pub fn new() -> Overlay {
+        Overlay {
+            visible: false,
+            cursor: 0x200,
+            pending_nibble: None,
+            search: None,
+            locked: false,
+        }
+    }
+
+    /// Moves the edit cursor to the PC, for when the machine pauses
+    pub fn pausing(&mut self, cpu: &CPU) {
+        self.cursor = cpu.pc();
+        self.pending_nibble = None;
+    }
+
+    /// True while key presses go to the memory editor instead of the CHIP-8 keypad
+    pub fn editing(&self, paused: bool) -> bool {
+        self.visible && paused
+    }
+
+    /// Handles a key press while editing
+    /// Returns false if the key was not consumed, in which case it should go to the keypad
+    pub fn handle_key(&mut self, key: Key, cpu: &mut CPU, paused: bool) -> bool {
+        if !self.editing(paused) {
+            return false;
+        }
+        let moved = match key {
+            Key::Left => Some(self.cursor.wrapping_sub(1)),
+            Key::Right => Some(self.cursor.wrapping_add(1)),
+            Key::Up => Some(self.cursor.wrapping_sub(ROW_BYTES)),
+            Key::Down => Some(self.cursor.wrapping_add(ROW_BYTES)),
+            Key::PageUp => Some(self.cursor.wrapping_sub(ROW_BYTES * 8)),
+            Key::PageDown => Some(self.cursor.wrapping_add(ROW_BYTES * 8)),
+            Key::Home => Some(cpu.pc()),
+            Key::End => Some(cpu.i()),
+            _ => None,
+        };
+        if let Some(address) = moved {
+            self.cursor = address & 0xFFF;
+            self.pending_nibble = None;
+            return true;
+        }
+
+        let comparison = match key {
+            Key::F6 => {
+                self.search = Some(Search::new(cpu));
+                return true;
+            }
+            Key::F7 => Some(Comparison::Decreased),
+            Key::F8 => Some(Comparison::Increased),
+            Key::F9 => Some(Comparison::Changed),
+            Key::F10 => Some(Comparison::Unchanged),
+            Key::Tab => {
+                // The next candidate after the cursor, wrapping around
+                if let Some(search) = &self.search {
+                    let candidates = search.candidates();
+                    let next = candidates.iter().find(|&&address| address > self.cursor);
+                    if let Some(&address) = next.or_else(|| candidates.first()) {
+                        self.cursor = address;
+                        self.pending_nibble = None;
+                    }
+                }
+                return true;
+            }
+            _ => None,
+        };
+        if let Some(comparison) = comparison {
+            if let Some(search) = &mut self.search {
+                search.filter(cpu, comparison);
+            }
+            return true;
+        }
+
+        match hex_digit(key) {
+            Some(_) if self.locked => true,
+            Some(digit) => {
+                match self.pending_nibble.take() {
+                    None => self.pending_nibble = Some(digit),
+                    Some(high) => {
+                        cpu.poke(self.cursor, (high << 4) | digit);
+                        self.cursor = (self.cursor + 1) & 0xFFF;
+                    }
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Draws the panel into buffer, a frame stride pixels wide, starting at column x
+    pub fn draw(&self, cpu: &CPU, paused: bool, buffer: &mut [u32], stride: usize, x: usize) {
+        let height = buffer.len() / stride;
+        for row in buffer.chunks_mut(stride).take(height) {
+            for pixel in row[x..x + PANEL_WIDTH].iter_mut() {
+                *pixel = BACKGROUND;
+            }
+        }
+        let mut text = Text {
+            buffer,
+            stride,
+            left: x + MARGIN,
+        };
+
+        let state = if paused { "PAUSED" } else { "RUNNING" };
+        text.print(0, 0, &format!("CHIP-8 DEBUG  {}", state), TEXT, None);
+
+        text.print(
+            2,
+            0,
+            &format!(
+                "PC {:03X}  I {:03X}  SP {:X}  DT {:02X}  ST {:02X}",
+                cpu.pc(),
+                cpu.i(),
+                cpu.sp(),
+                cpu.dt(),
+                cpu.st()
+            ),
+            TEXT,
+            None,
+        );
+
+        for (r, &value) in cpu.v().iter().enumerate() {
+            let line = 4 + r / 4;
+            let column = (r % 4) * 7;
+            text.print(line, column, &format!("V{:X} {:02X}", r, value), TEXT, None);
+        }
+
+        text.print(9, 0, "STACK", TEXT, None);
+        for (level, &address) in cpu.stack().iter().enumerate() {
+            let color = if level < cpu.sp() as usize { TEXT } else { DIM };
+            let line = 10 + level / 8;
+            let column = (level % 8) * 5;
+            text.print(line, column, &format!("{:03X}", address), color, None);
+        }
+
+        text.print(13, 0, "KEYS", TEXT, None);
+        for (key, &pressed) in cpu.keyboard.keys().iter().enumerate() {
+            let (fg, bg) = if pressed {
+                (BACKGROUND, Some(PC_HIGHLIGHT))
+            } else {
+                (DIM, None)
+            };
+            text.print(13, 5 + key * 2, &format!("{:X}", key), fg, bg);
+        }
+
+        let editing = self.editing(paused);
+        text.print(15, 0, "MEMORY AT PC", PC_HIGHLIGHT, None);
+        self.hex_view(&mut text, cpu, 16, cpu.pc(), 4, editing);
+        text.print(21, 0, "MEMORY AT I", I_HIGHLIGHT, None);
+        self.hex_view(&mut text, cpu, 22, cpu.i(), 4, editing);
+
+        if editing {
+            let pending = match self.pending_nibble {
+                Some(high) => format!("  {:X}_", high),
+                None => String::new(),
+            };
+            text.print(
+                27,
+                0,
+                &format!("EDIT {:03X}{}", self.cursor, pending),
+                CURSOR,
+                None,
+            );
+            self.hex_view(&mut text, cpu, 28, self.cursor, 6, editing);
+        }
+
+        text.print(
+            35,
+            0,
+            "F1 PANEL  F2 PAUSE  F3 STEP  F4 SLOW  F5 FAST",
+            DIM,
+            None,
+        );
+        if paused {
+            text.print(36, 0, "ARROWS PGUP PGDN MOVE  HOME PC  END I", DIM, None);
+            let poke = if self.locked {
+                "MOVIE: NO POKE"
+            } else {
+                "0-9 A-F POKE"
+            };
+            let line = format!("{}  F6 SEARCH  F7-10 LESS MORE DIFF SAME", poke);
+            text.print(37, 0, &line, DIM, None);
+        }
+        if let Some(search) = &self.search {
+            let candidates = search.candidates();
+            let mut line = format!("SEARCH {} LEFT", candidates.len());
+            // As many as fit, Tab visits the rest
+            for address in candidates
+                .iter()
+                .take(if candidates.len() <= 8 { 8 } else { 6 })
+            {
+                line.push_str(&format!(" {:03X}", address));
+            }
+            if candidates.len() > 8 {
+                line.push_str(" ...");
+            }
+            text.print(38, 0, &line, CURSOR, None);
+        }
+    }
+
+    /// Prints rows of memory around address, highlighting the PC, I and the cursor
+    fn hex_view(
+        &self,
+        text: &mut Text,
+        cpu: &CPU,
+        line: usize,
+        address: u16,
+        rows: u16,
+        editing: bool,
+    ) {
+        let memory = cpu.memory();
+        let first_row = (address & !(ROW_BYTES - 1)).wrapping_sub(ROW_BYTES * (rows / 2));
+        for row in 0..rows {
+            let row_address = first_row.wrapping_add(row * ROW_BYTES) & 0xFFF;
+            let line = line + row as usize;
+            text.print(line, 0, &format!("{:03X}:", row_address), DIM, None);
+            for offset in 0..ROW_BYTES {
+                let byte_address = (row_address + offset) & 0xFFF;
+                let in_range =
+                    |start: u16, len: u16| byte_address.wrapping_sub(start) & 0xFFF < len;
+                let bg = if editing && byte_address == self.cursor {
+                    Some(CURSOR)
+                } else if in_range(cpu.pc(), 2) {
+                    Some(PC_HIGHLIGHT)
+                } else if in_range(cpu.i(), 1) {
+                    Some(I_HIGHLIGHT)
+                } else {
+                    None
+                };
+                let fg = if bg.is_some() { BACKGROUND } else { TEXT };
+                let column = 5 + offset as usize * 3;
+                let value = format!("{:02X}", memory[byte_address as usize]);
+                text.print(line, column, &value, fg, bg);
+            }
+        }
+    }
+}
+
+/// Draws label, e.g. "PAUSED", in the top right corner of a screen width pixels wide
+pub fn draw_status(label: &str, buffer: &mut [u32], stride: usize, width: usize) {
+    let mut text = Text {
+        buffer,
+        stride,
+        left: width.saturating_sub(2 * MARGIN + label.len() * CELL_WIDTH),
+    };
+    text.print(0, 0, label, TEXT, Some(BACKGROUND));
+}
+
+/// Maps the keys 0-9 and A-F to their hex value
+fn hex_digit(key: Key) -> Option<u8> {
+    let digit = match key {
+        Key::Key0 => 0x0,
+        Key::Key1 => 0x1,
+        Key::Key2 => 0x2,
+        Key::Key3 => 0x3,
+        Key::Key4 => 0x4,
+        Key::Key5 => 0x5,
+        Key::Key6 => 0x6,
+        Key::Key7 => 0x7,
+        Key::Key8 => 0x8,
+        Key::Key9 => 0x9,
+        Key::A => 0xA,
+        Key::B => 0xB,
+        Key::C => 0xC,
+        Key::D => 0xD,
+        Key::E => 0xE,
+        Key::F => 0xF,
+        _ => return None,
+    };
+    Some(digit)
+}
+
+/// Draws text on a grid of character cells
+struct Text<'a> {
+    buffer: &'a mut [u32],
+    stride: usize,
+    left: usize,
+}
+
+impl<'a> Text<'a> {
+    fn print(&mut self, line: usize, column: usize, text: &str, fg: u32, bg: Option<u32>) {
+        let top = MARGIN + line * CELL_HEIGHT;
+        for (i, c) in text.chars().enumerate() {
+            let left = self.left + (column + i) * CELL_WIDTH;
+            if let Some(bg) = bg {
+                self.fill(left, top, CELL_WIDTH, CELL_HEIGHT, bg);
+            }
+            for (y, &bits) in glyph(c).iter().enumerate() {
+                for x in 0..3 {
+                    if bits & (0b100 >> x) != 0 {
+                        self.fill(
+                            left + GLYPH_SCALE / 2 + x * GLYPH_SCALE,
+                            top + GLYPH_SCALE / 2 + y * GLYPH_SCALE,
+                            GLYPH_SCALE,
+                            GLYPH_SCALE,
+                            fg,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    fn fill(&mut self, x: usize, y: usize, width: usize, height: usize, color: u32) {
+        for row in y..y + height {
+            let start = row * self.stride + x;
+            if let Some(pixels) = self.buffer.get_mut(start..start + width) {
+                for pixel in pixels.iter_mut() {
+                    *pixel = color;
+                }
+            }
+        }
+    }
+}
+
+/// 3x5 pixel glyphs, one row per byte, most significant of the 3 bits on the left
+fn glyph(c: char) -> [u8; 5] {
+    match c.to_ascii_uppercase() {
+        '0' => [7, 5, 5, 5, 7],
+        '1' => [2, 6, 2, 2, 7],
+        '2' => [7, 1, 7, 4, 7],
+        '3' => [7, 1, 7, 1, 7],
+        '4' => [5, 5, 7, 1, 1],
+        '5' => [7, 4, 7, 1, 7],
+        '6' => [7, 4, 7, 5, 7],
+        '7' => [7, 1, 2, 2, 2],
+        '8' => [7, 5, 7, 5, 7],
+        '9' => [7, 5, 7, 1, 7],
+        'A' => [2, 5, 7, 5, 5],
+        'B' => [6, 5, 6, 5, 6],
+        'C' => [3, 4, 4, 4, 3],
+        'D' => [6, 5, 5, 5, 6],
+        'E' => [7, 4, 6, 4, 7],
+        'F' => [7, 4, 6, 4, 4],
+        'G' => [3, 4, 5, 5, 3],
+        'H' => [5, 5, 7, 5, 5],
+        'I' => [7, 2, 2, 2, 7],
+        'J' => [1, 1, 1, 5, 2],
+        'K' => [5, 5, 6, 5, 5],
+        'L' => [4, 4, 4, 4, 7],
+        'M' => [5, 7, 7, 5, 5],
+        'N' => [6, 5, 5, 5, 5],
+        'O' => [2, 5, 5, 5, 2],
+        'P' => [6, 5, 6, 4, 4],
+        'Q' => [2, 5, 5, 6, 3],
+        'R' => [6, 5, 6, 5, 5],
+        'S' => [3, 4, 2, 1, 6],
+        'T' => [7, 2, 2, 2, 2],
+        'U' => [5, 5, 5, 5, 7],
+        'V' => [5, 5, 5, 5, 2],
+        'W' => [5, 5, 7, 7, 5],
+        'X' => [5, 5, 2, 5, 5],
+        'Y' => [5, 5, 2, 2, 2],
+        'Z' => [7, 1, 2, 4, 7],
+        ':' => [0, 2, 0, 2, 0],
+        '-' => [0, 0, 7, 0, 0],
+        '_' => [0, 0, 0, 0, 7],
+        '.' => [0, 0, 0, 0, 2],
+        '/' => [1, 1, 2, 4, 4],
+        _ => [0; 5],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn searches_memory_from_the_keyboard() {
+        let mut cpu = CPU::new();
+        cpu.reset();
+        cpu.poke(0x3F0, 3);
+        cpu.poke(0x3F8, 3);
+        let mut overlay = Overlay::new();
+        overlay.visible = true;
+        assert!(!overlay.handle_key(Key::F6, &mut cpu, false));
+
+        overlay.handle_key(Key::F6, &mut cpu, true);
+        // A life lost
+        cpu.poke(0x3F0, 2);
+        cpu.poke(0x3F8, 4);
+        overlay.handle_key(Key::F7, &mut cpu, true);
+        overlay.handle_key(Key::F10, &mut cpu, true);
+        let search = overlay.search.as_ref().unwrap();
+        assert_eq!(search.candidates(), [0x3F0]);
+
+        assert!(overlay.handle_key(Key::Tab, &mut cpu, true));
+        assert_eq!(overlay.cursor, 0x3F0);
+        // Infinite lives
+        overlay.handle_key(Key::Key9, &mut cpu, true);
+        overlay.handle_key(Key::Key9, &mut cpu, true);
+        assert_eq!(cpu.memory()[0x3F0], 0x99);
+
+        // Not while a movie records or plays
+        overlay.locked = true;
+        overlay.handle_key(Key::Left, &mut cpu, true);
+        assert!(overlay.handle_key(Key::Key1, &mut cpu, true));
+        assert!(overlay.handle_key(Key::Key1, &mut cpu, true));
+        assert_eq!(cpu.memory()[0x3F0], 0x99);
+    }
+}
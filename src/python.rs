@@ -0,0 +1,304 @@
+// Python module "chip8", enabled with the "python" feature
+// Build and install into the current virtualenv with `maturin develop`, see pyproject.toml.
+//
+//     import chip8
+//     env = chip8.Env(open("pong.ch8", "rb").read(), config=open("pong.toml").read())
+//     obs = env.reset(seed=1)
+//     obs, reward, done = env.step(1 << 4)
+
+use crate::cpu::display::{HEIGHT, WIDTH};
+use crate::cpu::{Error, CPU};
+use crate::env;
+use numpy::{PyArray1, PyArray2, PyArrayMethods, PyReadonlyArray1};
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+
+fn cpu_error(error: Error) -> PyErr {
+    PyRuntimeError::new_err(error.to_string())
+}
+
+/// Copies the memory into a 4096 byte array
+fn memory<'py>(py: Python<'py>, cpu: &CPU) -> Bound<'py, PyArray1<u8>> {
+    PyArray1::from_slice(py, cpu.memory())
+}
+
+/// Copies the screen into a 32x64 array of 0s and 1s
+fn screen<'py>(py: Python<'py>, cpu: &CPU) -> PyResult<Bound<'py, PyArray2<u8>>> {
+    PyArray1::from_slice(py, &cpu.display.screen()).reshape([HEIGHT, WIDTH])
+}
+
+/// A bare machine, for driving it by hand
+#[pyclass(name = "CPU")]
+pub struct PyCpu {
+    cpu: CPU,
+}
+
+#[pymethods]
+impl PyCpu {
+    #[new]
+    #[pyo3(signature = (seed=0))]
+    fn new(seed: u64) -> PyCpu {
+        let mut cpu = CPU::new();
+        cpu.reset();
+        cpu.seed(seed);
+        PyCpu { cpu }
+    }
+
+    fn reset(&mut self) {
+        self.cpu.reset();
+    }
+
+    fn load_rom(&mut self, rom: &[u8]) -> PyResult<()> {
+        self.cpu.load_rom(rom).map_err(cpu_error)
+    }
+
+    /// Executes cycles instructions
+    #[pyo3(signature = (cycles=1))]
+    fn run(&mut self, cycles: u64) -> PyResult<()> {
+        for _ in 0..cycles {
+            self.cpu.execute_cycle().map_err(cpu_error)?;
+        }
+        Ok(())
+    }
+
+    /// Ticks the 60Hz timers once
+    fn tick(&mut self) {
+        self.cpu.decrement_timers();
+    }
+
+    fn key_down(&mut self, key: usize) -> PyResult<()> {
+        self.cpu.keyboard.key_down(check_key(key)?);
+        Ok(())
+    }
+
+    fn key_up(&mut self, key: usize) -> PyResult<()> {
+        self.cpu.keyboard.key_up(check_key(key)?);
+        Ok(())
+    }
+
+    fn poke(&mut self, address: u16, value: u8) {
+        self.cpu.poke(address, value);
+    }
+
+    /// A copy of memory, 4096 bytes
+    #[getter]
+    fn memory<'py>(&self, py: Python<'py>) -> Bound<'py, PyArray1<u8>> {
+        memory(py, &self.cpu)
+    }
+
+    /// A copy of the screen, 32 rows of 64 pixels, 1 for lit
+    #[getter]
+    fn screen<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyArray2<u8>>> {
+        screen(py, &self.cpu)
+    }
+
+    /// A copy of V0 - VF
+    #[getter]
+    fn v<'py>(&self, py: Python<'py>) -> Bound<'py, PyArray1<u8>> {
+        PyArray1::from_slice(py, self.cpu.v())
+    }
+
+    #[getter]
+    fn pc(&self) -> u16 {
+        self.cpu.pc()
+    }
+
+    #[getter]
+    fn i(&self) -> u16 {
+        self.cpu.i()
+    }
+
+    #[getter]
+    fn cycles(&self) -> u64 {
+        self.cpu.cycles()
+    }
+}
+
+fn check_key(key: usize) -> PyResult<usize> {
+    if key <= 0xF {
+        Ok(key)
+    } else {
+        Err(PyValueError::new_err(format!("no key {:#x}", key)))
+    }
+}
+
+/// Reward extractor: how much the byte at address changed during a step, times scale
+/// Called as `reward(before, after)` with the memory before and after the step.
+#[pyclass]
+pub struct Delta {
+    address: usize,
+    scale: f64,
+}
+
+#[pymethods]
+impl Delta {
+    #[new]
+    #[pyo3(signature = (address, scale=1.0))]
+    fn new(address: u16, scale: f64) -> Delta {
+        Delta {
+            address: address as usize & 0xFFF,
+            scale,
+        }
+    }
+
+    fn __call__(&self, before: PyReadonlyArray1<u8>, after: PyReadonlyArray1<u8>) -> PyResult<f64> {
+        let old = *before.get(self.address).ok_or_else(short_memory)? as f64;
+        let new = *after.get(self.address).ok_or_else(short_memory)? as f64;
+        Ok((new - old) * self.scale)
+    }
+}
+
+/// Terminal condition: the byte at address equals value
+/// Called as `done(memory)` with the memory after a step.
+#[pyclass]
+pub struct Equals {
+    address: usize,
+    value: u8,
+}
+
+#[pymethods]
+impl Equals {
+    #[new]
+    fn new(address: u16, value: u8) -> Equals {
+        Equals {
+            address: address as usize & 0xFFF,
+            value,
+        }
+    }
+
+    fn __call__(&self, memory: PyReadonlyArray1<u8>) -> PyResult<bool> {
+        Ok(*memory.get(self.address).ok_or_else(short_memory)? == self.value)
+    }
+}
+
+fn short_memory() -> PyErr {
+    PyValueError::new_err("expected 4096 bytes of memory")
+}
+
+/// Reinforcement learning environment playing one ROM, see `env::Env`
+///
+/// config is the text of an environment config file, see `env::Config`, and the keyword
+/// arguments override its settings. Each step holds the keys of an action mask, bit n for key n,
+/// and returns the screen, the reward and whether the episode is over. reward and done are
+/// optional callables with the signatures of `Delta` and `Equals`, for games that keep score in
+/// ways the config can't describe; their reward is added to the config's, and either can end
+/// the episode.
+#[pyclass]
+pub struct Env {
+    env: env::Env,
+    reward: Option<Py<PyAny>>,
+    done: Option<Py<PyAny>>,
+    seed: u64,
+}
+
+#[pymethods]
+impl Env {
+    #[new]
+    #[pyo3(signature = (
+        rom,
+        config=None,
+        reward=None,
+        done=None,
+        frame_skip=None,
+        sticky_actions=None,
+        cycles_per_frame=None,
+        max_frames=None,
+        seed=0
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        rom: Vec<u8>,
+        config: Option<&str>,
+        reward: Option<Py<PyAny>>,
+        done: Option<Py<PyAny>>,
+        frame_skip: Option<u32>,
+        sticky_actions: Option<f64>,
+        cycles_per_frame: Option<u32>,
+        max_frames: Option<u64>,
+        seed: u64,
+    ) -> PyResult<Env> {
+        let mut config = match config {
+            Some(text) => env::Config::parse(text)
+                .map_err(|e| PyValueError::new_err(format!("config: {}", e)))?,
+            None => env::Config::default(),
+        };
+        config.frame_skip = frame_skip.unwrap_or(config.frame_skip);
+        config.sticky_actions = sticky_actions.unwrap_or(config.sticky_actions);
+        config.cycles_per_frame = cycles_per_frame.unwrap_or(config.cycles_per_frame);
+        config.max_frames = max_frames.or(config.max_frames);
+
+        let mut env = Env {
+            env: env::Env::new(&rom, config).map_err(cpu_error)?,
+            reward,
+            done,
+            seed,
+        };
+        env.env.reset(seed);
+        Ok(env)
+    }
+
+    /// Starts a new episode and returns the first observation
+    /// Episodes with the same seed play out the same given the same actions, sticky ones
+    /// included.
+    #[pyo3(signature = (seed=None))]
+    fn reset<'py>(
+        &mut self,
+        py: Python<'py>,
+        seed: Option<u64>,
+    ) -> PyResult<Bound<'py, PyArray2<u8>>> {
+        if let Some(seed) = seed {
+            self.seed = seed;
+        }
+        self.env.reset(self.seed);
+        screen(py, self.env.cpu())
+    }
+
+    /// Holds the keys in action, bit n for key n, for a step of `frame_skip` frames
+    /// Returns (observation, reward, done). A machine error, e.g. a stack overflow, raises
+    /// RuntimeError; call reset to carry on.
+    #[pyo3(signature = (action=0))]
+    #[allow(clippy::type_complexity)]
+    fn step<'py>(
+        &mut self,
+        py: Python<'py>,
+        action: u16,
+    ) -> PyResult<(Bound<'py, PyArray2<u8>>, f64, bool)> {
+        let before = memory(py, self.env.cpu());
+        let observation = self.env.step(action).map_err(cpu_error)?;
+        let after = memory(py, self.env.cpu());
+
+        let mut reward = observation.reward;
+        if let Some(callable) = &self.reward {
+            reward += callable
+                .bind(py)
+                .call1((before, after.clone()))?
+                .extract::<f64>()?;
+        }
+        let mut done = observation.done;
+        if let Some(callable) = &self.done {
+            done |= callable.bind(py).call1((after,))?.extract::<bool>()?;
+        }
+        Ok((screen(py, self.env.cpu())?, reward, done))
+    }
+
+    /// Frames since the episode started
+    #[getter]
+    fn frame(&self) -> u64 {
+        self.env.frame()
+    }
+
+    /// The machine's memory, e.g. to find where a game keeps its score
+    #[getter]
+    fn memory<'py>(&self, py: Python<'py>) -> Bound<'py, PyArray1<u8>> {
+        memory(py, self.env.cpu())
+    }
+}
+
+#[pymodule]
+fn chip8(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyCpu>()?;
+    module.add_class::<Env>()?;
+    module.add_class::<Delta>()?;
+    module.add_class::<Equals>()?;
+    Ok(())
+}
@@ -0,0 +1,127 @@
+use crate::cpu::display::{Display, WIDTH};
+use std::fmt;
+use std::str::FromStr;
+
+/// Draws the display rows set in the rows mask into a 0RGB frame buffer, each pixel as a
+/// scale by scale square
+/// Pass `Display::take_dirty` to only redraw what changed, or `ALL_ROWS` for a full frame.
+/// stride is the width of a buffer row, which may be wider than the scaled screen.
+pub fn scale(
+    display: &Display,
+    rows: u32,
+    buffer: &mut [u32],
+    stride: usize,
+    scale: usize,
+    on: u32,
+    off: u32,
+) {
+    for (y, &bits) in display.rows().iter().enumerate() {
+        if rows & 1 << y == 0 {
+            continue;
+        }
+        // Build the first scaled line, then copy it down for the rest of the square
+        let first = y * scale * stride;
+        for x in 0..WIDTH {
+            let color = if bits & 1 << (WIDTH - 1 - x) != 0 {
+                on
+            } else {
+                off
+            };
+            buffer[first + x * scale..first + (x + 1) * scale].fill(color);
+        }
+        for r in 1..scale {
+            buffer.copy_within(first..first + WIDTH * scale, first + r * stride);
+        }
+    }
+}
+
+/// Lit and unlit pixel colors as 0RGB
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Palette {
+    pub on: u32,
+    pub off: u32,
+}
+
+impl Palette {
+    /// Cyan on dark gray, the window's colors from the start
+    pub const CLASSIC: Palette = Palette {
+        on: 0x00FFFF,
+        off: 0x333333,
+    };
+
+    /// Named palettes, for `from_str`
+    pub const PRESETS: [(&'static str, Palette); 4] = [
+        ("classic", Palette::CLASSIC),
+        (
+            "green",
+            Palette {
+                on: 0x33FF66,
+                off: 0x0A1A0F,
+            },
+        ),
+        (
+            "amber",
+            Palette {
+                on: 0xFFB000,
+                off: 0x1A1000,
+            },
+        ),
+        (
+            "mono",
+            Palette {
+                on: 0xFFFFFF,
+                off: 0x000000,
+            },
+        ),
+    ];
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette::CLASSIC
+    }
+}
+
+/// Writes the preset name, or the colors as RRGGBB:RRGGBB
+impl fmt::Display for Palette {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match Palette::PRESETS
+            .iter()
+            .find(|&&(_, palette)| palette == *self)
+        {
+            Some((name, _)) => write!(f, "{}", name),
+            None => write!(f, "{:06X}:{:06X}", self.on, self.off),
+        }
+    }
+}
+
+/// Parses a preset name, or lit and unlit colors as hex, e.g. "ffffff:000000"
+impl FromStr for Palette {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Palette, String> {
+        if let Some(&(_, palette)) = Palette::PRESETS.iter().find(|&&(name, _)| name == text) {
+            return Ok(palette);
+        }
+        let color = |hex: &str| {
+            let hex = hex.trim_start_matches('#');
+            match u32::from_str_radix(hex, 16) {
+                Ok(color) if hex.len() == 6 => Some(color),
+                _ => None,
+            }
+        };
+        match text.split_once(':') {
+            Some((on, off)) => match (color(on), color(off)) {
+                (Some(on), Some(off)) => Ok(Palette { on, off }),
+                _ => Err(format!("expected RRGGBB:RRGGBB colors, not {}", text)),
+            },
+            None => {
+                let names: Vec<_> = Palette::PRESETS.iter().map(|&(name, _)| name).collect();
+                Err(format!(
+                    "expected one of {} or RRGGBB:RRGGBB colors",
+                    names.join(", ")
+                ))
+            }
+        }
+    }
+}
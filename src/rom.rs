@@ -0,0 +1,59 @@
+use crate::cpu::instruction::Instruction;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Where ROMs are loaded, everything below is reserved for the interpreter
+pub const START: usize = 0x200;
+/// The largest ROM that fits in memory
+pub const MAX_SIZE: usize = 4096 - START;
+
+/// CRC-32 (IEEE) of the ROM, used to key per-ROM files such as cheats
+pub fn hash(rom: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in rom {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+/// Reads a ROM file, failing with InvalidData if it's empty or can't fit in memory
+pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Vec<u8>> {
+    let rom = fs::read(path)?;
+    let message = if rom.is_empty() {
+        "the file is empty".to_string()
+    } else if rom.len() > MAX_SIZE {
+        format!(
+            "the file is {} bytes but ROMs can be at most {}, the memory above 0x{:03X}",
+            rom.len(),
+            MAX_SIZE,
+            START
+        )
+    } else {
+        return Ok(rom);
+    };
+    Err(io::Error::new(io::ErrorKind::InvalidData, message))
+}
+
+/// Writes a linear disassembly of rom, one instruction per line as loaded at 0x200
+/// Data mixed in with the code shows up as whatever instruction it happens to decode to; a
+/// trailing odd byte is written as DB.
+pub fn write_disassembly(out: &mut dyn Write, rom: &[u8]) -> io::Result<()> {
+    for (n, word) in rom.chunks(2).enumerate() {
+        let address = START + n * 2;
+        match *word {
+            [high, low] => {
+                let opcode = (high as u16) << 8 | low as u16;
+                let instruction = Instruction::decode(opcode);
+                writeln!(out, "{:03X}: {:04X}  {}", address, opcode, instruction)?;
+            }
+            [byte] => writeln!(out, "{:03X}: {:02X}    DB 0x{:02X}", address, byte, byte)?,
+            _ => unreachable!(),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,315 @@
+// Scripting reference: https://rhai.rs/book/
+
+use crate::cpu::hook::{Hook, NoHook};
+use crate::cpu::instruction::Instruction;
+use crate::cpu::CPU;
+use rhai::{Dynamic, Engine, EvalAltResult, FnPtr, Scope, AST, INT};
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::rc::Rc;
+
+pub type Error = Box<EvalAltResult>;
+
+/// Operations a script's top level or a single callback may run before it is stopped,
+/// so a runaway loop fails with an error instead of hanging the frontend
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// Script functions registered through on_frame, on_pc and on_write
+#[derive(Default)]
+struct Callbacks {
+    frame: Vec<FnPtr>,
+    pc: BTreeMap<u16, Vec<FnPtr>>,
+    /// Callbacks for writes to an address range, start inclusive and end exclusive
+    write: Vec<(u16, u16, FnPtr)>,
+}
+
+/// Records the memory written by the instruction that just ran
+#[derive(Default)]
+struct Watcher {
+    /// Start and length of the range the current instruction writes
+    writes: Option<(u16, u16)>,
+}
+
+impl Hook for Watcher {
+    fn before_instruction(&mut self, cpu: &CPU, opcode: u16) {
+        self.writes = match Instruction::decode(opcode) {
+            Instruction::LdB(_) => Some((cpu.i(), 3)),
+            Instruction::StoreRegs(x) => Some((cpu.i(), x as u16 + 1)),
+            _ => None,
+        };
+    }
+}
+
+/// A Rhai script driving the emulator
+///
+/// Scripts read and change the machine through global functions (`peek`, `poke`, `reg`,
+/// `set_reg`, `key_down`, `pixel`, ...) and register callbacks when they are loaded:
+///
+/// ```text
+/// on_frame(|| if peek(0x3F0) > 99 { print("new high score"); stop(); });
+/// on_pc(0x2A4, || set_reg(0, 3));          // infinite lives
+/// on_write(0x300, 0x310, |addr, value| print(`score byte ${addr} = ${value}`));
+/// ```
+///
+/// While a callback runs, the CPU is moved into the script's shared slot, so callbacks
+/// always see (and change) the live machine.
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    cpu: Rc<RefCell<CPU>>,
+    callbacks: Rc<RefCell<Callbacks>>,
+    stopped: Rc<Cell<bool>>,
+    watcher: Watcher,
+}
+
+impl Script {
+    /// Reads and loads the script at path, see `Script::new`
+    pub fn load<P: AsRef<Path>>(path: P, cpu: &mut CPU) -> Result<Script, Error> {
+        let source = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        Script::new(&source, cpu)
+    }
+
+    /// Compiles source and runs its top level once against cpu, registering its callbacks
+    pub fn new(source: &str, cpu: &mut CPU) -> Result<Script, Error> {
+        let shared = Rc::new(RefCell::new(CPU::new()));
+        let callbacks = Rc::new(RefCell::new(Callbacks::default()));
+        let stopped = Rc::new(Cell::new(false));
+
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        register_machine(&mut engine, &shared);
+        register_callbacks(&mut engine, &callbacks);
+        let flag = stopped.clone();
+        engine.register_fn("stop", move || flag.set(true));
+
+        let ast = engine.compile(source)?;
+        let mut script = Script {
+            engine,
+            ast,
+            scope: Scope::new(),
+            cpu: shared,
+            callbacks,
+            stopped,
+            watcher: Watcher::default(),
+        };
+        script.with_cpu(cpu, |script| {
+            script
+                .engine
+                .run_ast_with_scope(&mut script.scope, &script.ast)
+        })?;
+        Ok(script)
+    }
+
+    /// True once the script has called stop()
+    pub fn stopped(&self) -> bool {
+        self.stopped.get()
+    }
+
+    /// Executes one CPU cycle, then runs the PC and memory write callbacks it triggered
+    /// CPU errors are returned as script errors.
+    pub fn execute_cycle(&mut self, cpu: &mut CPU) -> Result<(), Error> {
+        self.execute_cycle_with(cpu, &mut NoHook)
+    }
+
+    /// `execute_cycle`, also calling hook around the instruction
+    pub fn execute_cycle_with<H: Hook>(
+        &mut self,
+        cpu: &mut CPU,
+        hook: &mut H,
+    ) -> Result<(), Error> {
+        cpu.execute_cycle_with(&mut (&mut self.watcher, hook))
+            .map_err(|e| format!("{} at {:#05x}", e, cpu.pc()))?;
+
+        let mut calls: Vec<(FnPtr, Vec<Dynamic>)> = Vec::new();
+        {
+            let callbacks = self.callbacks.borrow();
+            if let Some((start, len)) = self.watcher.writes.take() {
+                for address in start..start + len {
+                    let address = address & 0xFFF;
+                    let value = cpu.memory()[address as usize] as INT;
+                    for (from, to, f) in &callbacks.write {
+                        if (*from..*to).contains(&address) {
+                            calls.push((f.clone(), vec![(address as INT).into(), value.into()]));
+                        }
+                    }
+                }
+            }
+            if let Some(functions) = callbacks.pc.get(&cpu.pc()) {
+                calls.extend(functions.iter().map(|f| (f.clone(), Vec::new())));
+            }
+        }
+        self.call_all(cpu, calls)
+    }
+
+    /// Runs the frame callbacks, call once per frame after `CPU::decrement_timers`
+    pub fn end_frame(&mut self, cpu: &mut CPU) -> Result<(), Error> {
+        let calls = self
+            .callbacks
+            .borrow()
+            .frame
+            .iter()
+            .map(|f| (f.clone(), Vec::new()))
+            .collect();
+        self.call_all(cpu, calls)
+    }
+
+    fn call_all(&mut self, cpu: &mut CPU, calls: Vec<(FnPtr, Vec<Dynamic>)>) -> Result<(), Error> {
+        if calls.is_empty() {
+            return Ok(());
+        }
+        self.with_cpu(cpu, |script| {
+            for (f, args) in calls {
+                let _: Dynamic = f.call(&script.engine, &script.ast, args)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Moves cpu into the shared slot for the duration of f
+    fn with_cpu<T>(
+        &mut self,
+        cpu: &mut CPU,
+        f: impl FnOnce(&mut Script) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        std::mem::swap(cpu, &mut *self.cpu.borrow_mut());
+        let result = f(self);
+        std::mem::swap(cpu, &mut *self.cpu.borrow_mut());
+        result
+    }
+}
+
+/// Registers the functions that read and change the machine
+fn register_machine(engine: &mut Engine, shared: &Rc<RefCell<CPU>>) {
+    let cpu = shared.clone();
+    engine.register_fn("peek", move |address: INT| {
+        cpu.borrow().memory()[address as usize & 0xFFF] as INT
+    });
+    let cpu = shared.clone();
+    engine.register_fn("poke", move |address: INT, value: INT| {
+        cpu.borrow_mut().poke(address as u16, value as u8)
+    });
+    let cpu = shared.clone();
+    engine.register_fn("reg", move |x: INT| {
+        cpu.borrow().v()[x as usize & 0xF] as INT
+    });
+    let cpu = shared.clone();
+    engine.register_fn("set_reg", move |x: INT, value: INT| {
+        cpu.borrow_mut().set_v(x as usize & 0xF, value as u8)
+    });
+    let cpu = shared.clone();
+    engine.register_fn("reg_i", move || cpu.borrow().i() as INT);
+    let cpu = shared.clone();
+    engine.register_fn("set_reg_i", move |value: INT| {
+        cpu.borrow_mut().set_i(value as u16)
+    });
+    let cpu = shared.clone();
+    engine.register_fn("pc", move || cpu.borrow().pc() as INT);
+    let cpu = shared.clone();
+    engine.register_fn("set_pc", move |value: INT| {
+        cpu.borrow_mut().set_pc(value as u16)
+    });
+    let cpu = shared.clone();
+    engine.register_fn("sp", move || cpu.borrow().sp() as INT);
+    let cpu = shared.clone();
+    engine.register_fn("dt", move || cpu.borrow().dt() as INT);
+    let cpu = shared.clone();
+    engine.register_fn("set_dt", move |value: INT| {
+        cpu.borrow_mut().set_dt(value as u16)
+    });
+    let cpu = shared.clone();
+    engine.register_fn("st", move || cpu.borrow().st() as INT);
+    let cpu = shared.clone();
+    engine.register_fn("set_st", move |value: INT| {
+        cpu.borrow_mut().set_st(value as u16)
+    });
+    let cpu = shared.clone();
+    engine.register_fn("frame", move || cpu.borrow().frames() as INT);
+    let cpu = shared.clone();
+    engine.register_fn("cycles", move || cpu.borrow().cycles() as INT);
+    let cpu = shared.clone();
+    engine.register_fn("key_down", move |key: INT| {
+        cpu.borrow_mut().keyboard.key_down(key as usize & 0xF)
+    });
+    let cpu = shared.clone();
+    engine.register_fn("key_up", move |key: INT| {
+        cpu.borrow_mut().keyboard.key_up(key as usize & 0xF)
+    });
+    let cpu = shared.clone();
+    engine.register_fn("key_pressed", move |key: INT| {
+        cpu.borrow().keyboard.key_pressed(key as usize & 0xF)
+    });
+    let cpu = shared.clone();
+    engine.register_fn("pixel", move |x: INT, y: INT| {
+        cpu.borrow().display.pixel(x as usize, y as usize)
+    });
+}
+
+/// Registers on_frame, on_pc and on_write
+fn register_callbacks(engine: &mut Engine, callbacks: &Rc<RefCell<Callbacks>>) {
+    let registry = callbacks.clone();
+    engine.register_fn("on_frame", move |f: FnPtr| {
+        registry.borrow_mut().frame.push(f)
+    });
+    let registry = callbacks.clone();
+    engine.register_fn("on_pc", move |address: INT, f: FnPtr| {
+        let address = address as u16 & 0xFFF;
+        registry.borrow_mut().pc.entry(address).or_default().push(f)
+    });
+    let registry = callbacks.clone();
+    engine.register_fn("on_write", move |f: FnPtr| {
+        registry.borrow_mut().write.push((0, 0x1000, f))
+    });
+    let registry = callbacks.clone();
+    engine.register_fn("on_write", move |start: INT, end: INT, f: FnPtr| {
+        registry
+            .borrow_mut()
+            .write
+            .push((start as u16 & 0xFFF, end.clamp(0, 0x1000) as u16, f))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn callbacks_see_and_change_the_machine() {
+        let mut cpu = CPU::new();
+        cpu.reset();
+        // 200: LD V0, 42; 202: LD I, 300; 204: LD [I], V0; 206: JP 206
+        cpu.load_rom(&[0x60, 0x2A, 0xA3, 0x00, 0xF0, 0x55, 0x12, 0x06])
+            .unwrap();
+        let source = r#"
+            let writes = [];
+            on_write(0x300, 0x301, |address, value| writes.push(value));
+            on_pc(0x206, || { poke(0x301, reg(0) + 1); stop(); });
+            on_frame(|| key_down(5));
+        "#;
+        let mut script = Script::new(source, &mut cpu).unwrap();
+        for _ in 0..4 {
+            script.execute_cycle(&mut cpu).unwrap();
+        }
+        script.end_frame(&mut cpu).unwrap();
+
+        assert!(script.stopped());
+        // The store at 204 wrote 42 to 0x300; the poke to 0x301 is outside the range
+        let writes = script.scope.get_value::<rhai::Array>("writes").unwrap();
+        let writes: Vec<INT> = writes.into_iter().map(|v| v.as_int().unwrap()).collect();
+        assert_eq!(writes, [42]);
+        assert_eq!(cpu.memory()[0x301], 43);
+        assert!(cpu.keyboard.key_pressed(5));
+    }
+
+    #[test]
+    fn runaway_scripts_fail() {
+        let mut cpu = CPU::new();
+        cpu.reset();
+        assert!(Script::new("loop {}", &mut cpu).is_err());
+
+        cpu.load_rom(&[0x12, 0x00]).unwrap();
+        let mut script = Script::new("on_frame(|| { while true {} });", &mut cpu).unwrap();
+        assert!(script.end_frame(&mut cpu).is_err());
+    }
+}
@@ -0,0 +1,301 @@
+// Settings for the emulator window, layered from defaults, the config file, the config file's
+// section for the ROM being played and the command line, later layers winning
+//
+// The config file is $XDG_CONFIG_HOME/chip-8/config.toml, or ~/.config/chip-8/config.toml:
+//
+//     scale = 10
+//     palette = "amber"
+//
+//     # Arrow keys as well as the default keys
+//     [keymap]
+//     Up = 0x2
+//     Down = 0x8
+//
+//     # Sections for single ROMs, by file name or by CRC-32 as `chip-8 info` shows it
+//     [rom."pong.ch8"]
+//     ips = 900
+//     [rom."B04497BB"]
+//     quirks = "schip"
+
+use crate::cpu::quirks::Quirks;
+use crate::render::Palette;
+use crate::rom;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// One layer of settings, anything left out falls through to the layers below
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Layer {
+    pub scale: Option<u32>,
+    /// A preset name or RRGGBB:RRGGBB, see `Palette`
+    pub palette: Option<String>,
+    /// Host key names, as minifb's `Key` variants, to CHIP-8 keys
+    /// Entries are added to the keymap below rather than replacing it.
+    #[serde(default)]
+    pub keymap: BTreeMap<String, u8>,
+    /// Quirk profile, see `Quirks::profile`
+    pub quirks: Option<String>,
+    /// Instructions per second
+    pub ips: Option<u32>,
+    /// Whether the buzzer is silenced
+    pub mute: Option<bool>,
+    /// Per-ROM layers, only in the config file's top level
+    #[serde(default)]
+    pub rom: BTreeMap<String, Layer>,
+}
+
+/// The settings in effect, and which layer each came from
+#[derive(Clone, Debug, PartialEq)]
+pub struct Settings {
+    pub scale: u32,
+    pub palette: Palette,
+    pub keymap: BTreeMap<String, u8>,
+    pub quirks: Quirks,
+    /// Name of the quirk profile
+    pub quirks_profile: String,
+    pub ips: u32,
+    pub mute: bool,
+    /// Setting name, or "keymap.<key>", to the layer that set it
+    origins: BTreeMap<String, String>,
+}
+
+const DEFAULT_KEYMAP: [(&str, u8); 16] = [
+    ("Key1", 0x1),
+    ("Key2", 0x2),
+    ("Key3", 0x3),
+    ("Key4", 0xC),
+    ("Q", 0x4),
+    ("W", 0x5),
+    ("F", 0x6),
+    ("P", 0xD),
+    ("A", 0x7),
+    ("R", 0x8),
+    ("S", 0x9),
+    ("T", 0xE),
+    ("Z", 0xA),
+    ("X", 0x0),
+    ("C", 0xB),
+    ("V", 0xF),
+];
+
+impl Default for Settings {
+    fn default() -> Settings {
+        let mut settings = Settings {
+            scale: 15,
+            palette: Palette::CLASSIC,
+            keymap: BTreeMap::new(),
+            quirks: Quirks::default(),
+            quirks_profile: "default".to_string(),
+            ips: 600,
+            mute: false,
+            origins: BTreeMap::new(),
+        };
+        for &(name, key) in DEFAULT_KEYMAP.iter() {
+            settings.keymap.insert(name.to_string(), key);
+        }
+        settings
+    }
+}
+
+impl Settings {
+    /// Where the config file is looked for, None without XDG_CONFIG_HOME or HOME
+    pub fn config_path() -> Option<PathBuf> {
+        let dir = match env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+            Some(dir) => PathBuf::from(dir),
+            None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+        };
+        Some(dir.join("chip-8").join("config.toml"))
+    }
+
+    /// The defaults overlaid with the config file at path and its sections for the ROM
+    /// A missing file counts as an empty one.
+    pub fn load<P: AsRef<Path>>(path: P, rom_path: &Path, rom: &[u8]) -> io::Result<Settings> {
+        let path = path.as_ref();
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        Settings::parse(&text, &path.display().to_string(), rom_path, rom)
+    }
+
+    /// Like `load`, with the config file's contents in text and origin naming it
+    pub fn parse(text: &str, origin: &str, rom_path: &Path, rom: &[u8]) -> io::Result<Settings> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        let config: Layer =
+            toml::from_str(text).map_err(|e| invalid(format!("{}: {}", origin, e)))?;
+
+        let mut settings = Settings::default();
+        settings.apply(&config, origin).map_err(invalid)?;
+        // File name first so the CRC-32, which can't match another ROM, wins
+        let name = rom_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned());
+        let hash = format!("{:08X}", rom::hash(rom));
+        for (section, layer) in config.rom.iter() {
+            if layer.rom.is_empty() && Some(section) == name.as_ref() {
+                let origin = format!("{} [rom.\"{}\"]", origin, section);
+                settings.apply(layer, &origin).map_err(invalid)?;
+            }
+        }
+        for (section, layer) in config.rom.iter() {
+            if !layer.rom.is_empty() {
+                let message = format!("{}: [rom.\"{}\"] can't have ROM sections", origin, section);
+                return Err(invalid(message));
+            }
+            if section.eq_ignore_ascii_case(&hash) {
+                let origin = format!("{} [rom.\"{}\"]", origin, section);
+                settings.apply(layer, &origin).map_err(invalid)?;
+            }
+        }
+        Ok(settings)
+    }
+
+    /// Overlays layer, recording origin as where its settings came from
+    pub fn apply(&mut self, layer: &Layer, origin: &str) -> Result<(), String> {
+        let error = |message: String| format!("{}: {}", origin, message);
+        if let Some(scale) = layer.scale {
+            if !(1..=64).contains(&scale) {
+                return Err(error(format!("scale {} isn't between 1 and 64", scale)));
+            }
+            self.scale = scale;
+            self.set_origin("scale", origin);
+        }
+        if let Some(palette) = &layer.palette {
+            self.palette = palette.parse().map_err(error)?;
+            self.set_origin("palette", origin);
+        }
+        for (name, &key) in layer.keymap.iter() {
+            if key > 0xF {
+                return Err(error(format!("keymap.{} = {} isn't a key 0-F", name, key)));
+            }
+            self.keymap.insert(name.clone(), key);
+            self.set_origin(&format!("keymap.{}", name), origin);
+        }
+        if let Some(profile) = &layer.quirks {
+            self.quirks = Quirks::profile(profile)
+                .ok_or_else(|| error(format!("unknown quirk profile {}", profile)))?;
+            self.quirks_profile = profile.clone();
+            self.set_origin("quirks", origin);
+        }
+        if let Some(ips) = layer.ips {
+            if ips == 0 {
+                return Err(error("ips must be at least 1".to_string()));
+            }
+            self.ips = ips;
+            self.set_origin("ips", origin);
+        }
+        if let Some(mute) = layer.mute {
+            self.mute = mute;
+            self.set_origin("mute", origin);
+        }
+        Ok(())
+    }
+
+    /// Which layer set a setting, "default" if none did
+    pub fn origin(&self, setting: &str) -> &str {
+        self.origins.get(setting).map_or("default", String::as_str)
+    }
+
+    fn set_origin(&mut self, setting: &str, origin: &str) {
+        self.origins.insert(setting.to_string(), origin.to_string());
+    }
+
+    /// The settings as a config file, each commented with the layer it came from
+    pub fn to_toml(&self) -> String {
+        let mut out = String::new();
+        let line = |out: &mut String, setting: String, origin: &str| {
+            writeln!(out, "{:<28} # {}", setting, origin).unwrap();
+        };
+        line(
+            &mut out,
+            format!("scale = {}", self.scale),
+            self.origin("scale"),
+        );
+        line(
+            &mut out,
+            format!("palette = \"{}\"", self.palette),
+            self.origin("palette"),
+        );
+        line(
+            &mut out,
+            format!("quirks = \"{}\"", self.quirks_profile),
+            self.origin("quirks"),
+        );
+        line(&mut out, format!("ips = {}", self.ips), self.origin("ips"));
+        line(
+            &mut out,
+            format!("mute = {}", self.mute),
+            self.origin("mute"),
+        );
+        out.push_str("\n[keymap]\n");
+        for (name, &key) in self.keymap.iter() {
+            let origin = self.origin(&format!("keymap.{}", name));
+            line(&mut out, format!("{} = 0x{:X}", name, key), origin);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layers_merge_in_order() {
+        let rom = [0x12, 0x00];
+        let config = format!(
+            "scale = 10\n\
+             palette = \"amber\"\n\
+             [keymap]\nUp = 2\n\
+             [rom.\"pong.ch8\"]\nips = 900\nscale = 5\n\
+             [rom.\"{:08x}\"]\nips = 1200\nquirks = \"schip\"\n",
+            rom::hash(&rom)
+        );
+        let pong = Path::new("roms/pong.ch8");
+        let settings = Settings::parse(&config, "config.toml", pong, &[0x00, 0xE0]).unwrap();
+        assert_eq!((settings.scale, settings.ips), (5, 900));
+        assert_eq!(settings.palette.to_string(), "amber");
+        assert_eq!((settings.keymap["Up"], settings.keymap["W"]), (0x2, 0x5));
+        assert_eq!(settings.origin("scale"), "config.toml [rom.\"pong.ch8\"]");
+        assert_eq!(settings.origin("palette"), "config.toml");
+        assert_eq!(settings.origin("mute"), "default");
+
+        // The CRC-32 section applies after the file name's
+        let mut settings = Settings::parse(&config, "config.toml", pong, &rom).unwrap();
+        assert_eq!((settings.ips, settings.quirks), (1200, Quirks::SCHIP));
+        let hash_origin = format!("# config.toml [rom.\"{:08x}\"]", rom::hash(&rom));
+
+        let command_line = Layer {
+            scale: Some(3),
+            mute: Some(true),
+            ..Layer::default()
+        };
+        settings.apply(&command_line, "command line").unwrap();
+        let toml = settings.to_toml();
+        assert!(toml.contains("scale = 3"));
+        assert!(toml.contains("# command line"));
+        assert!(toml.contains("ips = 1200"));
+        assert!(toml.contains(&hash_origin));
+        assert!(toml.contains("Up = 0x2"));
+        let printed: Layer = toml::from_str(&toml).unwrap();
+        assert_eq!(printed.ips, Some(1200));
+
+        for bad in [
+            "scale = 0",
+            "palette = \"pink\"",
+            "quirks = \"vip\"",
+            "[keymap]\nUp = 16",
+            "speed = 2",
+            "[rom.a.rom.b]\nips = 1",
+        ] {
+            assert!(Settings::parse(bad, "bad", pong, &[]).is_err(), "{}", bad);
+        }
+    }
+}
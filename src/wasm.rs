@@ -0,0 +1,178 @@
+// JavaScript API for the browser build, enabled with the "wasm" feature
+// Build with `wasm-pack build --target web -- --features wasm`, then serve web/index.html
+// next to the generated pkg/ directory. tests/wasm.rs checks the API under Node, run it with
+// `wasm-pack test --node -- --features wasm`.
+
+use crate::cpu::display::{HEIGHT, WIDTH};
+use crate::cpu::CPU;
+use crate::machine::{Machine, Speed};
+use crate::render;
+use wasm_bindgen::prelude::*;
+
+/// Colors as RGBA bytes, the same as the native window's
+const ON: [u8; 4] = [0x00, 0xFF, 0xFF, 0xFF];
+const OFF: [u8; 4] = [0x33, 0x33, 0x33, 0xFF];
+
+/// Instructions executed per 60Hz frame unless changed with `set_cycles_per_frame`
+const CYCLES_PER_FRAME: u32 = 10;
+
+/// An emulator for JavaScript to drive, one `run_frame` call per animation frame
+#[wasm_bindgen]
+pub struct Emulator {
+    machine: Machine,
+    /// RGBA pixels, row by row
+    frame: Vec<u32>,
+}
+
+#[wasm_bindgen]
+impl Emulator {
+    /// Creates an emulator with nothing loaded
+    /// seed feeds Cxkk, pass e.g. `Math.random() * 2 ** 32`.
+    #[wasm_bindgen(constructor)]
+    pub fn new(seed: u32) -> Emulator {
+        let mut cpu = CPU::new();
+        cpu.reset();
+        cpu.seed(seed as u64);
+        let mut emulator = Emulator {
+            machine: Machine::new(cpu, CYCLES_PER_FRAME * 60),
+            frame: vec![0; WIDTH * HEIGHT],
+        };
+        emulator.paint();
+        emulator
+    }
+
+    /// Resets the machine and loads rom at 0x200
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), String> {
+        let cpu = &mut self.machine.cpu;
+        cpu.reset();
+        cpu.load_rom(rom).map_err(|e| e.to_string())?;
+        self.machine.flush();
+        self.machine.set_paused(false);
+        self.paint();
+        Ok(())
+    }
+
+    /// Runs one 60Hz frame if one is due, see `Machine::tick`, then repaints
+    /// Fails with a message if an instruction does, pausing the machine as it was at that
+    /// instruction.
+    pub fn run_frame(&mut self) -> Result<(), String> {
+        let result = self.machine.update();
+        self.paint();
+        match result {
+            Ok(_) => Ok(()),
+            Err(e) => Err(format!("{} at {:#05x}", e, self.machine.cpu.pc())),
+        }
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.machine.toggle_pause();
+    }
+
+    /// Pauses, and has the next `run_frame` run exactly one frame
+    pub fn advance_frame(&mut self) {
+        self.machine.advance_frame();
+    }
+
+    /// Steps slow motion through half, quarter and normal speed
+    pub fn slower(&mut self) {
+        self.machine.set_speed(self.machine.speed().slower());
+    }
+
+    pub fn toggle_fast_forward(&mut self) {
+        let speed = if self.machine.speed() == Speed::FastForward {
+            Speed::Normal
+        } else {
+            Speed::FastForward
+        };
+        self.machine.set_speed(speed);
+    }
+
+    /// False while fast-forwarding, when the page should call `run_frame` several times per
+    /// animation frame
+    pub fn throttled(&self) -> bool {
+        self.machine.throttled()
+    }
+
+    /// "PAUSED", "0.5X" and so on for an indicator, undefined at normal speed
+    pub fn status(&self) -> Option<String> {
+        self.machine.status().map(str::to_string)
+    }
+
+    /// Presses key 0-F
+    pub fn key_down(&mut self, key: u8) {
+        self.machine.cpu.keyboard.key_down(key as usize & 0xF);
+    }
+
+    /// Releases key 0-F
+    pub fn key_up(&mut self, key: u8) {
+        self.machine.cpu.keyboard.key_up(key as usize & 0xF);
+    }
+
+    /// Where the RGBA frame starts in the module's memory, `width() * height() * 4` bytes
+    /// The pointer stays valid for the emulator's lifetime.
+    pub fn framebuffer(&self) -> *const u8 {
+        self.frame.as_ptr() as *const u8
+    }
+
+    pub fn width() -> usize {
+        WIDTH
+    }
+
+    pub fn height() -> usize {
+        HEIGHT
+    }
+
+    /// Whether the buzzer should sound, i.e. the sound timer is running
+    pub fn sound(&self) -> bool {
+        self.machine.cpu.st() > 0
+    }
+
+    pub fn set_cycles_per_frame(&mut self, cycles: u32) {
+        self.machine.set_ips(cycles * 60);
+    }
+}
+
+impl Emulator {
+    /// Redraws the rows that changed since the last paint
+    fn paint(&mut self) {
+        // Bytes in memory order, whatever the target's endianness
+        let on = u32::from_ne_bytes(ON);
+        let off = u32::from_ne_bytes(OFF);
+        let display = &mut self.machine.cpu.display;
+        let rows = display.take_dirty();
+        render::scale(display, rows, &mut self.frame, WIDTH, 1, on, off);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_show_up_in_the_framebuffer() {
+        let mut emulator = Emulator::new(1);
+        #[rustfmt::skip]
+        emulator.load_rom(&[
+            0x60, 0x0A, // 200: LD V0, 0x0A
+            0xF0, 0x29, // 202: LD F, V0
+            0xD1, 0x15, // 204: DRW V1, V1, 5
+            0x12, 0x06, // 206: JP 0x206
+        ])
+        .unwrap();
+        emulator.run_frame().unwrap();
+        emulator.toggle_pause();
+        assert_eq!(emulator.status().as_deref(), Some("PAUSED"));
+
+        let bytes = unsafe {
+            std::slice::from_raw_parts(
+                emulator.framebuffer(),
+                Emulator::width() * Emulator::height() * 4,
+            )
+        };
+        // The top of the "A" glyph, 0xF0, at the top left
+        assert_eq!(bytes[..4], ON);
+        assert_eq!(bytes[3 * 4..4 * 4], ON);
+        assert_eq!(bytes[4 * 4..5 * 4], OFF);
+        assert!(emulator.load_rom(&[0; 4096]).is_err());
+    }
+}
@@ -0,0 +1,203 @@
+//! Builds a CPU in a known state, runs one opcode and describes what changed, and
+//! generates random opcodes for property tests
+#![allow(dead_code)]
+
+use chip_8::cpu::display::{HEIGHT, WIDTH};
+use chip_8::cpu::CPU;
+use proptest::prelude::*;
+
+/// Everything an opcode can change
+#[derive(Clone, PartialEq)]
+pub struct State {
+    pub memory: Vec<u8>,
+    pub v: [u8; 16],
+    pub i: u16,
+    pub dt: u16,
+    pub st: u16,
+    pub pc: u16,
+    pub sp: u8,
+    pub stack: [u16; 16],
+    pub screen: Vec<u8>,
+}
+
+impl State {
+    pub fn of(cpu: &mut CPU) -> State {
+        State {
+            memory: cpu.memory().to_vec(),
+            v: *cpu.v(),
+            i: cpu.i(),
+            dt: cpu.dt(),
+            st: cpu.st(),
+            pc: cpu.pc(),
+            sp: cpu.sp(),
+            stack: *cpu.stack(),
+            screen: cpu.display.screen().to_vec(),
+        }
+    }
+
+    /// One line per difference from before, e.g. "PC 0x200 -> 0x202" or "V3 0x00 -> 0x07"
+    pub fn diff(&self, before: &State) -> Vec<String> {
+        let mut changes = Vec::new();
+        let mut change = |name: String, old: String, new: String| {
+            changes.push(format!("{} {} -> {}", name, old, new))
+        };
+        for x in 0..16 {
+            if before.v[x] != self.v[x] {
+                change(format!("V{:X}", x), hex8(before.v[x]), hex8(self.v[x]));
+            }
+        }
+        if before.i != self.i {
+            change("I".into(), hex16(before.i), hex16(self.i));
+        }
+        if before.dt != self.dt {
+            change("DT".into(), hex16(before.dt), hex16(self.dt));
+        }
+        if before.st != self.st {
+            change("ST".into(), hex16(before.st), hex16(self.st));
+        }
+        if before.pc != self.pc {
+            change("PC".into(), hex16(before.pc), hex16(self.pc));
+        }
+        if before.sp != self.sp {
+            change("SP".into(), before.sp.to_string(), self.sp.to_string());
+        }
+        for level in 0..16 {
+            if before.stack[level] != self.stack[level] {
+                let (old, new) = (before.stack[level], self.stack[level]);
+                change(format!("stack[{}]", level), hex16(old), hex16(new));
+            }
+        }
+        for address in 0..self.memory.len() {
+            if before.memory[address] != self.memory[address] {
+                let (old, new) = (before.memory[address], self.memory[address]);
+                change(format!("[{:#05x}]", address), hex8(old), hex8(new));
+            }
+        }
+        for index in 0..WIDTH * HEIGHT {
+            if before.screen[index] != self.screen[index] {
+                let (old, new) = (before.screen[index], self.screen[index]);
+                let pixel = format!("({}, {})", index % WIDTH, index / WIDTH);
+                change(pixel, old.to_string(), new.to_string());
+            }
+        }
+        changes
+    }
+}
+
+fn hex8(value: u8) -> String {
+    format!("{:#04x}", value)
+}
+
+fn hex16(value: u16) -> String {
+    format!("{:#05x}", value)
+}
+
+/// A CPU set up for a single opcode test
+pub struct Setup {
+    pub cpu: CPU,
+}
+
+impl Setup {
+    /// A reset CPU, so PC is 0x200 and the font is loaded
+    pub fn new() -> Setup {
+        let mut cpu = CPU::new();
+        cpu.reset();
+        Setup { cpu }
+    }
+
+    pub fn v(mut self, x: usize, value: u8) -> Setup {
+        self.cpu.set_v(x, value);
+        self
+    }
+
+    pub fn i(mut self, value: u16) -> Setup {
+        self.cpu.set_i(value);
+        self
+    }
+
+    pub fn dt(mut self, value: u16) -> Setup {
+        self.cpu.set_dt(value);
+        self
+    }
+
+    pub fn pc(mut self, value: u16) -> Setup {
+        self.cpu.set_pc(value);
+        self
+    }
+
+    /// Pushes return addresses as if CALL had run from each of them
+    pub fn calls(mut self, returns: &[u16]) -> Setup {
+        for (level, &address) in returns.iter().enumerate() {
+            let opcode = 0x2000 | (address & 0xFFF);
+            let pc = self.cpu.pc();
+            self.cpu.set_pc(address - 2);
+            self.cpu.execute(opcode).unwrap();
+            // Leave PC where it was, only the stack matters
+            self.cpu.set_pc(pc);
+            assert_eq!(self.cpu.sp() as usize, level + 1);
+        }
+        self
+    }
+
+    pub fn memory(mut self, address: u16, bytes: &[u8]) -> Setup {
+        for (offset, &byte) in bytes.iter().enumerate() {
+            self.cpu.poke(address + offset as u16, byte);
+        }
+        self
+    }
+
+    pub fn key(mut self, key: usize) -> Setup {
+        self.cpu.keyboard.key_down(key);
+        self
+    }
+
+    /// Lights the given pixels
+    pub fn pixels(mut self, pixels: &[(usize, usize)]) -> Setup {
+        for &(x, y) in pixels {
+            self.cpu.display.set_pixel(x, y, true);
+        }
+        self
+    }
+
+    /// Executes opcode and returns the changes it made
+    pub fn run(mut self, opcode: u16) -> Vec<String> {
+        let before = State::of(&mut self.cpu);
+        self.cpu.execute(opcode).unwrap();
+        State::of(&mut self.cpu).diff(&before)
+    }
+}
+
+/// Runs opcode on setup and checks that it made exactly the expected changes
+pub fn assert_changes(setup: Setup, opcode: u16, expected: &[&str]) {
+    let changes = setup.run(opcode);
+    assert_eq!(changes, expected, "opcode {:04X}", opcode);
+}
+
+/// Opcode patterns and the bits that are free in each, so every instruction gets generated
+/// often, not just the ones with a lot of free bits
+#[rustfmt::skip]
+const PATTERNS: [(u16, u16); 34] = [
+    (0x00E0, 0x000), (0x00EE, 0x000), (0x0000, 0xFFF), (0x1000, 0xFFF), (0x2000, 0xFFF),
+    (0x3000, 0xFFF), (0x4000, 0xFFF), (0x5000, 0xFF0), (0x6000, 0xFFF), (0x7000, 0xFFF),
+    (0x8000, 0xFF0), (0x8001, 0xFF0), (0x8002, 0xFF0), (0x8003, 0xFF0), (0x8004, 0xFF0),
+    (0x8005, 0xFF0), (0x8006, 0xFF0), (0x8007, 0xFF0), (0x800E, 0xFF0), (0x9000, 0xFF0),
+    (0xA000, 0xFFF), (0xB000, 0xFFF), (0xC000, 0xF00), (0xD000, 0xFFF), (0xE09E, 0xF00),
+    (0xE0A1, 0xF00), (0xF007, 0xF00), (0xF00A, 0xF00), (0xF015, 0xF00), (0xF018, 0xF00),
+    (0xF01E, 0xF00), (0xF029, 0xF00), (0xF033, 0xF00), (0xF055, 0xF00),
+];
+
+/// Random opcodes, mostly valid ones
+/// Cxkk always has kk = 0, since random numbers can't be compared between runs.
+pub fn opcode() -> impl Strategy<Value = u16> {
+    let pattern = (0..PATTERNS.len(), any::<u16>())
+        .prop_map(|(index, bits)| PATTERNS[index].0 | (bits & PATTERNS[index].1));
+    // Fx65 doesn't fit in the table above
+    let load = (0..16u16).prop_map(|x| 0xF065 | x << 8);
+    prop_oneof![8 => pattern, 1 => load, 1 => any::<u16>()].prop_map(|opcode| {
+        if opcode & 0xF000 == 0xC000 {
+            opcode & 0xFF00
+        } else {
+            opcode
+        }
+    })
+}
@@ -0,0 +1,277 @@
+//! Runs test ROMs headlessly and compares the final screen against golden images
+//!
+//! ROMs live in tests/roms and goldens in tests/golden, see tests/roms/README.md for where to
+//! get them. The ROMs aren't checked in, so their tests are ignored unless run with
+//! `--ignored`, and then a missing ROM or golden fails. Every run is also checked against the
+//! reference interpreter in tests/reference, and with `UPDATE_GOLDENS=1` the goldens are
+//! rewritten from the reference's screen, never from the emulator's own output.
+
+mod reference;
+
+use chip_8::cpu::display::{HEIGHT, WIDTH};
+use chip_8::cpu::quirks::Quirks;
+use chip_8::cpu::CPU;
+use chip_8::engine::Engine;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use reference::Machine;
+
+const CYCLES_PER_FRAME: u64 = 10;
+
+struct Case {
+    name: &'static str,
+    rom: &'static str,
+    frames: u64,
+    quirks: Quirks,
+    /// Value stored at 0x1FF before the ROM starts, which some test ROMs read to skip
+    /// their menu
+    preset: Option<u8>,
+    /// Keys pressed at the start of a frame and held for 5 frames
+    presses: &'static [(u64, usize)],
+}
+
+fn dir(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join(name)
+}
+
+/// Runs rom for the case's frames on engine and renders the screen, '#' for lit pixels and
+/// '.' for unlit
+fn run(case: &Case, rom: &[u8], mut engine: Engine) -> String {
+    let mut cpu = CPU::new();
+    cpu.reset();
+    cpu.quirks = case.quirks;
+    cpu.load_rom(rom).unwrap();
+    if let Some(value) = case.preset {
+        cpu.poke(0x1FF, value);
+    }
+    for frame in 0..case.frames {
+        for &(at, key) in case.presses {
+            if frame == at {
+                cpu.keyboard.key_down(key);
+            } else if frame == at + 5 {
+                cpu.keyboard.key_up(key);
+            }
+        }
+        if let Err(error) = engine.run(&mut cpu, CYCLES_PER_FRAME) {
+            panic!(
+                "{} failed at {:#05x} on the {} engine: {}",
+                case.name,
+                cpu.pc(),
+                engine.name(),
+                error
+            );
+        }
+        cpu.decrement_timers();
+    }
+
+    let screen = cpu.display.screen();
+    let mut image = String::with_capacity((WIDTH + 1) * HEIGHT);
+    for row in screen.chunks(WIDTH) {
+        image.extend(row.iter().map(|&pixel| if pixel == 1 { '#' } else { '.' }));
+        image.push('\n');
+    }
+    image
+}
+
+/// `run` on the reference interpreter
+fn run_reference(case: &Case, rom: &[u8]) -> String {
+    let mut machine = Machine::new(case.quirks);
+    machine.memory[0x200..0x200 + rom.len()].copy_from_slice(rom);
+    if let Some(value) = case.preset {
+        machine.memory[0x1FF] = value;
+    }
+    for frame in 0..case.frames {
+        for &(at, key) in case.presses {
+            if frame == at {
+                machine.keys[key] = true;
+            } else if frame == at + 5 {
+                machine.keys[key] = false;
+            }
+        }
+        for _ in 0..CYCLES_PER_FRAME {
+            let pc = machine.pc as usize;
+            assert!(pc < 0xFFF, "{} ran off the end of memory", case.name);
+            let opcode = u16::from_be_bytes([machine.memory[pc], machine.memory[pc + 1]]);
+            if let Err(error) = machine.step(opcode) {
+                panic!(
+                    "{} failed at {:#05x} on the reference: {}",
+                    case.name, pc, error
+                );
+            }
+        }
+        machine.tick();
+    }
+
+    let mut image = String::with_capacity((WIDTH + 1) * HEIGHT);
+    for row in machine.screen.iter() {
+        image.extend(row.iter().map(|&lit| if lit { '#' } else { '.' }));
+        image.push('\n');
+    }
+    image
+}
+
+/// Compares rom's output against the reference interpreter and its golden image, or rewrites
+/// the golden with UPDATE_GOLDENS set
+fn check(case: &Case, rom: &[u8]) {
+    let image = run(case, rom, Engine::Interpreter);
+    let reference = run_reference(case, rom);
+    assert!(
+        image == reference,
+        "{} differs from the reference interpreter, got:\n{}\nexpected:\n{}",
+        case.name,
+        image,
+        reference
+    );
+    for name in Engine::NAMES.iter() {
+        let engine = Engine::from_name(name).unwrap();
+        assert!(
+            run(case, rom, engine) == image,
+            "{} differs on the {} engine",
+            case.name,
+            name
+        );
+    }
+    let golden = dir("golden").join(format!("{}.txt", case.name));
+    if env::var_os("UPDATE_GOLDENS").is_some() {
+        fs::create_dir_all(dir("golden")).unwrap();
+        fs::write(&golden, &reference).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&golden).unwrap_or_else(|_| {
+        panic!(
+            "no golden image {}, see tests/roms/README.md to create it",
+            golden.display()
+        )
+    });
+    assert!(
+        image == expected,
+        "{} differs from {}, got:\n{}",
+        case.name,
+        golden.display(),
+        image
+    );
+}
+
+/// Runs a case whose ROM is read from tests/roms
+fn check_file(case: &Case) {
+    let path = dir("roms").join(case.rom);
+    let rom = fs::read(&path).unwrap_or_else(|e| {
+        panic!(
+            "{}: {}, see tests/roms/README.md for where to get it",
+            path.display(),
+            e
+        )
+    });
+    check(case, &rom);
+}
+
+#[test]
+fn smoke() {
+    // Draws "C8" with the built-in font, then loops forever
+    #[rustfmt::skip]
+    let rom = [
+        0x60, 0x0C, // LD V0, 0x0C
+        0xF0, 0x29, // LD F, V0
+        0x61, 0x01, // LD V1, 0x01
+        0x62, 0x01, // LD V2, 0x01
+        0xD1, 0x25, // DRW V1, V2, 5
+        0x60, 0x08, // LD V0, 0x08
+        0xF0, 0x29, // LD F, V0
+        0x61, 0x06, // LD V1, 0x06
+        0xD1, 0x25, // DRW V1, V2, 5
+        0x12, 0x12, // JP 0x212
+    ];
+    let case = Case {
+        name: "smoke",
+        rom: "",
+        frames: 2,
+        quirks: Quirks::default(),
+        preset: None,
+        presses: &[],
+    };
+    check(&case, &rom);
+}
+
+#[test]
+#[ignore = "needs the test ROMs, see tests/roms/README.md"]
+fn chip8_logo() {
+    check_file(&Case {
+        name: "chip8_logo",
+        rom: "1-chip8-logo.ch8",
+        frames: 60,
+        quirks: Quirks::default(),
+        preset: None,
+        presses: &[],
+    });
+}
+
+#[test]
+#[ignore = "needs the test ROMs, see tests/roms/README.md"]
+fn ibm_logo() {
+    check_file(&Case {
+        name: "ibm_logo",
+        rom: "2-ibm-logo.ch8",
+        frames: 60,
+        quirks: Quirks::default(),
+        preset: None,
+        presses: &[],
+    });
+}
+
+#[test]
+#[ignore = "needs the test ROMs, see tests/roms/README.md"]
+fn opcodes() {
+    check_file(&Case {
+        name: "opcodes",
+        rom: "3-corax+.ch8",
+        frames: 120,
+        quirks: Quirks::default(),
+        preset: None,
+        presses: &[],
+    });
+}
+
+#[test]
+#[ignore = "needs the test ROMs, see tests/roms/README.md"]
+fn flags() {
+    check_file(&Case {
+        name: "flags",
+        rom: "4-flags.ch8",
+        frames: 120,
+        quirks: Quirks::default(),
+        preset: None,
+        presses: &[],
+    });
+}
+
+#[test]
+#[ignore = "needs the test ROMs, see tests/roms/README.md"]
+fn quirks() {
+    check_file(&Case {
+        name: "quirks",
+        rom: "5-quirks.ch8",
+        frames: 600,
+        quirks: Quirks::CHIP8,
+        // Plain CHIP-8 in the ROM's menu
+        preset: Some(1),
+        presses: &[],
+    });
+}
+
+#[test]
+#[ignore = "needs the test ROMs, see tests/roms/README.md"]
+fn keypad() {
+    check_file(&Case {
+        name: "keypad",
+        rom: "6-keypad.ch8",
+        frames: 120,
+        quirks: Quirks::default(),
+        // Ex9E test, with a few keys held down
+        preset: Some(1),
+        presses: &[(30, 0x1), (30, 0x5), (30, 0xF)],
+    });
+}
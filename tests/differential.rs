@@ -0,0 +1,185 @@
+//! Runs random instruction sequences through `CPU` and the reference interpreter in
+//! tests/reference, comparing the whole machine after every step, once per quirk profile
+#![cfg(not(target_arch = "wasm32"))]
+
+mod common;
+mod reference;
+
+use chip_8::cpu::display::WIDTH;
+use chip_8::cpu::quirks::Quirks;
+use chip_8::cpu::CPU;
+use common::{opcode, State};
+use proptest::prelude::*;
+use reference::Machine;
+
+#[derive(Clone, Debug)]
+enum Step {
+    Execute(u16),
+    Tick,
+    Press(usize),
+    Release(usize),
+}
+
+fn step() -> impl Strategy<Value = Step> {
+    prop_oneof![
+        12 => opcode().prop_map(Step::Execute),
+        1 => Just(Step::Tick),
+        1 => (0..16usize).prop_map(Step::Press),
+        1 => (0..16usize).prop_map(Step::Release),
+    ]
+}
+
+/// The part of a machine a test starts from
+#[derive(Clone, Debug)]
+struct Start {
+    memory: Vec<u8>,
+    v: [u8; 16],
+    i: u16,
+    dt: u8,
+    st: u8,
+    keys: [bool; 16],
+    pixels: Vec<bool>,
+}
+
+fn start() -> impl Strategy<Value = Start> {
+    (
+        prop::collection::vec(any::<u8>(), 0x1000 - 0x200),
+        any::<[u8; 16]>(),
+        0..0x1000u16,
+        any::<u8>(),
+        any::<u8>(),
+        any::<[bool; 16]>(),
+        prop::collection::vec(prop::bool::weighted(0.2), 64 * 32),
+    )
+        .prop_map(|(program, v, i, dt, st, keys, pixels)| Start {
+            memory: program,
+            v,
+            i,
+            dt,
+            st,
+            keys,
+            pixels,
+        })
+}
+
+fn setup(start: &Start, quirks: Quirks) -> (CPU, Machine) {
+    let mut cpu = CPU::new();
+    cpu.reset();
+    cpu.quirks = quirks;
+    let mut machine = Machine::new(quirks);
+
+    for (offset, &byte) in start.memory.iter().enumerate() {
+        cpu.poke(0x200 + offset as u16, byte);
+        machine.memory[0x200 + offset] = byte;
+    }
+    for x in 0..16 {
+        cpu.set_v(x, start.v[x]);
+        if start.keys[x] {
+            cpu.keyboard.key_down(x);
+        }
+    }
+    machine.v = start.v;
+    machine.keys = start.keys;
+    cpu.set_i(start.i);
+    machine.i = start.i;
+    cpu.set_dt(start.dt as u16);
+    cpu.set_st(start.st as u16);
+    machine.dt = start.dt as u16;
+    machine.st = start.st as u16;
+    for (index, &lit) in start.pixels.iter().enumerate() {
+        cpu.display.set_pixel(index % WIDTH, index / WIDTH, lit);
+        machine.screen[index / WIDTH][index % WIDTH] = lit;
+    }
+    (cpu, machine)
+}
+
+/// The reference machine in the same shape as `CPU`'s state
+fn state(machine: &Machine) -> State {
+    let mut stack = [0; 16];
+    stack[..machine.stack.len()].copy_from_slice(&machine.stack);
+    State {
+        memory: machine.memory.clone(),
+        v: machine.v,
+        i: machine.i,
+        dt: machine.dt,
+        st: machine.st,
+        pc: machine.pc,
+        sp: machine.stack.len() as u8,
+        stack,
+        screen: machine
+            .screen
+            .iter()
+            .flat_map(|row| row.iter().map(|&lit| lit as u8))
+            .collect(),
+    }
+}
+
+/// `CPU`'s state, leaving out the stale return addresses above SP
+fn cpu_state(cpu: &mut CPU) -> State {
+    let mut state = State::of(cpu);
+    for entry in state.stack.iter_mut().skip(state.sp as usize) {
+        *entry = 0;
+    }
+    state
+}
+
+fn check(start: Start, steps: Vec<Step>, quirks: Quirks) -> Result<(), TestCaseError> {
+    let (mut cpu, mut machine) = setup(&start, quirks);
+    for (index, step) in steps.iter().enumerate() {
+        match *step {
+            Step::Execute(opcode) => {
+                let expected = machine.step(opcode);
+                let actual = cpu.execute(opcode);
+                prop_assert_eq!(actual, expected, "step {} ({:04X})", index, opcode);
+            }
+            Step::Tick => {
+                machine.tick();
+                cpu.decrement_timers();
+            }
+            Step::Press(key) => {
+                machine.keys[key] = true;
+                cpu.keyboard.key_down(key);
+            }
+            Step::Release(key) => {
+                machine.keys[key] = false;
+                cpu.keyboard.key_up(key);
+            }
+        }
+        let expected = state(&machine);
+        let actual = cpu_state(&mut cpu);
+        prop_assert!(
+            actual == expected,
+            "step {} ({:X?}) differs from the reference: {:?}",
+            index,
+            step,
+            actual.diff(&expected)
+        );
+    }
+    Ok(())
+}
+
+proptest! {
+    #[test]
+    fn matches_reference_with_default_quirks(
+        start in start(),
+        steps in prop::collection::vec(step(), 1..64),
+    ) {
+        check(start, steps, Quirks::default())?;
+    }
+
+    #[test]
+    fn matches_reference_with_chip8_quirks(
+        start in start(),
+        steps in prop::collection::vec(step(), 1..64),
+    ) {
+        check(start, steps, Quirks::CHIP8)?;
+    }
+
+    #[test]
+    fn matches_reference_with_schip_quirks(
+        start in start(),
+        steps in prop::collection::vec(step(), 1..64),
+    ) {
+        check(start, steps, Quirks::SCHIP)?;
+    }
+}
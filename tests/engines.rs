@@ -0,0 +1,158 @@
+//! Checks that every engine leaves the CPU exactly as the plain interpreter does
+#![cfg(not(target_arch = "wasm32"))]
+
+mod common;
+
+use chip_8::cpu::hook::Hook;
+use chip_8::cpu::instruction::Instruction;
+use chip_8::cpu::quirks::Quirks;
+use chip_8::cpu::{Error, CPU};
+use chip_8::engine::Engine;
+use common::opcode;
+use proptest::prelude::*;
+
+/// Notices Cxkk with a non-zero mask, after which runs can't be compared
+#[derive(Default)]
+struct RandomDetector {
+    seen: bool,
+}
+
+impl Hook for RandomDetector {
+    fn before_instruction(&mut self, _cpu: &CPU, opcode: u16) {
+        if let Instruction::Rnd(_, kk) = Instruction::decode(opcode) {
+            self.seen |= kk != 0;
+        }
+    }
+}
+
+fn quirks() -> impl Strategy<Value = Quirks> {
+    prop_oneof![
+        Just(Quirks::default()),
+        Just(Quirks::CHIP8),
+        Just(Quirks::SCHIP)
+    ]
+}
+
+/// Points the addresses in jumps, calls and Annn into the program itself, so programs loop
+/// and overwrite their own code
+fn aim(program: Vec<u16>) -> Vec<u16> {
+    let size = program.len() as u16 * 2;
+    program
+        .into_iter()
+        .map(|opcode| match opcode >> 12 {
+            0x1 | 0x2 | 0xA | 0xB => opcode & 0xF000 | (0x200 + (opcode & 0xFFF) % size),
+            _ => opcode,
+        })
+        .collect()
+}
+
+fn machine(program: &[u16], v: [u8; 16], keys: u16, quirks: Quirks) -> CPU {
+    let mut cpu = CPU::new();
+    cpu.reset();
+    cpu.quirks = quirks;
+    let rom: Vec<u8> = program.iter().flat_map(|op| op.to_be_bytes()).collect();
+    cpu.load_rom(&rom).unwrap();
+    for (x, &value) in v.iter().enumerate() {
+        cpu.set_v(x, value);
+        if keys & 1 << x != 0 {
+            cpu.keyboard.key_down(x);
+        }
+    }
+    cpu
+}
+
+/// Runs the plain interpreter, or None if the program used random numbers
+fn interpret(cpu: &mut CPU, cycles: u64) -> Option<Result<(), Error>> {
+    let mut detector = RandomDetector::default();
+    let mut result = Ok(());
+    for _ in 0..cycles {
+        result = cpu.execute_cycle_with(&mut detector);
+        if detector.seen {
+            return None;
+        }
+        if result.is_err() {
+            break;
+        }
+    }
+    Some(result)
+}
+
+fn check(engine: &str, program: &[u16], v: [u8; 16], keys: u16, quirks: Quirks, cycles: u64) {
+    let mut expected = machine(program, v, keys, quirks);
+    let expected_result = match interpret(&mut expected, cycles) {
+        Some(result) => result,
+        None => return,
+    };
+
+    let mut actual = machine(program, v, keys, quirks);
+    let mut engine = Engine::from_name(engine).unwrap();
+    // Split the run so engines also get resumed part way through their blocks
+    let result = engine
+        .run(&mut actual, cycles / 3)
+        .and_then(|()| engine.run(&mut actual, cycles - cycles / 3));
+
+    assert_eq!(result, expected_result);
+    assert!(
+        actual.save_state()[..] == expected.save_state()[..],
+        "{} engine differs from the interpreter at PC {:#05x} after {} cycles",
+        engine.name(),
+        actual.pc(),
+        actual.cycles()
+    );
+}
+
+#[test]
+fn self_modifying_code_is_run_as_written() {
+    #[rustfmt::skip]
+    let rom = [
+        0xA2, 0x0C, // 200: LD I, 0x20C
+        0x60, 0x74, // 202: LD V0, 0x74
+        0x61, 0x05, // 204: LD V1, 0x05
+        0x22, 0x0C, // 206: CALL 0x20C
+        0xF1, 0x55, // 208: LD [I], V1, turning 20C into ADD V4, 5
+        0x22, 0x0C, // 20A: CALL 0x20C
+        0x73, 0x01, // 20C: ADD V3, 1
+        0x00, 0xEE, // 20E: RET
+    ];
+    for name in Engine::NAMES {
+        let mut cpu = CPU::new();
+        cpu.reset();
+        cpu.load_rom(&rom).unwrap();
+        let mut engine = Engine::from_name(name).unwrap();
+        engine.run(&mut cpu, 10).unwrap();
+        assert_eq!((cpu.v()[3], cpu.v()[4]), (1, 5), "{}", name);
+        assert_eq!((cpu.pc(), cpu.cycles()), (0x20C, 10), "{}", name);
+    }
+}
+
+proptest! {
+    // Self-modifying code is rare in random programs, so look at more of them
+    #![proptest_config(ProptestConfig::with_cases(1024))]
+
+    #[test]
+    fn cached_matches_interpreter(
+        program in prop::collection::vec(opcode(), 1..256).prop_map(aim),
+        v in any::<[u8; 16]>(),
+        keys in any::<u16>(),
+        quirks in quirks(),
+        cycles in 1..5000u64,
+    ) {
+        check("cached", &program, v, keys, quirks, cycles);
+    }
+}
+
+#[cfg(all(feature = "jit", target_arch = "x86_64"))]
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(1024))]
+
+    #[test]
+    fn jit_matches_interpreter(
+        program in prop::collection::vec(opcode(), 1..256).prop_map(aim),
+        v in any::<[u8; 16]>(),
+        keys in any::<u16>(),
+        quirks in quirks(),
+        cycles in 1..5000u64,
+    ) {
+        check("jit", &program, v, keys, quirks, cycles);
+    }
+}
@@ -0,0 +1,216 @@
+// A minimal headless libretro frontend: loads the built core like RetroArch would and checks
+// what comes out of its callbacks
+#![cfg(feature = "libretro")]
+
+use libloading::{Library, Symbol};
+use std::os::raw::{c_char, c_uint, c_void};
+use std::ptr;
+use std::slice;
+use std::sync::Mutex;
+
+const WIDTH: usize = 64;
+const HEIGHT: usize = 32;
+const ON: u32 = 0x0000_FFFF;
+const JOYPAD_A: c_uint = 8;
+
+#[repr(C)]
+struct SystemAvInfo {
+    base_width: c_uint,
+    base_height: c_uint,
+    max_width: c_uint,
+    max_height: c_uint,
+    aspect_ratio: f32,
+    fps: f64,
+    sample_rate: f64,
+}
+
+#[repr(C)]
+struct GameInfo {
+    path: *const c_char,
+    data: *const c_void,
+    size: usize,
+    meta: *const c_char,
+}
+
+#[repr(C)]
+struct InputDescriptor {
+    port: c_uint,
+    device: c_uint,
+    index: c_uint,
+    id: c_uint,
+    description: *const c_char,
+}
+
+/// What the core handed the frontend
+struct Host {
+    pixel_format: Option<c_uint>,
+    buttons: Vec<c_uint>,
+    frame: Vec<u32>,
+    frames: usize,
+    audio: Vec<i16>,
+    pressed: Option<c_uint>,
+}
+
+static HOST: Mutex<Host> = Mutex::new(Host {
+    pixel_format: None,
+    buttons: Vec::new(),
+    frame: Vec::new(),
+    frames: 0,
+    audio: Vec::new(),
+    pressed: None,
+});
+
+extern "C" fn environment(cmd: c_uint, data: *mut c_void) -> bool {
+    let mut host = HOST.lock().unwrap();
+    match cmd {
+        // SET_PIXEL_FORMAT
+        10 => {
+            host.pixel_format = Some(unsafe { *(data as *const c_uint) });
+            true
+        }
+        // SET_INPUT_DESCRIPTORS
+        11 => {
+            let mut descriptor = data as *const InputDescriptor;
+            unsafe {
+                while !(*descriptor).description.is_null() {
+                    host.buttons.push((*descriptor).id);
+                    descriptor = descriptor.add(1);
+                }
+            }
+            true
+        }
+        _ => false,
+    }
+}
+
+extern "C" fn video_refresh(data: *const c_void, width: c_uint, height: c_uint, pitch: usize) {
+    assert_eq!((width as usize, height as usize), (WIDTH, HEIGHT));
+    let mut host = HOST.lock().unwrap();
+    let pixels = unsafe { slice::from_raw_parts(data as *const u32, pitch / 4 * HEIGHT) };
+    host.frame = pixels.to_vec();
+    host.frames += 1;
+}
+
+extern "C" fn audio_sample(_left: i16, _right: i16) {}
+
+extern "C" fn audio_sample_batch(data: *const i16, frames: usize) -> usize {
+    let samples = unsafe { slice::from_raw_parts(data, frames * 2) };
+    HOST.lock().unwrap().audio = samples.to_vec();
+    frames
+}
+
+extern "C" fn input_poll() {}
+
+extern "C" fn input_state(port: c_uint, device: c_uint, _index: c_uint, id: c_uint) -> i16 {
+    let pressed = HOST.lock().unwrap().pressed;
+    (port == 0 && device == 1 && pressed == Some(id)) as i16
+}
+
+/// The cdylib cargo built for this test, next to it in target/*/deps
+fn core() -> Library {
+    let exe = std::env::current_exe().unwrap();
+    let path = exe.with_file_name(libloading::library_filename("chip_8"));
+    unsafe { Library::new(&path) }.unwrap_or_else(|e| panic!("{}: {}", path.display(), e))
+}
+
+#[test]
+fn runs_frames_headlessly() {
+    let core = core();
+    unsafe {
+        macro_rules! call {
+            ($name:ident: $type:ty $(, $arg:expr)*) => {{
+                let function: Symbol<$type> = core.get(stringify!($name).as_bytes()).unwrap();
+                function($($arg),*)
+            }};
+        }
+
+        assert_eq!(call!(retro_api_version: extern "C" fn() -> c_uint), 1);
+        call!(retro_set_environment: extern "C" fn(extern "C" fn(c_uint, *mut c_void) -> bool), environment);
+        call!(retro_set_video_refresh: extern "C" fn(extern "C" fn(*const c_void, c_uint, c_uint, usize)), video_refresh);
+        call!(retro_set_audio_sample: extern "C" fn(extern "C" fn(i16, i16)), audio_sample);
+        call!(retro_set_audio_sample_batch: extern "C" fn(extern "C" fn(*const i16, usize) -> usize), audio_sample_batch);
+        call!(retro_set_input_poll: extern "C" fn(extern "C" fn()), input_poll);
+        call!(retro_set_input_state: extern "C" fn(extern "C" fn(c_uint, c_uint, c_uint, c_uint) -> i16), input_state);
+        call!(retro_init: extern "C" fn());
+
+        let mut av = std::mem::zeroed::<SystemAvInfo>();
+        call!(retro_get_system_av_info: extern "C" fn(*mut SystemAvInfo), &mut av);
+        assert_eq!(
+            (av.base_width, av.base_height),
+            (WIDTH as c_uint, HEIGHT as c_uint)
+        );
+        assert_eq!(av.fps, 60.0);
+        let samples_per_frame = (av.sample_rate / av.fps) as usize;
+
+        #[rustfmt::skip]
+        let rom: [u8; 16] = [
+            0x60, 0x0A, // 200: LD V0, 0x0A
+            0xF0, 0x29, // 202: LD F, V0
+            0xD1, 0x15, // 204: DRW V1, V1, 5
+            0x62, 0x05, // 206: LD V2, 5
+            0x63, 0x3C, // 208: LD V3, 60
+            0xE2, 0xA1, // 20A: SKNP V2, buzz while key 5 is held
+            0xF3, 0x18, // 20C: LD ST, V3
+            0x12, 0x0A, // 20E: JP 0x20A
+        ];
+        let game = GameInfo {
+            path: ptr::null(),
+            data: rom.as_ptr() as *const c_void,
+            size: rom.len(),
+            meta: ptr::null(),
+        };
+        assert!(call!(retro_load_game: extern "C" fn(*const GameInfo) -> bool, &game));
+        {
+            let host = HOST.lock().unwrap();
+            assert_eq!(host.pixel_format, Some(1));
+            let mut buttons = host.buttons.clone();
+            buttons.sort_unstable();
+            assert_eq!(buttons, (0..16).collect::<Vec<_>>());
+        }
+
+        call!(retro_run: extern "C" fn());
+        {
+            let host = HOST.lock().unwrap();
+            assert_eq!(host.frames, 1);
+            // The top of the "A" glyph, 0xF0, at the top left
+            assert_eq!(host.frame[..4], [ON; 4]);
+            assert_ne!(host.frame[4], ON);
+            assert_eq!(host.audio.len(), samples_per_frame * 2);
+            assert!(host.audio.iter().all(|&sample| sample == 0));
+        }
+
+        let size = call!(retro_serialize_size: extern "C" fn() -> usize);
+        let mut quiet = vec![0u8; size];
+        assert!(
+            call!(retro_serialize: extern "C" fn(*mut c_void, usize) -> bool, quiet.as_mut_ptr() as *mut c_void, size)
+        );
+
+        // A on the joypad is key 5
+        HOST.lock().unwrap().pressed = Some(JOYPAD_A);
+        call!(retro_run: extern "C" fn());
+        HOST.lock().unwrap().pressed = None;
+        {
+            let host = HOST.lock().unwrap();
+            assert!(host.audio.iter().any(|&sample| sample > 0));
+            assert!(host.audio.iter().any(|&sample| sample < 0));
+        }
+
+        assert!(
+            call!(retro_unserialize: extern "C" fn(*const c_void, usize) -> bool, quiet.as_ptr() as *const c_void, size)
+        );
+        call!(retro_run: extern "C" fn());
+        assert!(HOST.lock().unwrap().audio.iter().all(|&sample| sample == 0));
+        let mut state = vec![0u8; size];
+        assert!(
+            !call!(retro_serialize: extern "C" fn(*mut c_void, usize) -> bool, state.as_mut_ptr() as *mut c_void, size - 1)
+        );
+        assert!(
+            !call!(retro_unserialize: extern "C" fn(*const c_void, usize) -> bool, state.as_ptr() as *const c_void, size)
+        );
+
+        call!(retro_reset: extern "C" fn());
+        call!(retro_unload_game: extern "C" fn());
+        call!(retro_deinit: extern "C" fn());
+        assert_eq!(HOST.lock().unwrap().frames, 3);
+    }
+}
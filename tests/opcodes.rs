@@ -0,0 +1,449 @@
+//! One test per opcode, each checking every change the opcode makes
+#![cfg(not(target_arch = "wasm32"))]
+
+mod common;
+
+use chip_8::cpu::Error;
+use common::{assert_changes, Setup, State};
+
+#[test]
+fn cls() {
+    let setup = Setup::new().pixels(&[(0, 0), (63, 31)]);
+    assert_changes(
+        setup,
+        0x00E0,
+        &["PC 0x200 -> 0x202", "(0, 0) 1 -> 0", "(63, 31) 1 -> 0"],
+    );
+}
+
+#[test]
+fn ret() {
+    let setup = Setup::new().calls(&[0x300, 0x400]).pc(0x500);
+    assert_changes(setup, 0x00EE, &["PC 0x500 -> 0x400", "SP 2 -> 1"]);
+}
+
+#[test]
+fn sys_is_ignored() {
+    assert_changes(Setup::new(), 0x0123, &["PC 0x200 -> 0x202"]);
+}
+
+#[test]
+fn jp() {
+    assert_changes(Setup::new(), 0x1ABC, &["PC 0x200 -> 0xabc"]);
+}
+
+#[test]
+fn call() {
+    assert_changes(
+        Setup::new(),
+        0x2ABC,
+        &["PC 0x200 -> 0xabc", "SP 0 -> 1", "stack[0] 0x000 -> 0x202"],
+    );
+}
+
+#[test]
+fn se_byte() {
+    assert_changes(Setup::new().v(3, 0x42), 0x3342, &["PC 0x200 -> 0x204"]);
+    assert_changes(Setup::new().v(3, 0x41), 0x3342, &["PC 0x200 -> 0x202"]);
+}
+
+#[test]
+fn sne_byte() {
+    assert_changes(Setup::new().v(3, 0x41), 0x4342, &["PC 0x200 -> 0x204"]);
+    assert_changes(Setup::new().v(3, 0x42), 0x4342, &["PC 0x200 -> 0x202"]);
+}
+
+#[test]
+fn se_reg() {
+    let equal = Setup::new().v(1, 7).v(2, 7);
+    assert_changes(equal, 0x5120, &["PC 0x200 -> 0x204"]);
+    let different = Setup::new().v(1, 7).v(2, 8);
+    assert_changes(different, 0x5120, &["PC 0x200 -> 0x202"]);
+}
+
+#[test]
+fn ld_byte() {
+    assert_changes(
+        Setup::new(),
+        0x6A42,
+        &["VA 0x00 -> 0x42", "PC 0x200 -> 0x202"],
+    );
+}
+
+#[test]
+fn add_byte_wraps_without_touching_vf() {
+    assert_changes(
+        Setup::new().v(1, 0xFF),
+        0x7102,
+        &["V1 0xff -> 0x01", "PC 0x200 -> 0x202"],
+    );
+}
+
+#[test]
+fn ld_reg() {
+    assert_changes(
+        Setup::new().v(2, 9),
+        0x8120,
+        &["V1 0x00 -> 0x09", "PC 0x200 -> 0x202"],
+    );
+}
+
+#[test]
+fn or() {
+    assert_changes(
+        Setup::new().v(1, 0b1100).v(2, 0b1010),
+        0x8121,
+        &["V1 0x0c -> 0x0e", "PC 0x200 -> 0x202"],
+    );
+}
+
+#[test]
+fn and() {
+    assert_changes(
+        Setup::new().v(1, 0b1100).v(2, 0b1010),
+        0x8122,
+        &["V1 0x0c -> 0x08", "PC 0x200 -> 0x202"],
+    );
+}
+
+#[test]
+fn xor() {
+    assert_changes(
+        Setup::new().v(1, 0b1100).v(2, 0b1010),
+        0x8123,
+        &["V1 0x0c -> 0x06", "PC 0x200 -> 0x202"],
+    );
+}
+
+#[test]
+fn add_reg_sets_carry() {
+    assert_changes(
+        Setup::new().v(1, 0xF0).v(2, 0x20),
+        0x8124,
+        &["V1 0xf0 -> 0x10", "VF 0x00 -> 0x01", "PC 0x200 -> 0x202"],
+    );
+    assert_changes(
+        Setup::new().v(1, 0x10).v(2, 0x20).v(0xF, 1),
+        0x8124,
+        &["V1 0x10 -> 0x30", "VF 0x01 -> 0x00", "PC 0x200 -> 0x202"],
+    );
+}
+
+#[test]
+fn sub_sets_not_borrow() {
+    assert_changes(
+        Setup::new().v(1, 0x30).v(2, 0x10),
+        0x8125,
+        &["V1 0x30 -> 0x20", "VF 0x00 -> 0x01", "PC 0x200 -> 0x202"],
+    );
+    assert_changes(
+        Setup::new().v(1, 0x10).v(2, 0x30).v(0xF, 1),
+        0x8125,
+        &["V1 0x10 -> 0xe0", "VF 0x01 -> 0x00", "PC 0x200 -> 0x202"],
+    );
+}
+
+#[test]
+fn shr() {
+    assert_changes(
+        Setup::new().v(1, 0b101),
+        0x8106,
+        &["V1 0x05 -> 0x02", "VF 0x00 -> 0x01", "PC 0x200 -> 0x202"],
+    );
+}
+
+#[test]
+fn subn_sets_not_borrow() {
+    assert_changes(
+        Setup::new().v(1, 0x10).v(2, 0x30),
+        0x8127,
+        &["V1 0x10 -> 0x20", "VF 0x00 -> 0x01", "PC 0x200 -> 0x202"],
+    );
+    assert_changes(
+        Setup::new().v(1, 0x30).v(2, 0x10).v(0xF, 1),
+        0x8127,
+        &["V1 0x30 -> 0xe0", "VF 0x01 -> 0x00", "PC 0x200 -> 0x202"],
+    );
+}
+
+#[test]
+fn shl() {
+    assert_changes(
+        Setup::new().v(1, 0b1000_0001),
+        0x810E,
+        &["V1 0x81 -> 0x02", "VF 0x00 -> 0x01", "PC 0x200 -> 0x202"],
+    );
+}
+
+#[test]
+fn sne_reg() {
+    let different = Setup::new().v(1, 7).v(2, 8);
+    assert_changes(different, 0x9120, &["PC 0x200 -> 0x204"]);
+    let equal = Setup::new().v(1, 7).v(2, 7);
+    assert_changes(equal, 0x9120, &["PC 0x200 -> 0x202"]);
+}
+
+#[test]
+fn ld_i() {
+    assert_changes(
+        Setup::new(),
+        0xA123,
+        &["I 0x000 -> 0x123", "PC 0x200 -> 0x202"],
+    );
+}
+
+#[test]
+fn jp_v0() {
+    assert_changes(Setup::new().v(0, 0x10), 0xB300, &["PC 0x200 -> 0x310"]);
+}
+
+#[test]
+fn rnd_is_masked() {
+    assert_changes(
+        Setup::new().v(1, 0xFF),
+        0xC100,
+        &["V1 0xff -> 0x00", "PC 0x200 -> 0x202"],
+    );
+    for _ in 0..100 {
+        let mut setup = Setup::new();
+        setup.cpu.execute(0xC10F).unwrap();
+        assert_eq!(setup.cpu.v()[1] & 0xF0, 0);
+    }
+}
+
+#[test]
+fn drw() {
+    let setup = Setup::new()
+        .v(1, 2)
+        .v(2, 3)
+        .i(0x300)
+        .memory(0x300, &[0b1010_0000]);
+    assert_changes(
+        setup,
+        0xD121,
+        &["PC 0x200 -> 0x202", "(2, 3) 0 -> 1", "(4, 3) 0 -> 1"],
+    );
+}
+
+#[test]
+fn drw_sets_vf_only_when_a_pixel_is_erased() {
+    let setup = Setup::new()
+        .v(1, 2)
+        .v(2, 3)
+        .i(0x300)
+        .memory(0x300, &[0b1000_0000])
+        .pixels(&[(2, 3)]);
+    assert_changes(
+        setup,
+        0xD121,
+        &["VF 0x00 -> 0x01", "PC 0x200 -> 0x202", "(2, 3) 1 -> 0"],
+    );
+
+    // Lit pixels the sprite doesn't cover are no collision
+    let setup = Setup::new()
+        .v(1, 2)
+        .v(2, 3)
+        .v(0xF, 1)
+        .i(0x300)
+        .memory(0x300, &[0b1000_0000])
+        .pixels(&[(3, 3)]);
+    assert_changes(
+        setup,
+        0xD121,
+        &["VF 0x01 -> 0x00", "PC 0x200 -> 0x202", "(2, 3) 0 -> 1"],
+    );
+}
+
+#[test]
+fn drw_wraps_around() {
+    let setup = Setup::new()
+        .v(1, 63)
+        .v(2, 31)
+        .i(0x300)
+        .memory(0x300, &[0b1100_0000, 0b1000_0000]);
+    assert_changes(
+        setup,
+        0xD122,
+        &[
+            "PC 0x200 -> 0x202",
+            "(63, 0) 0 -> 1",
+            "(0, 31) 0 -> 1",
+            "(63, 31) 0 -> 1",
+        ],
+    );
+}
+
+#[test]
+fn skp() {
+    assert_changes(Setup::new().v(1, 5).key(5), 0xE19E, &["PC 0x200 -> 0x204"]);
+    assert_changes(Setup::new().v(1, 5).key(4), 0xE19E, &["PC 0x200 -> 0x202"]);
+}
+
+#[test]
+fn sknp() {
+    assert_changes(Setup::new().v(1, 5).key(4), 0xE1A1, &["PC 0x200 -> 0x204"]);
+    assert_changes(Setup::new().v(1, 5).key(5), 0xE1A1, &["PC 0x200 -> 0x202"]);
+}
+
+#[test]
+fn ld_vx_dt() {
+    assert_changes(
+        Setup::new().dt(0x3C),
+        0xF107,
+        &["V1 0x00 -> 0x3c", "PC 0x200 -> 0x202"],
+    );
+}
+
+#[test]
+fn ld_vx_k_waits_for_a_key() {
+    assert_changes(Setup::new(), 0xF10A, &[]);
+    assert_changes(
+        Setup::new().key(0xB),
+        0xF10A,
+        &["V1 0x00 -> 0x0b", "PC 0x200 -> 0x202"],
+    );
+}
+
+#[test]
+fn ld_dt_vx() {
+    assert_changes(
+        Setup::new().v(1, 0x3C),
+        0xF115,
+        &["DT 0x000 -> 0x03c", "PC 0x200 -> 0x202"],
+    );
+}
+
+#[test]
+fn ld_st_vx() {
+    assert_changes(
+        Setup::new().v(1, 0x3C),
+        0xF118,
+        &["ST 0x000 -> 0x03c", "PC 0x200 -> 0x202"],
+    );
+}
+
+#[test]
+fn add_i() {
+    assert_changes(
+        Setup::new().v(1, 0x10).i(0x300),
+        0xF11E,
+        &["I 0x300 -> 0x310", "PC 0x200 -> 0x202"],
+    );
+}
+
+#[test]
+fn ld_f() {
+    assert_changes(
+        Setup::new().v(1, 0xA),
+        0xF129,
+        &["I 0x000 -> 0x032", "PC 0x200 -> 0x202"],
+    );
+    // Only the low nibble selects the digit
+    assert_changes(
+        Setup::new().v(1, 0xFA),
+        0xF129,
+        &["I 0x000 -> 0x032", "PC 0x200 -> 0x202"],
+    );
+}
+
+#[test]
+fn ld_b() {
+    assert_changes(
+        Setup::new().v(1, 254).i(0x300),
+        0xF133,
+        &[
+            "PC 0x200 -> 0x202",
+            "[0x300] 0x00 -> 0x02",
+            "[0x301] 0x00 -> 0x05",
+            "[0x302] 0x00 -> 0x04",
+        ],
+    );
+}
+
+#[test]
+fn store_regs() {
+    assert_changes(
+        Setup::new().v(0, 1).v(1, 2).v(2, 3).i(0x300),
+        0xF155,
+        &[
+            "PC 0x200 -> 0x202",
+            "[0x300] 0x00 -> 0x01",
+            "[0x301] 0x00 -> 0x02",
+        ],
+    );
+}
+
+#[test]
+fn load_regs() {
+    assert_changes(
+        Setup::new().i(0x300).memory(0x300, &[1, 2, 3]),
+        0xF165,
+        &["V0 0x00 -> 0x01", "V1 0x00 -> 0x02", "PC 0x200 -> 0x202"],
+    );
+}
+
+#[test]
+fn errors_leave_the_machine_untouched() {
+    let cases = [
+        (Setup::new(), 0x00EE, Error::StackUnderflow),
+        (
+            Setup::new().calls(&[0x202; 16]),
+            0x2300,
+            Error::StackOverflow,
+        ),
+        (
+            Setup::new().i(0xFFE),
+            0xD123,
+            Error::AddressOutOfRange(0xFFE),
+        ),
+        (
+            Setup::new().i(0xFFE),
+            0xF133,
+            Error::AddressOutOfRange(0xFFE),
+        ),
+        (
+            Setup::new().i(0xFFE),
+            0xF255,
+            Error::AddressOutOfRange(0xFFE),
+        ),
+        (
+            Setup::new().i(0xFFE),
+            0xF265,
+            Error::AddressOutOfRange(0xFFE),
+        ),
+        (Setup::new().v(1, 0x10), 0xE19E, Error::InvalidKey(0x10)),
+        (Setup::new().v(1, 0x10), 0xE1A1, Error::InvalidKey(0x10)),
+    ];
+    for (mut setup, opcode, error) in cases {
+        let before = State::of(&mut setup.cpu);
+        assert_eq!(
+            setup.cpu.execute(opcode),
+            Err(error),
+            "opcode {:04X}",
+            opcode
+        );
+        assert_eq!(
+            State::of(&mut setup.cpu).diff(&before),
+            Vec::<String>::new()
+        );
+    }
+}
+
+#[test]
+fn stack_pointer_stays_within_the_stack() {
+    let mut setup = Setup::new();
+    assert_eq!(setup.cpu.set_sp(16), Ok(()));
+    assert_eq!(setup.cpu.set_sp(17), Err(Error::StackOverflow));
+    assert_eq!(setup.cpu.set_sp(200), Err(Error::StackOverflow));
+    assert_eq!(setup.cpu.sp(), 16);
+    assert_eq!(setup.cpu.execute(0x2300), Err(Error::StackOverflow));
+}
+
+#[test]
+fn fetch_past_the_end_of_memory_fails() {
+    let mut setup = Setup::new().pc(0xFFF);
+    assert_eq!(
+        setup.cpu.execute_cycle(),
+        Err(Error::AddressOutOfRange(0xFFF))
+    );
+}
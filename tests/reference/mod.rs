@@ -0,0 +1,208 @@
+//! A deliberately simple CHIP-8 interpreter to check `CPU` against
+//!
+//! Written from the spec rather than from `CPU`, so the two only agree if both are right.
+//! Instructions that can't run fail with the same `Error` as `CPU` gives.
+
+use chip_8::cpu::quirks::Quirks;
+use chip_8::cpu::Error;
+
+pub struct Machine {
+    pub memory: Vec<u8>,
+    pub v: [u8; 16],
+    pub i: u16,
+    pub dt: u16,
+    pub st: u16,
+    pub pc: u16,
+    pub stack: Vec<u16>,
+    pub keys: [bool; 16],
+    pub screen: [[bool; 64]; 32],
+    pub quirks: Quirks,
+}
+
+const FONT: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, 0x20, 0x60, 0x20, 0x20, 0x70, 0xF0, 0x10, 0xF0, 0x80, 0xF0, 0xF0,
+    0x10, 0xF0, 0x10, 0xF0, 0x90, 0x90, 0xF0, 0x10, 0x10, 0xF0, 0x80, 0xF0, 0x10, 0xF0, 0xF0, 0x80,
+    0xF0, 0x90, 0xF0, 0xF0, 0x10, 0x20, 0x40, 0x40, 0xF0, 0x90, 0xF0, 0x90, 0xF0, 0xF0, 0x90, 0xF0,
+    0x10, 0xF0, 0xF0, 0x90, 0xF0, 0x90, 0x90, 0xE0, 0x90, 0xE0, 0x90, 0xE0, 0xF0, 0x80, 0x80, 0x80,
+    0xF0, 0xE0, 0x90, 0x90, 0x90, 0xE0, 0xF0, 0x80, 0xF0, 0x80, 0xF0, 0xF0, 0x80, 0xF0, 0x80, 0x80,
+];
+
+impl Machine {
+    /// A machine as `CPU::reset` leaves it
+    pub fn new(quirks: Quirks) -> Machine {
+        let mut memory = vec![0; 4096];
+        memory[..80].copy_from_slice(&FONT);
+        Machine {
+            memory,
+            v: [0; 16],
+            i: 0,
+            dt: 0,
+            st: 0,
+            pc: 0x200,
+            stack: Vec::new(),
+            keys: [false; 16],
+            screen: [[false; 64]; 32],
+            quirks,
+        }
+    }
+
+    /// One 60Hz timer tick
+    pub fn tick(&mut self) {
+        self.dt = self.dt.saturating_sub(1);
+        self.st = self.st.saturating_sub(1);
+    }
+
+    /// Runs opcode as if it had just been fetched from PC
+    /// Nothing changes when it fails.
+    pub fn step(&mut self, opcode: u16) -> Result<(), Error> {
+        let x = (opcode >> 8 & 0xF) as usize;
+        let y = (opcode >> 4 & 0xF) as usize;
+        let n = (opcode & 0xF) as usize;
+        let kk = (opcode & 0xFF) as u8;
+        let nnn = opcode & 0xFFF;
+        let next = self.pc + 2;
+        let skip = |condition: bool| if condition { next + 2 } else { next };
+        let in_memory = |len: usize| self.i as usize + len <= 4096;
+
+        let mut pc = next;
+        match (opcode >> 12, x, y, n) {
+            (0x0, 0x0, 0xE, 0x0) => self.screen = [[false; 64]; 32],
+            (0x0, 0x0, 0xE, 0xE) => pc = self.stack.pop().ok_or(Error::StackUnderflow)?,
+            (0x1, ..) => pc = nnn,
+            (0x2, ..) => {
+                if self.stack.len() == 16 {
+                    return Err(Error::StackOverflow);
+                }
+                self.stack.push(next);
+                pc = nnn;
+            }
+            (0x3, ..) => pc = skip(self.v[x] == kk),
+            (0x4, ..) => pc = skip(self.v[x] != kk),
+            (0x5, _, _, 0x0) => pc = skip(self.v[x] == self.v[y]),
+            (0x6, ..) => self.v[x] = kk,
+            (0x7, ..) => self.v[x] = self.v[x].wrapping_add(kk),
+            (0x8, _, _, 0x0) => self.v[x] = self.v[y],
+            (0x8, _, _, 0x1..=0x3) => {
+                self.v[x] = match n {
+                    1 => self.v[x] | self.v[y],
+                    2 => self.v[x] & self.v[y],
+                    _ => self.v[x] ^ self.v[y],
+                };
+                if self.quirks.vf_reset {
+                    self.v[0xF] = 0;
+                }
+            }
+            (0x8, _, _, 0x4) => {
+                let sum = self.v[x] as u16 + self.v[y] as u16;
+                self.set_with_flag(x, sum as u8, sum > 0xFF);
+            }
+            (0x8, _, _, 0x5) => {
+                let (a, b) = (self.v[x], self.v[y]);
+                self.set_with_flag(x, a.wrapping_sub(b), a >= b);
+            }
+            (0x8, _, _, 0x7) => {
+                let (a, b) = (self.v[y], self.v[x]);
+                self.set_with_flag(x, a.wrapping_sub(b), a >= b);
+            }
+            (0x8, _, _, 0x6) => {
+                let value = self.v[if self.quirks.shift_vy { y } else { x }];
+                self.set_with_flag(x, value / 2, value % 2 == 1);
+            }
+            (0x8, _, _, 0xE) => {
+                let value = self.v[if self.quirks.shift_vy { y } else { x }];
+                self.set_with_flag(x, value.wrapping_mul(2), value >= 0x80);
+            }
+            (0x9, _, _, 0x0) => pc = skip(self.v[x] != self.v[y]),
+            (0xA, ..) => self.i = nnn,
+            (0xB, ..) => {
+                let register = if self.quirks.jump_vx { x } else { 0 };
+                pc = nnn + self.v[register] as u16;
+            }
+            // Only ever generated with kk = 0, so the random byte doesn't matter
+            (0xC, ..) => self.v[x] = 0,
+            (0xD, ..) => {
+                if !in_memory(n) {
+                    return Err(Error::AddressOutOfRange(self.i));
+                }
+                self.draw(x, y, n);
+            }
+            (0xE, ..) if kk == 0x9E || kk == 0xA1 => {
+                let key = self.v[x] as usize;
+                if key > 0xF {
+                    return Err(Error::InvalidKey(key as u8));
+                }
+                pc = skip(self.keys[key] == (kk == 0x9E));
+            }
+            (0xF, ..) => match kk {
+                0x07 => self.v[x] = self.dt as u8,
+                0x0A => match (0..16).find(|&key| self.keys[key]) {
+                    Some(key) => self.v[x] = key as u8,
+                    None => pc = self.pc,
+                },
+                0x15 => self.dt = self.v[x] as u16,
+                0x18 => self.st = self.v[x] as u16,
+                0x1E => self.i = self.i.wrapping_add(self.v[x] as u16),
+                0x29 => self.i = (self.v[x] % 16) as u16 * 5,
+                0x33 => {
+                    if !in_memory(3) {
+                        return Err(Error::AddressOutOfRange(self.i));
+                    }
+                    let value = self.v[x];
+                    let i = self.i as usize;
+                    self.memory[i] = value / 100;
+                    self.memory[i + 1] = value / 10 % 10;
+                    self.memory[i + 2] = value % 10;
+                }
+                0x55 | 0x65 => {
+                    if !in_memory(x + 1) {
+                        return Err(Error::AddressOutOfRange(self.i));
+                    }
+                    for register in 0..=x {
+                        let address = self.i as usize + register;
+                        if kk == 0x55 {
+                            self.memory[address] = self.v[register];
+                        } else {
+                            self.v[register] = self.memory[address];
+                        }
+                    }
+                    if self.quirks.load_store_increment_i {
+                        self.i += x as u16 + 1;
+                    }
+                }
+                _ => (),
+            },
+            // Everything else, including 0nnn, does nothing
+            _ => (),
+        }
+        self.pc = pc;
+        Ok(())
+    }
+
+    /// Stores value in Vx, then the flag in VF
+    fn set_with_flag(&mut self, x: usize, value: u8, flag: bool) {
+        self.v[x] = value;
+        self.v[0xF] = flag as u8;
+    }
+
+    fn draw(&mut self, x: usize, y: usize, n: usize) {
+        let (left, top) = (self.v[x] as usize % 64, self.v[y] as usize % 32);
+        let mut erased = false;
+        for row in 0..n {
+            let sprite = self.memory[self.i as usize + row];
+            for column in 0..8 {
+                if sprite & (0x80 >> column) == 0 {
+                    continue;
+                }
+                let (mut px, mut py) = (left + column, top + row);
+                if self.quirks.clip_sprites && (px >= 64 || py >= 32) {
+                    continue;
+                }
+                px %= 64;
+                py %= 32;
+                erased |= self.screen[py][px];
+                self.screen[py][px] = !self.screen[py][px];
+            }
+        }
+        self.v[0xF] = erased as u8;
+    }
+}
@@ -0,0 +1,72 @@
+// The JavaScript API as the page sees it, across the wasm-bindgen boundary
+// Run under Node with `wasm-pack test --node -- --features wasm`.
+#![cfg(all(target_arch = "wasm32", feature = "wasm"))]
+
+use chip_8::wasm::Emulator;
+use js_sys::Uint8Array;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+const ON: [u8; 4] = [0x00, 0xFF, 0xFF, 0xFF];
+const OFF: [u8; 4] = [0x33, 0x33, 0x33, 0xFF];
+
+#[rustfmt::skip]
+const ROM: [u8; 8] = [
+    0x60, 0x0A, // 200: LD V0, 0x0A
+    0xF0, 0x29, // 202: LD F, V0
+    0xD1, 0x15, // 204: DRW V1, V1, 5
+    0x12, 0x06, // 206: JP 0x206
+];
+
+/// The frame as web/index.html reads it, through a view of the module's memory
+fn framebuffer(emulator: &Emulator) -> Vec<u8> {
+    let memory = wasm_bindgen::memory().unchecked_into::<js_sys::WebAssembly::Memory>();
+    let length = Emulator::width() * Emulator::height() * 4;
+    Uint8Array::new_with_byte_offset_and_length(
+        &memory.buffer(),
+        emulator.framebuffer() as u32,
+        length as u32,
+    )
+    .to_vec()
+}
+
+#[wasm_bindgen_test]
+fn frames_show_up_in_the_framebuffer() {
+    let mut emulator = Emulator::new(1);
+    let blank = framebuffer(&emulator);
+    assert!(blank.chunks(4).all(|pixel| pixel == OFF));
+
+    emulator.load_rom(&ROM).unwrap();
+    emulator.run_frame().unwrap();
+    let pixels = framebuffer(&emulator);
+    // The top of the "A" glyph, 0xF0, at the top left
+    assert_eq!(pixels[..4], ON);
+    assert_eq!(pixels[3 * 4..4 * 4], ON);
+    assert_eq!(pixels[4 * 4..5 * 4], OFF);
+
+    // The pointer outlives a reload, and the view sees the new frame
+    emulator.load_rom(&[0x12, 0x00]).unwrap();
+    assert_eq!(framebuffer(&emulator), blank);
+}
+
+#[wasm_bindgen_test]
+fn controls_and_errors_cross_the_boundary() {
+    let mut emulator = Emulator::new(1);
+    emulator.load_rom(&ROM).unwrap();
+    assert_eq!(emulator.status(), None);
+    emulator.toggle_pause();
+    assert_eq!(emulator.status().as_deref(), Some("PAUSED"));
+    emulator.toggle_pause();
+    emulator.slower();
+    assert_eq!(emulator.status().as_deref(), Some("0.5X"));
+    emulator.toggle_fast_forward();
+    assert!(!emulator.throttled());
+
+    // Errors reach JavaScript as strings
+    let error = emulator.load_rom(&[0; 4096]).unwrap_err();
+    assert!(!error.is_empty());
+    emulator.load_rom(&[0x00, 0xEE]).unwrap(); // RET with nothing to return to
+    let error = emulator.run_frame().unwrap_err();
+    assert!(error.ends_with("at 0x200"), "{}", error);
+    assert_eq!(emulator.status().as_deref(), Some("PAUSED"));
+}